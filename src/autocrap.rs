@@ -1,2 +1,57 @@
+pub mod bench;
+pub mod bridge;
+pub mod capture;
+pub mod check;
+pub mod clock;
+pub mod color;
 pub mod config;
+pub mod confirm;
+pub mod control;
+pub mod determinism;
+pub mod device;
+pub mod diagnostics;
+pub mod diff;
+pub mod docs;
+pub mod effective;
+pub mod export;
+pub mod failsafe;
+pub mod fields;
+pub mod filter;
+pub mod groupaction;
+pub mod idle;
+pub mod import;
 pub mod interpreter;
+pub mod invert;
+pub mod jog;
+pub mod lfo;
+pub mod logfile;
+pub mod looper;
+#[cfg(feature = "midi")]
+pub mod mididevice;
+#[cfg(feature = "midi")]
+pub mod midiloop;
+pub mod miditransport;
+#[cfg(feature = "web")]
+pub mod obs;
+pub mod offset;
+pub mod page;
+pub mod plugin;
+pub mod profile;
+pub mod rawfeedback;
+pub mod reconnect;
+pub mod remote;
+pub mod replay;
+pub mod scene;
+#[cfg(feature = "osc")]
+pub mod schedule;
+pub mod sockopt;
+pub mod state;
+pub mod threads;
+pub mod throttle;
+pub mod touch;
+pub mod traffic;
+pub mod transfer;
+pub mod transport;
+pub mod unhandled;
+pub mod usb;
+pub mod vars;
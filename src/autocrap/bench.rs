@@ -0,0 +1,88 @@
+use std::time::{Duration, Instant};
+
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::{Config, Mapping},
+    interpreter::Interpreter
+};
+
+#[derive(Debug)]
+enum Event {
+    Ctrl(u8, u8),
+    Osc(OscMessage),
+    Midi(Vec<u8>),
+    Tick,
+}
+
+/// a small xorshift generator, so that runs are repeatable without pulling in a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// runs `count` synthetic events through a fresh interpreter and prints throughput and latency percentiles.
+pub fn run(config: &Config, count: usize) {
+    let mappings: Vec<Mapping> = config.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+    let ctrl_nums: Vec<u8> = mappings.iter().flat_map(|m| m.ctrl_in_nums()).collect();
+    let osc_addrs: Vec<String> = mappings.iter().map(|m| m.osc_addr()).collect();
+    let midi_specs: Vec<_> = mappings.iter().filter_map(|m| m.midi).collect();
+
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    let events: Vec<Event> = (0..count)
+        .map(|_| {
+            let val = rng.below(128) as u8;
+            match rng.below(4) {
+                0 if !ctrl_nums.is_empty() => Event::Ctrl(ctrl_nums[rng.below(ctrl_nums.len())], val),
+                1 if !osc_addrs.is_empty() => Event::Osc(OscMessage {
+                    addr: osc_addrs[rng.below(osc_addrs.len())].clone(),
+                    args: vec![OscType::Float(val as f32 / 127.0)]
+                }),
+                2 if !midi_specs.is_empty() => Event::Midi(midi_specs[rng.below(midi_specs.len())].message(val)),
+                _ => Event::Tick
+            }
+        })
+        .collect();
+
+    let mut interpreter = Interpreter::new(config);
+    interpreter.init();
+
+    let mut latencies = Vec::with_capacity(events.len());
+    let mut responses = 0usize;
+    let start = Instant::now();
+    for event in &events {
+        let t = Instant::now();
+        let handled = match event {
+            Event::Ctrl(num, val) => interpreter.handle_ctrl(*num, *val).is_some() as usize,
+            Event::Osc(msg) => interpreter.handle_osc(msg).is_some() as usize,
+            Event::Midi(msg) => interpreter.handle_midi(msg).is_some() as usize,
+            Event::Tick => interpreter.tick(t).len(),
+        };
+        latencies.push(t.elapsed());
+        responses += handled;
+    }
+    let elapsed = start.elapsed();
+
+    latencies.sort();
+    let percentile = |p: f64| latencies
+        .get(((latencies.len() as f64 * p) as usize).min(latencies.len().saturating_sub(1)))
+        .copied()
+        .unwrap_or(Duration::ZERO);
+
+    println!("{} mappings, {} events, {} responses", mappings.len(), events.len(), responses);
+    println!("{:.0} events/s", events.len() as f64 / elapsed.as_secs_f64());
+    println!(
+        "latency: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        percentile(0.5), percentile(0.9), percentile(0.99), percentile(1.0)
+    );
+}
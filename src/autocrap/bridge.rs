@@ -0,0 +1,53 @@
+use rosc::OscMessage;
+
+use super::{
+    config::{CtrlKind, Mapping, MidiSpec, RelativeMode, ValueTable},
+    interpreter::osc_7bit
+};
+
+/// translates between MIDI and OSC when both interfaces are in use: a message on one side sets the
+/// mapping's value, which is then sent out on the other side.
+#[derive(Debug)]
+pub struct Bridge {
+    targets: Vec<Target>,
+}
+
+#[derive(Debug)]
+struct Target {
+    name: String,
+    osc_addr: String,
+    midi: Option<MidiSpec>,
+    midi_table: Option<ValueTable>,
+    bipolar: bool,
+}
+
+impl Bridge {
+    pub fn new(mappings: &[Mapping]) -> Bridge {
+        Bridge {
+            targets: mappings.iter()
+                .map(|m| Target {
+                    name: m.name.clone(),
+                    osc_addr: m.osc_addr(),
+                    midi: m.midi,
+                    midi_table: m.midi_table.clone(),
+                    bipolar: matches!(m.ctrl_kind, CtrlKind::Relative { mode: RelativeMode::Bipolar, .. }),
+                })
+                .collect(),
+        }
+    }
+
+    /// the mapping an OSC message is for, and the value it carries.
+    pub fn osc_value(&self, msg: &OscMessage) -> Option<(String, u8)> {
+        let target = self.targets.iter().find(|t| t.osc_addr == msg.addr)?;
+        let val = osc_7bit(msg.args.first()?, target.bipolar)?;
+        Some((target.name.clone(), val))
+    }
+
+    /// the mapping a MIDI message is for, and the value it carries.
+    pub fn midi_value(&self, msg: &[u8]) -> Option<(String, u8)> {
+        self.targets.iter().find_map(|t| {
+            let val = t.midi?.value(msg)?;
+            Some((t.name.clone(), t.midi_table.as_ref().map_or(val, |table| table.input(val))))
+        })
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime}
+};
+
+use rosc::{encoder, OscBundle, OscMessage, OscPacket, OscTime};
+
+/// ticks per quarter note. at the default tempo of 120 bpm, one tick is a millisecond.
+const SMF_DIVISION: u16 = 500;
+const SMF_TEMPO: u32 = 500_000;
+const SMF_HEADER_LEN: u64 = 14;
+const SMF_END_OF_TRACK: [u8; 4] = [0x00, 0xff, 0x2f, 0x00];
+
+/// how often a MIDI file is written out while events keep coming. `flush` writes it out in between.
+const SMF_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// records outgoing messages to a file as they happen.
+///
+/// `.mid`/`.midi` files are standard MIDI files (format 0). anything else records OSC as a stream of
+/// timetagged bundles, each prefixed with its size as in OSC 1.0 stream framing.
+#[derive(Debug)]
+pub enum Capture {
+    Midi(SmfWriter),
+    Osc(BufWriter<File>),
+}
+
+impl Capture {
+    pub fn create(path: &Path) -> Result<Capture, Box<dyn Error>> {
+        let file = File::create(path)?;
+        let is_midi = path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("mid") || e.eq_ignore_ascii_case("midi"));
+
+        Ok(if is_midi {
+            Capture::Midi(SmfWriter::new(file)?)
+        } else {
+            Capture::Osc(BufWriter::new(file))
+        })
+    }
+
+    /// records MIDI sent in answer to an event received `at`.
+    pub fn midi(&mut self, data: &[u8], at: Instant) -> io::Result<()> {
+        match self {
+            Capture::Midi(smf) => smf.write_event(data, at),
+            Capture::Osc(_) => Ok(())
+        }
+    }
+
+    /// whether this records MIDI rather than OSC.
+    pub fn is_midi(&self) -> bool {
+        matches!(self, Capture::Midi(_))
+    }
+
+    /// writes out whatever is buffered, e.g. while nothing is being sent.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Capture::Midi(smf) => smf.sync(),
+            Capture::Osc(file) => file.flush()
+        }
+    }
+
+    /// records an OSC message sent in answer to an event received `at`.
+    pub fn osc(&mut self, msg: &OscMessage, at: Instant) -> Result<(), Box<dyn Error>> {
+        let Capture::Osc(file) = self else {
+            return Ok(());
+        };
+
+        let bundle = OscPacket::Bundle(OscBundle {
+            timetag: OscTime::try_from(system_time(at))?,
+            content: vec![OscPacket::Message(msg.clone())]
+        });
+        let buf = encoder::encode(&bundle)?;
+        file.write_all(&(buf.len() as u32).to_be_bytes())?;
+        file.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// writes a single track standard MIDI file. events are buffered and written out together, keeping the file
+/// valid every time, so that at most the last `SMF_SYNC_INTERVAL` is lost when autocrap is killed.
+#[derive(Debug)]
+pub struct SmfWriter {
+    file: File,
+    /// length of the events written to the file.
+    track_len: u32,
+    /// events not written yet.
+    pending: Vec<u8>,
+    last_sync: Instant,
+    start: Instant,
+    last_tick: u64,
+}
+
+impl SmfWriter {
+    fn new(mut file: File) -> io::Result<SmfWriter> {
+        file.write_all(b"MThd")?;
+        file.write_all(&6u32.to_be_bytes())?;
+        file.write_all(&0u16.to_be_bytes())?;
+        file.write_all(&1u16.to_be_bytes())?;
+        file.write_all(&SMF_DIVISION.to_be_bytes())?;
+        file.write_all(b"MTrk")?;
+        file.write_all(&0u32.to_be_bytes())?;
+
+        let mut smf = SmfWriter {
+            file,
+            track_len: 0,
+            pending: vec![],
+            last_sync: Instant::now(),
+            start: Instant::now(),
+            last_tick: 0,
+        };
+        let tempo = SMF_TEMPO.to_be_bytes();
+        smf.pending.extend([0x00, 0xff, 0x51, 0x03, tempo[1], tempo[2], tempo[3]]);
+        smf.sync()?;
+        Ok(smf)
+    }
+
+    fn write_event(&mut self, data: &[u8], at: Instant) -> io::Result<()> {
+        let Some(&status) = data.first() else {
+            return Ok(());
+        };
+
+        let event = match status {
+            // sysex is stored with its length instead of the leading F0
+            0xf0 => [&[0xf0][..], &vlq(data.len() as u32 - 1), &data[1..]].concat(),
+            // realtime and other system messages have no place in a file
+            0xf1..=0xff => return Ok(()),
+            _ => data.to_vec()
+        };
+
+        let ms_per_tick = SMF_TEMPO as f64 / 1000.0 / SMF_DIVISION as f64;
+        let tick = (at.saturating_duration_since(self.start).as_secs_f64() * 1000.0 / ms_per_tick) as u64;
+        let delta = (tick - self.last_tick.min(tick)) as u32;
+        self.last_tick = tick;
+
+        self.pending.extend(vlq(delta));
+        self.pending.extend(event);
+        if self.last_sync.elapsed() < SMF_SYNC_INTERVAL {
+            return Ok(());
+        }
+        self.sync()
+    }
+
+    /// writes the pending events over the end of track marker, then puts the marker back.
+    fn sync(&mut self) -> io::Result<()> {
+        self.last_sync = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        self.pending.extend(SMF_END_OF_TRACK);
+        self.file.seek(SeekFrom::Start(SMF_HEADER_LEN + 8 + self.track_len as u64))?;
+        self.file.write_all(&self.pending)?;
+        self.track_len += (self.pending.len() - SMF_END_OF_TRACK.len()) as u32;
+        self.pending.clear();
+
+        self.file.seek(SeekFrom::Start(SMF_HEADER_LEN + 4))?;
+        self.file.write_all(&(self.track_len + SMF_END_OF_TRACK.len() as u32).to_be_bytes())?;
+        self.file.flush()
+    }
+}
+
+impl Drop for SmfWriter {
+    fn drop(&mut self) {
+        let _ = self.sync();
+    }
+}
+
+/// the wall clock time of a monotonic timestamp.
+pub fn system_time(at: Instant) -> SystemTime {
+    let now = Instant::now();
+    match now.checked_duration_since(at) {
+        Some(ago) => SystemTime::now() - ago,
+        None => SystemTime::now() + at.duration_since(now)
+    }
+}
+
+/// a MIDI variable-length quantity.
+fn vlq(mut n: u32) -> Vec<u8> {
+    let mut bytes = vec![(n & 0x7f) as u8];
+    n >>= 7;
+    while n > 0 {
+        bytes.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
@@ -0,0 +1,104 @@
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, SystemTime}
+};
+
+use log::{info, warn};
+
+use super::{
+    config::Config,
+    device::Result
+};
+
+/// how often the file is looked at for changes while watching.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// checks the config at `path` with `check`, printing whether it is fine. with `watch`, checks it again every
+/// time it is saved, until stopped. with `notify`, errors also go to a desktop notification, and so does the
+/// config being fine again after them. returns whether the (last) check passed.
+pub fn run(path: &Path, watch: bool, notify: bool, check: fn(&Config, &serde_json::Value) -> Result<()>) -> bool {
+    let mut ok = report(path, check, notify, true);
+    if !watch {
+        return ok;
+    }
+
+    info!("watching {} for changes", path.display());
+    let mut last_modified = modified(path);
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let now = modified(path);
+        // editors which save by replacing the file can leave it missing for a moment
+        if now.is_none() || now == last_modified {
+            continue;
+        }
+        last_modified = now;
+        ok = report(path, check, notify, ok);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// checks once and prints the outcome. `was_ok` is how the previous check went.
+fn report(path: &Path, check: fn(&Config, &serde_json::Value) -> Result<()>, notify: bool, was_ok: bool) -> bool {
+    match check_file(path, check) {
+        Ok(()) => {
+            println!("{}: ok", path.display());
+            if notify && !was_ok {
+                send_notification(&format!("{} is fine again", file_name(path)));
+            }
+            true
+        },
+        Err(e) => {
+            println!("{}: {}", path.display(), e);
+            if notify {
+                send_notification(&format!("{}: {}", file_name(path), e));
+            }
+            false
+        }
+    }
+}
+
+fn check_file(path: &Path, check: fn(&Config, &serde_json::Value) -> Result<()>) -> Result<()> {
+    let text = fs::read_to_string(path)?;
+    // straight from the text rather than the value, so that errors say where they are
+    let config: Config = serde_json::from_str(&text)?;
+    let value: serde_json::Value = serde_json::from_str(&text)?;
+    check(&config, &value)?;
+    // only errors with strict on, but worth knowing about either way
+    if !config.strict {
+        for problem in config.mapping_problems() {
+            warn!("{}", problem);
+        }
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+/// shows a desktop notification, with `notify-send` on Linux and `osascript` on macOS.
+fn send_notification(message: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title \"autocrap\"", message);
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.args(["autocrap", message]);
+        command
+    } else {
+        warn!("desktop notifications are not supported on this platform");
+        return;
+    };
+
+    if let Err(e) = command.status() {
+        warn!("could not show a desktop notification: {}", e);
+    }
+}
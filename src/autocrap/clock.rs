@@ -0,0 +1,168 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::ClockConfig,
+    determinism,
+    interpreter::{osc_number, CtrlResponse, MidiResponse, OscResponse, Response}
+};
+
+/// MIDI clock resolution, in pulses per quarter note.
+pub const PPQN: u32 = 24;
+
+const MIDI_CLOCK: u8 = 0xf8;
+
+const MIN_BPM: f32 = 20.0;
+const MAX_BPM: f32 = 300.0;
+
+/// taps further apart than this start a new tap sequence.
+const MAX_TAP_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_TAPS: usize = 4;
+
+/// if the ticker falls further behind than this many pulses, skip ahead instead of catching up.
+const MAX_PULSE_BACKLOG: u32 = PPQN;
+
+const OSC_TAP_ADDR: &str = "/autocrap/tap";
+const OSC_BPM_ADDR: &str = "/autocrap/bpm";
+
+#[derive(Debug)]
+pub struct Clock {
+    bpm: f32,
+    send_midi: bool,
+    ctrl_in_num: Option<u8>,
+    ctrl_out_num: Option<u8>,
+    taps: Vec<Instant>,
+    pulse: u64,
+    last_pulse: Instant,
+    led_on: bool,
+}
+
+impl Clock {
+    pub fn new(config: &ClockConfig) -> Clock {
+        Clock {
+            bpm: config.bpm.clamp(MIN_BPM, MAX_BPM),
+            send_midi: config.send_midi,
+            ctrl_in_num: config.ctrl_in_num,
+            ctrl_out_num: config.ctrl_out_num,
+            taps: vec![],
+            pulse: 0,
+            last_pulse: determinism::now(),
+            led_on: false,
+        }
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) -> Response {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+        info!("tempo: {:.2} bpm", self.bpm);
+        self.bpm_response()
+    }
+
+    pub fn pulse_interval(&self) -> Duration {
+        Duration::from_secs_f32(60.0 / (self.bpm * PPQN as f32))
+    }
+
+    /// position in quarter notes since the clock started.
+    pub fn beats(&self, now: Instant) -> f64 {
+        let since_pulse = now.saturating_duration_since(self.last_pulse).as_secs_f64()
+            / self.pulse_interval().as_secs_f64();
+        (self.pulse as f64 + since_pulse.min(1.0)) / PPQN as f64
+    }
+
+    pub fn tap(&mut self, now: Instant) -> Response {
+        if let Some(&last) = self.taps.last() {
+            if now.saturating_duration_since(last) > MAX_TAP_INTERVAL {
+                self.taps.clear();
+            }
+        }
+
+        self.taps.push(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+
+        // a tap marks the start of a beat
+        self.pulse = 0;
+        self.last_pulse = now;
+
+        if self.taps.len() < 2 {
+            return Response::new();
+        }
+
+        let span = self.taps[self.taps.len() - 1].duration_since(self.taps[0]);
+        let interval = span.as_secs_f32() / (self.taps.len() - 1) as f32;
+        self.set_bpm(60.0 / interval)
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Vec<Response> {
+        let mut responses = vec![];
+        let interval = self.pulse_interval();
+
+        let mut pulses = 0;
+        while now.saturating_duration_since(self.last_pulse) >= interval {
+            self.last_pulse += interval;
+            self.pulse += 1;
+            pulses += 1;
+
+            if pulses > MAX_PULSE_BACKLOG {
+                self.last_pulse = now;
+                break;
+            }
+
+            if self.send_midi {
+                responses.push(MidiResponse {
+                    data: vec![MIDI_CLOCK],
+                    page: None
+                }.into());
+            }
+        }
+
+        if let Some(num) = self.ctrl_out_num {
+            // lit for the first half of each beat
+            let led_on = self.pulse % (PPQN as u64) < (PPQN as u64) / 2;
+            if led_on != self.led_on {
+                self.led_on = led_on;
+                responses.push(CtrlResponse {
+                    data: vec![num, if led_on { 0x7f } else { 0x00 }],
+                    color: None,
+                    raw: false
+                }.into());
+            }
+        }
+
+        responses
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        if self.ctrl_in_num != Some(num) {
+            return None;
+        }
+
+        if val == 0x00 {
+            return Some(Response::new());
+        }
+
+        Some(self.tap(determinism::now()))
+    }
+
+    pub fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        match msg.addr.as_str() {
+            OSC_TAP_ADDR => Some(self.tap(determinism::now())),
+            OSC_BPM_ADDR => {
+                let Some(bpm) = msg.args.first().and_then(osc_number) else {
+                    return Some(self.bpm_response());
+                };
+                Some(self.set_bpm(bpm))
+            },
+            _ => None
+        }
+    }
+
+    fn bpm_response(&self) -> Response {
+        OscResponse {
+            addr: OSC_BPM_ADDR.to_string(),
+            args: vec![OscType::Float(self.bpm)]
+        }.into()
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Instant;
+
+use rosc::OscMessage;
+
+use super::{
+    config::{ColorConfig, Mapping},
+    interpreter::{CtrlLogic, CtrlResponse, Response},
+    lfo::Lfo
+};
+
+/// wraps a mapping with colored LEDs, giving the feedback for its LED a color on the way out.
+#[derive(Debug)]
+pub struct ColorLogic {
+    inner: Box<dyn CtrlLogic>,
+    ctrl_out_num: u8,
+    color: ColorConfig,
+}
+
+impl ColorLogic {
+    /// wraps `inner`, or returns it as is if the mapping has no LED.
+    pub fn wrap(inner: Box<dyn CtrlLogic>, mapping: &Mapping, color: ColorConfig) -> Box<dyn CtrlLogic> {
+        let Some(ctrl_out_num) = mapping.ctrl_out_num else {
+            return inner;
+        };
+
+        Box::new(ColorLogic { inner, ctrl_out_num, color })
+    }
+
+    /// `on` dimmed by the value, or `off` for 0.
+    fn color_for(&self, val: u8) -> [u8; 3] {
+        if val == 0x00 {
+            return self.color.off;
+        }
+        self.color.on.map(|c| (c as u16 * val.min(0x7f) as u16 / 0x7f) as u8)
+    }
+
+    fn paint(&self, mut leds: Vec<CtrlResponse>) -> Vec<CtrlResponse> {
+        for led in &mut leds {
+            if let [num, val] = led.data[..] {
+                if num == self.ctrl_out_num {
+                    led.color = Some(self.color_for(val));
+                }
+            }
+        }
+        leds
+    }
+
+    fn paint_response(&self, response: Option<Response>) -> Option<Response> {
+        let mut response = response?;
+        response.ctrl = self.paint(response.ctrl);
+        Some(response)
+    }
+}
+
+impl CtrlLogic for ColorLogic {
+    fn from_mapping(_mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        // only made by wrapping another logic
+        None
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let response = self.inner.handle_ctrl(num, val);
+        self.paint_response(response)
+    }
+
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        let response = self.inner.handle_osc(msg);
+        self.paint_response(response)
+    }
+
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        let response = self.inner.handle_midi(msg);
+        self.paint_response(response)
+    }
+
+    fn init(&mut self) -> Vec<Response> {
+        self.inner.init().into_iter().filter_map(|r| self.paint_response(Some(r))).collect()
+    }
+
+    fn tick(&mut self, now: Instant, lfos: &[Lfo]) -> Option<Response> {
+        let response = self.inner.tick(now, lfos);
+        self.paint_response(response)
+    }
+
+    fn ticks(&self) -> bool {
+        self.inner.ticks()
+    }
+
+    fn apply(&mut self, name: &str, val: u8) -> Option<Response> {
+        let response = self.inner.apply(name, val);
+        self.paint_response(response)
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        let leds = self.inner.leds();
+        self.paint(leds)
+    }
+
+    fn led(&mut self, name: &str, val: u8) -> Option<Vec<CtrlResponse>> {
+        let leds = self.inner.led(name, val)?;
+        Some(self.paint(leds))
+    }
+
+    fn is_in_state(&self, name: &str, is: u8) -> Option<bool> {
+        self.inner.is_in_state(name, is)
+    }
+
+    fn value(&self, name: &str) -> Option<u8> {
+        self.inner.value(name)
+    }
+
+    #[cfg(feature = "osc")]
+    fn osc_value(&self, name: &str) -> Option<f32> {
+        self.inner.osc_value(name)
+    }
+}
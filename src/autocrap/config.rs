@@ -1,4 +1,4 @@
-use std::{net::{SocketAddrV4}};
+use std::{collections::BTreeMap, net::{IpAddr, SocketAddr, SocketAddrV4}};
 
 use serde::{Serialize, Deserialize};
 
@@ -12,43 +12,215 @@ pub enum OnOffMode {
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum RelativeMode {
     Raw,
-    Accumulate
+    Accumulate,
+    Bipolar
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransportAction {
+    Play,
+    Stop,
+    Record,
+    Loop
+}
+
+impl TransportAction {
+    pub const ALL: [TransportAction; 4] = [
+        TransportAction::Play,
+        TransportAction::Stop,
+        TransportAction::Record,
+        TransportAction::Loop
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransportAction::Play => "play",
+            TransportAction::Stop => "stop",
+            TransportAction::Record => "record",
+            TransportAction::Loop => "loop",
+        }
+    }
+}
+
+/// how jog wheel deltas are encoded in CC values.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum JogEncoding {
+    /// +1 is 0x01, -1 is 0x7f.
+    #[default]
+    TwosComplement,
+    /// +1 is 0x41, -1 is 0x3f.
+    Offset,
+    /// +1 is 0x01, -1 is 0x41.
+    SignMagnitude
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CtrlKind {
     OnOff { mode: OnOffMode },
     EightBit,
-    Relative { mode: RelativeMode },
+    Relative {
+        mode: RelativeMode,
+        #[serde(default)]
+        detent: u8,
+        #[serde(default)]
+        wrap: bool,
+        /// turn the value last reported by the host, rather than the internal one.
+        #[serde(default)]
+        follow_host: bool
+    },
+    Transport { action: TransportAction },
+    Jog {
+        #[serde(default)]
+        encoding: JogEncoding,
+        touch_ctrl_in_num: Option<u8>,
+        scratch_midi: Option<MidiSpec>
+    },
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MidiKind {
     Cc,
+    Note,
     // CoarseFine,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-pub enum Mode {
-    Raw,
-    Accumulate,
-}
-
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MidiSpec {
     pub channel: u8,
     pub kind: MidiKind,
     pub num: u8,
+    /// accept input on any channel. output still goes to `channel`.
+    #[serde(default)]
+    pub omni: bool,
 }
 
 impl MidiSpec {
-    pub fn index(&self, i: u8) -> MidiSpec {
-        MidiSpec {
-            channel: self.channel,
-            kind: self.kind,
-            num: self.num + i
+    /// the spec `i` numbers further on, or `None` if that is past 127.
+    pub fn index(&self, i: u8) -> Option<MidiSpec> {
+        Some(MidiSpec {
+            num: self.num.checked_add(i).filter(|&num| num <= 0x7f)?,
+            ..*self
+        })
+    }
+
+    /// builds the message sending `val` for this spec. for notes, 0 is sent as a note off.
+    pub fn message(&self, val: u8) -> Vec<u8> {
+        match self.kind {
+            MidiKind::Cc => vec![0b10110000 | self.channel, self.num, val],
+            MidiKind::Note if val == 0 => vec![0b10000000 | self.channel, self.num, 0x00],
+            MidiKind::Note => vec![0b10010000 | self.channel, self.num, val],
         }
     }
+
+    /// the spec `msg` would match, if it is a CC, note on or note off.
+    #[cfg(feature = "midi")]
+    pub fn of_message(msg: &[u8]) -> Option<MidiSpec> {
+        let [status, num, _] = *msg else {
+            return None;
+        };
+        let kind = match status & 0xf0 {
+            0b10110000 => MidiKind::Cc,
+            0b10010000 | 0b10000000 => MidiKind::Note,
+            _ => return None
+        };
+        Some(MidiSpec { channel: status & 0x0f, kind, num, omni: false })
+    }
+
+    /// extracts the value from `msg` if it matches this spec. note offs have the value 0.
+    pub fn value(&self, msg: &[u8]) -> Option<u8> {
+        let [status, num, val] = *msg else {
+            return None;
+        };
+
+        if num != self.num || (status & 0x0f != self.channel && !self.omni) {
+            return None;
+        }
+
+        match (self.kind, status & 0xf0) {
+            (MidiKind::Cc, 0b10110000) => Some(val),
+            (MidiKind::Note, 0b10010000) => Some(val),
+            (MidiKind::Note, 0b10000000) => Some(0x00),
+            _ => None
+        }
+    }
+}
+
+/// what to do when several mappings claim the same control, OSC address or MIDI message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// the mapping with the highest priority, or the first one listed, handles the event.
+    #[default]
+    First,
+    /// every matching mapping handles the event.
+    All,
+    /// refuse to load configs with conflicts between mappings of equal priority.
+    Reject
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum LimitNotify {
+    Osc,
+    Blink
+}
+
+/// where a control's LED gets its state from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeedbackSource {
+    /// the control lights its own LED, and follows feedback from the host.
+    #[default]
+    Internal,
+    /// only feedback from the host lights the LED.
+    External,
+    /// lit while the host's MIDI transport is playing.
+    MidiPlaying,
+    /// lit while the host's MIDI transport is stopped.
+    MidiStopped,
+    /// lit for the first half of every beat of the host's MIDI clock, while playing.
+    MidiBeat
+}
+
+impl FeedbackSource {
+    pub fn is_midi_transport(self) -> bool {
+        matches!(self, FeedbackSource::MidiPlaying | FeedbackSource::MidiStopped | FeedbackSource::MidiBeat)
+    }
+}
+
+/// whether a mapping which is on every page has one state, or one for each page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// one state shared by all pages, like a master volume.
+    #[default]
+    Global,
+    /// a state of its own on each page.
+    Page
+}
+
+/// a step in working out the value a `Relative` mapping sends, from its state.
+/// only these steps can be reordered, the input and output handling around the logic is fixed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stage {
+    /// snapping to the center, in `Bipolar` mode.
+    Detent,
+    /// adding the `lfo`'s modulation.
+    Lfo,
+    /// rounding to one of `steps` values.
+    Steps,
+    /// keeping between `min` and `max`.
+    Limits,
+}
+
+fn default_stages() -> Vec<Stage> {
+    vec![Stage::Detent, Stage::Lfo, Stage::Steps, Stage::Limits]
+}
+
+/// what a control does in OBS, when using the OBS interface.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ObsAction {
+    Scene(String),
+    Stream,
+    Record,
+    InputMute(String),
+    InputVolume(String)
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -57,25 +229,363 @@ pub struct Mapping {
     pub ctrl_in_sequence: Option<Vec<u8>>,
     pub ctrl_in_num: Option<u8>,
     pub ctrl_out_num: Option<u8>,
+    pub ctrl_mode_num: Option<u8>,
+    /// the motor of a motorized fader, moved to follow the host.
+    pub ctrl_motor_num: Option<u8>,
     pub ctrl_kind: CtrlKind,
     pub midi: Option<MidiSpec>,
+    pub lfo: Option<String>,
+    pub steps: Option<u8>,
+    /// the order in which detent, lfo, steps and limits are applied. stages left out are skipped.
+    #[serde(default = "default_stages")]
+    pub stages: Vec<Stage>,
+    pub min: Option<u8>,
+    pub max: Option<u8>,
+    pub limit_notify: Option<LimitNotify>,
+    pub obs: Option<ObsAction>,
+    /// the value (0-127) the control starts out with, instead of off.
+    pub initial: Option<u8>,
+    #[serde(default)]
+    pub feedback_source: FeedbackSource,
+    /// the value (0-127) to jump to when the link to the host is lost.
+    pub failsafe: Option<u8>,
+    /// mappings with a higher priority get to handle events first.
+    #[serde(default)]
+    pub priority: i32,
+    /// the page on which the mapping is active. if not given, it is active on all pages.
+    pub page: Option<u8>,
+    /// whether the mapping keeps its state across pages, when it has no `page`.
+    #[serde(default)]
+    pub scope: Scope,
+    /// extra outputs driven by the same control.
+    #[serde(default)]
+    pub links: Vec<Link>,
+    /// only active while another mapping is in a given state.
+    pub when: Option<Condition>,
+    /// replaces the values sent over MIDI.
+    pub midi_table: Option<ValueTable>,
+    /// asks for a second press before acting.
+    pub confirm: Option<ConfirmConfig>,
+    /// LED colors, for devices with RGB LEDs.
+    pub color: Option<ColorConfig>,
+    /// the LED is lit by 0 and turned off by 127, instead of the other way around.
+    #[serde(default)]
+    pub invert_led: bool,
+    /// bytes for the device, for feedback which none of the ctrl kinds knows how to give.
+    #[serde(default)]
+    pub raw_feedback: Vec<RawFeedback>,
+    /// send the release this long after the press, instead of when the button is let go.
+    pub pulse_ms: Option<u64>,
+    /// whether this mapping was made from another mapping's link.
+    #[serde(skip)]
+    pub linked: bool,
+}
+
+/// a state of another mapping, by name. for `OnOff` mappings, any value other than 0 means on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Condition {
+    pub control: String,
+    pub is: u8,
+}
+
+/// how a mapping which is hard to undo is confirmed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConfirmConfig {
+    /// a button which confirms, instead of pressing the same one again.
+    pub ctrl_in_num: Option<u8>,
+    /// how long to wait for confirmation.
+    #[serde(default = "default_confirm_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_confirm_timeout_ms() -> u64 {
+    2000
+}
+
+/// the colors of a mapping's LED, as red, green and blue (0-255).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ColorConfig {
+    /// the color when lit, dimmed by lower values.
+    pub on: [u8; 3],
+    /// the color when the value is 0.
+    #[serde(default)]
+    pub off: [u8; 3],
+}
+
+/// a message sent to the device as it is, made from bytes and placeholders such as `"{value}"`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RawFeedback {
+    /// sent when the control is moved on the device.
+    OnCtrl(Vec<TemplateByte>),
+    /// sent when the mapping's value changes, and whenever its LEDs are redrawn.
+    OnValue(Vec<TemplateByte>),
+}
+
+impl RawFeedback {
+    pub fn template(&self) -> &[TemplateByte] {
+        match self {
+            RawFeedback::OnCtrl(template) | RawFeedback::OnValue(template) => template
+        }
+    }
+
+    /// fills in placeholders other than `{value}`, such as `{i}` in a range.
+    pub fn substitute(&self, vars: &[(&str, u8)]) -> RawFeedback {
+        let template = self.template().iter()
+            .map(|b| match b {
+                TemplateByte::Placeholder(p) => TemplateByte::Placeholder(substitute(p, vars)),
+                b => b.clone()
+            })
+            .collect();
+        match self {
+            RawFeedback::OnCtrl(_) => RawFeedback::OnCtrl(template),
+            RawFeedback::OnValue(_) => RawFeedback::OnValue(template),
+        }
+    }
+
+    /// the message for a value, or `None` if a placeholder doesn't make a byte.
+    pub fn render(&self, val: u8) -> Option<Vec<u8>> {
+        self.template().iter().map(|b| b.render(val)).collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateByte {
+    Byte(u8),
+    /// `{value}`, optionally with an offset as in `{value+64}`.
+    Placeholder(String),
+}
+
+impl TemplateByte {
+    pub fn render(&self, val: u8) -> Option<u8> {
+        match self {
+            TemplateByte::Byte(b) => Some(*b),
+            TemplateByte::Placeholder(p) => substitute(p, &[("value", val)]).parse().ok()
+        }
+    }
+}
+
+/// values to send instead of a mapping's value (0-127). each entry applies from its value up to the next entry's.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ValueTable(pub BTreeMap<u8, u8>);
+
+impl ValueTable {
+    /// what to send for a value. values below the first entry get its output.
+    pub fn output(&self, val: u8) -> u8 {
+        self.0.range(..=val).next_back()
+            .or_else(|| self.0.iter().next())
+            .map_or(val, |(_, &out)| out)
+    }
+
+    /// the value for something received back from the host: the start of the entry whose output is closest.
+    pub fn input(&self, out: u8) -> u8 {
+        self.0.iter()
+            .min_by_key(|(_, &o)| o.abs_diff(out))
+            .map_or(out, |(&val, _)| val)
+    }
+}
+
+/// an extra output of a mapping, sending its value under another name and/or MIDI message.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Link {
+    pub name: Option<String>,
+    pub midi: Option<MidiSpec>,
 }
 
 impl Mapping {
-    pub fn index(&self, i: u8) -> Mapping {
+    pub fn new(name: String, ctrl_kind: CtrlKind) -> Mapping {
         Mapping {
-            name: self.name.replace("{i}", &i.to_string()),
-            ctrl_in_sequence: self.ctrl_in_sequence.as_ref().map(|s| s.iter().map(|n| n+i).collect()),
-            ctrl_in_num: self.ctrl_in_num.map(|n| n+i),
-            ctrl_out_num: self.ctrl_out_num.map(|n| n+i),
-            ctrl_kind: self.ctrl_kind,
-            midi: self.midi.map(|m| m.index(i)),
+            name,
+            ctrl_in_sequence: None,
+            ctrl_in_num: None,
+            ctrl_out_num: None,
+            ctrl_mode_num: None,
+            ctrl_motor_num: None,
+            ctrl_kind,
+            midi: None,
+            lfo: None,
+            steps: None,
+            stages: default_stages(),
+            min: None,
+            max: None,
+            limit_notify: None,
+            obs: None,
+            initial: None,
+            feedback_source: FeedbackSource::Internal,
+            failsafe: None,
+            priority: 0,
+            page: None,
+            scope: Scope::Global,
+            links: vec![],
+            when: None,
+            midi_table: None,
+            confirm: None,
+            color: None,
+            invert_led: false,
+            raw_feedback: vec![],
+            pulse_ms: None,
+            linked: false,
+        }
+    }
+
+    /// the mapping for index `i` of a range, or `None` if a number would go out of range.
+    pub fn index(&self, i: u8) -> Option<Mapping> {
+        let mapping = self.offset(i, i)?;
+        Some(Mapping {
+            name: substitute(&self.name, &[("i", i)]),
+            links: mapping.links.iter()
+                .map(|link| Link {
+                    name: link.name.as_ref().map(|name| substitute(name, &[("i", i)])),
+                    ..link.clone()
+                })
+                .collect(),
+            raw_feedback: mapping.raw_feedback.iter().map(|raw| raw.substitute(&[("i", i)])).collect(),
+            ..mapping
+        })
+    }
+
+    /// the mapping, followed by a mapping for each of its links. these only send, leaving the LEDs
+    /// to the original.
+    pub fn with_links(self) -> Vec<Mapping> {
+        let links: Vec<Mapping> = self.links.iter().enumerate()
+            .map(|(i, link)| Mapping {
+                name: link.name.clone().unwrap_or_else(|| format!("{}/{}", self.name, i + 1)),
+                ctrl_out_num: None,
+                ctrl_mode_num: None,
+                midi: link.midi,
+                links: vec![],
+                raw_feedback: vec![],
+                linked: true,
+                ..self.clone()
+            })
+            .collect();
+        [vec![self], links].concat()
+    }
+
+    /// a copy of the mapping for each of `count` pages if it is page-local, with `{page}` in its name
+    /// replaced by the page number.
+    pub fn per_page(self, count: u8) -> Vec<Mapping> {
+        if self.scope != Scope::Page || self.page.is_some() {
+            return vec![self];
         }
+        (1..=count.max(1))
+            .map(|page| Mapping {
+                name: substitute(&self.name, &[("page", page)]),
+                page: Some(page),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// adds `ctrl_offset` to the control numbers and `midi_offset` to the MIDI number, or `None` if a number
+    /// would go out of range.
+    fn offset(&self, ctrl_offset: u8, midi_offset: u8) -> Option<Mapping> {
+        // `None` if out of range, `Some(None)` if there is no number to begin with
+        let add = |num: Option<u8>| match num {
+            Some(m) => m.checked_add(ctrl_offset).map(Some),
+            None => Some(None)
+        };
+        let midi = |spec: Option<MidiSpec>| match spec {
+            Some(m) => m.index(midi_offset).map(Some),
+            None => Some(None)
+        };
+        let ctrl_in_sequence = match self.ctrl_in_sequence {
+            Some(ref s) => Some(s.iter().map(|m| m.checked_add(ctrl_offset)).collect::<Option<_>>()?),
+            None => None
+        };
+        Some(Mapping {
+            ctrl_in_sequence,
+            ctrl_in_num: add(self.ctrl_in_num)?,
+            ctrl_out_num: add(self.ctrl_out_num)?,
+            ctrl_mode_num: add(self.ctrl_mode_num)?,
+            ctrl_motor_num: add(self.ctrl_motor_num)?,
+            midi: midi(self.midi)?,
+            links: self.links.iter()
+                .map(|link| Some(Link {
+                    midi: midi(link.midi)?,
+                    ..link.clone()
+                }))
+                .collect::<Option<_>>()?,
+            ..self.clone()
+        })
     }
 
     pub fn osc_addr(&self) -> String {
         format!("/{}", self.name)
     }
+
+    /// every control number this mapping reads from.
+    pub fn ctrl_in_nums(&self) -> Vec<u8> {
+        let mut nums: Vec<u8> = self.ctrl_in_num.into_iter()
+            .chain(self.ctrl_in_sequence.iter().flatten().copied())
+            .collect();
+        if let CtrlKind::Jog { touch_ctrl_in_num: Some(num), .. } = self.ctrl_kind {
+            nums.push(num);
+        }
+        nums
+    }
+
+    /// describes what this mapping has in common with `other`, if anything.
+    fn conflict_with(&self, other: &Mapping) -> Option<String> {
+        let other_nums = other.ctrl_in_nums();
+        let same_page = self.page.is_none() || other.page.is_none() || self.page == other.page;
+        // never active at the same time
+        let exclusive = matches!((&self.when, &other.when), (Some(a), Some(b)) if a.control == b.control && a.is != b.is);
+        if let Some(num) = self.ctrl_in_nums().into_iter().find(|n| same_page && !exclusive && other_nums.contains(n)) {
+            return Some(format!("control {}", num));
+        }
+        if self.name == other.name {
+            return Some(format!("OSC address {}", self.osc_addr()));
+        }
+        match (self.midi, other.midi) {
+            (Some(a), Some(b)) if a == b => Some(format!("MIDI {:?} {} on channel {}", a.kind, a.num, a.channel)),
+            _ => None
+        }
+    }
+}
+
+/// replaces placeholders such as `{i}` in a name with the values of the given variables.
+/// an offset and/or zero padding may be given, as in `{i+1}` or `{i+1:02}`. unknown placeholders are left alone.
+pub fn substitute(name: &str, vars: &[(&str, u8)]) -> String {
+    let mut result = String::new();
+    let mut rest = name;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        match vars.iter().find_map(|&(var, val)| format_var(&rest[1..end], var, val)) {
+            Some(formatted) => result.push_str(&formatted),
+            None => result.push_str(&rest[..=end])
+        }
+        rest = &rest[end+1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn format_var(placeholder: &str, var: &str, val: u8) -> Option<String> {
+    let expr = placeholder.strip_prefix(var)?;
+    let (offset, width) = match expr.split_once(':') {
+        Some((offset, width)) => (offset, width.parse::<usize>().ok()?),
+        None => (expr, 0)
+    };
+    let offset: i32 = match offset {
+        "" => 0,
+        _ => offset.strip_prefix('+').unwrap_or(offset).parse().ok()?
+    };
+    Some(format!("{:0width$}", val as i32 + offset, width = width))
+}
+
+/// a block of play, stop, record and loop buttons on consecutive control numbers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransportBlock {
+    pub ctrl_in_num: u8,
+    pub ctrl_out_num: Option<u8>,
+    pub midi: Option<MidiSpec>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -83,30 +593,230 @@ pub enum AbstractMapping {
     Single(Mapping),
     Range {
         count: u8,
-        mapping: Mapping
-    }
+        mapping: Mapping,
+        /// explicit control/note numbers per index, for irregular layouts. these replace `n+i`.
+        #[serde(default)]
+        ctrl_in_nums: Vec<u8>,
+        #[serde(default)]
+        ctrl_out_nums: Vec<u8>,
+        #[serde(default)]
+        ctrl_mode_nums: Vec<u8>,
+        #[serde(default)]
+        midi_nums: Vec<u8>,
+        /// names substituted for `{label}`, per index.
+        #[serde(default)]
+        labels: Vec<String>
+    },
+    Grid {
+        rows: u8,
+        cols: u8,
+        mapping: Mapping,
+        /// difference between the control numbers of adjacent rows. defaults to `cols`.
+        row_stride: Option<u8>,
+        /// difference between the control numbers of adjacent columns. defaults to 1.
+        col_stride: Option<u8>,
+        /// strides for the MIDI number. default to the control number strides.
+        midi_row_stride: Option<u8>,
+        midi_col_stride: Option<u8>
+    },
+    Transport(TransportBlock)
 }
 
 impl AbstractMapping {
+    /// the mappings, which are left out if they can't be expanded. configs are checked with `expand` first.
     pub fn expand_iter(&self) -> impl Iterator<Item = Mapping> {
+        self.expand().unwrap_or_default().into_iter()
+    }
+
+    /// the mappings, or an error if an index or control/MIDI number would not fit.
+    pub fn expand(&self) -> Result<Vec<Mapping>, String> {
         let mut mappings = vec![];
         match self {
-            AbstractMapping::Single(mapping) => mappings.push(mapping.index(0)),
-            AbstractMapping::Range { count, mapping } => {
+            AbstractMapping::Single(mapping) => mappings.push(mapping.index(0)
+                .ok_or_else(|| format!("{}: MIDI number out of range", mapping.name))?),
+            AbstractMapping::Range { count, mapping, ctrl_in_nums, ctrl_out_nums, ctrl_mode_nums, midi_nums, labels } => {
                 for i in 0..*count {
-                    mappings.push(mapping.index(i));
+                    let at = |nums: &[u8]| nums.get(i as usize).copied();
+                    // numbers listed explicitly don't get the index added, so they can't go out of range
+                    let mut template = mapping.clone();
+                    if at(ctrl_in_nums).is_some() {
+                        template.ctrl_in_num = None;
+                    }
+                    if at(ctrl_out_nums).is_some() {
+                        template.ctrl_out_num = None;
+                    }
+                    if at(ctrl_mode_nums).is_some() {
+                        template.ctrl_mode_num = None;
+                    }
+                    if let (Some(_), Some(midi)) = (at(midi_nums), template.midi.as_mut()) {
+                        midi.num = 0;
+                    }
+                    let mut indexed = template.index(i)
+                        .ok_or_else(|| format!("{}: index {} puts a control or MIDI number out of range", mapping.name, i))?;
+                    if let Some(n) = at(ctrl_in_nums) {
+                        indexed.ctrl_in_num = Some(n);
+                    }
+                    if let Some(n) = at(ctrl_out_nums) {
+                        indexed.ctrl_out_num = Some(n);
+                    }
+                    if let Some(n) = at(ctrl_mode_nums) {
+                        indexed.ctrl_mode_num = Some(n);
+                    }
+                    if let (Some(n), Some(midi)) = (at(midi_nums), indexed.midi.as_mut()) {
+                        midi.num = n;
+                    }
+                    if let Some(label) = labels.get(i as usize) {
+                        indexed.name = indexed.name.replace("{label}", label);
+                    }
+                    mappings.push(indexed);
+                }
+            },
+            AbstractMapping::Grid { rows, cols, mapping, row_stride, col_stride, midi_row_stride, midi_col_stride } => {
+                let row_stride = row_stride.unwrap_or(*cols);
+                let col_stride = col_stride.unwrap_or(1);
+                let midi_row_stride = midi_row_stride.unwrap_or(row_stride);
+                let midi_col_stride = midi_col_stride.unwrap_or(col_stride);
+                let out_of_range = |row, col| format!("{}: row {} col {} puts a control or MIDI number out of range", mapping.name, row, col);
+                for row in 0..*rows {
+                    for col in 0..*cols {
+                        let at = |row_stride: u8, col_stride: u8| row.checked_mul(row_stride)?.checked_add(col.checked_mul(col_stride)?);
+                        let (Some(i), Some(ctrl_offset), Some(midi_offset)) = (at(*cols, 1), at(row_stride, col_stride), at(midi_row_stride, midi_col_stride)) else {
+                            return Err(out_of_range(row, col));
+                        };
+                        let vars = [("i", i), ("row", row), ("col", col)];
+                        let offset = mapping.offset(ctrl_offset, midi_offset).ok_or_else(|| out_of_range(row, col))?;
+                        mappings.push(Mapping {
+                            name: substitute(&mapping.name, &vars),
+                            raw_feedback: mapping.raw_feedback.iter().map(|raw| raw.substitute(&vars)).collect(),
+                            ..offset
+                        });
+                    }
+                }
+            },
+            AbstractMapping::Transport(block) => {
+                for (i, action) in TransportAction::ALL.into_iter().enumerate() {
+                    let i = i as u8;
+                    let out_of_range = || format!("transport block: {} puts a control or MIDI number out of range", action.name());
+                    mappings.push(Mapping {
+                        ctrl_in_num: Some(block.ctrl_in_num.checked_add(i).ok_or_else(out_of_range)?),
+                        ctrl_out_num: block.ctrl_out_num.map(|n| n.checked_add(i).ok_or_else(out_of_range)).transpose()?,
+                        midi: block.midi.map(|m| m.index(i).ok_or_else(out_of_range)).transpose()?,
+                        ..Mapping::new(format!("transport/{}", action.name()), CtrlKind::Transport { action })
+                    });
                 }
             }
         };
-        mappings.into_iter()
+        Ok(mappings)
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OscInterface {
-    pub host_addr: SocketAddrV4,
+    /// the address messages are sent from. if not given, they are sent from `in_addr`.
+    pub host_addr: Option<SocketAddrV4>,
     pub out_addr: SocketAddrV4,
-    pub in_addr: SocketAddrV4
+    pub in_addr: SocketAddrV4,
+    #[serde(default)]
+    pub forward: Vec<OscForward>,
+    #[serde(default)]
+    pub socket: SocketOptions,
+    /// addresses allowed to send to `in_addr`. if empty, anyone can.
+    #[serde(default)]
+    pub allow: Vec<IpAddr>,
+    /// a secret that incoming messages must carry as their first argument.
+    pub password: Option<String>,
+    /// where answers to queries such as `/autocrap/get` go.
+    #[serde(default)]
+    pub reply_to: ReplyTo,
+    /// bundles timed less than this far in the future are applied right away.
+    #[serde(default = "default_bundle_tolerance_ms")]
+    pub bundle_tolerance_ms: u64,
+    /// send the messages for each event as a bundle timetagged with when the event was received.
+    #[serde(default)]
+    pub timetag: bool,
+    /// put in front of every address sent, and expected in front of every address received, e.g. `/nocturn1`.
+    #[serde(default)]
+    pub prefix: String,
+}
+
+fn default_bundle_tolerance_ms() -> u64 {
+    10
+}
+
+impl OscInterface {
+    /// whether sending and receiving go through the same socket, because `host_addr` is left out or would
+    /// bind the same port as `in_addr`.
+    pub fn shares_socket(&self) -> bool {
+        self.host_addr.is_none_or(|host| host.port() == self.in_addr.port()
+            && (host.ip() == self.in_addr.ip() || host.ip().is_unspecified() || self.in_addr.ip().is_unspecified()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ReplyTo {
+    /// to `out_addr`, like everything else.
+    #[default]
+    OutAddr,
+    /// back to the address the query came from, so that several clients each get their own answers.
+    Sender,
+}
+
+/// options for the OSC sockets, for networks where control traffic has to compete.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SocketOptions {
+    /// differentiated services code point to mark outgoing packets with, 0-63.
+    pub dscp: Option<u8>,
+    pub send_buffer_size: Option<usize>,
+    pub recv_buffer_size: Option<usize>,
+    /// network interface to send and receive through, such as `eth0`.
+    pub device: Option<String>,
+}
+
+/// passes OSC messages which autocrap doesn't handle through to another application.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OscForward {
+    /// messages with addresses starting with this are sent to `addr`.
+    pub prefix: String,
+    /// where to forward to. unhandled messages coming from here are sent on to `out_addr`.
+    pub addr: SocketAddrV4,
+}
+
+impl OscInterface {
+    /// whether packets from `from` are accepted. forwarding destinations always are.
+    #[cfg(feature = "osc")]
+    pub fn allows(&self, from: SocketAddr) -> bool {
+        self.allow.is_empty()
+            || self.allow.contains(&from.ip())
+            || self.is_forward(from)
+    }
+
+    /// whether `from` is one of the applications messages are forwarded to.
+    #[cfg(feature = "osc")]
+    pub fn is_forward(&self, from: SocketAddr) -> bool {
+        self.forward.iter().any(|f| SocketAddr::V4(f.addr) == from)
+    }
+
+    /// where to pass on an unhandled packet from `from`, containing messages with the given addresses.
+    #[cfg(feature = "osc")]
+    pub fn forward_to<'a>(&self, from: SocketAddr, mut addrs: impl Iterator<Item = &'a str>) -> Option<SocketAddrV4> {
+        if self.is_forward(from) {
+            return Some(self.out_addr);
+        }
+
+        addrs.find_map(|addr| self.forward.iter().find(|f| addr.starts_with(&f.prefix)))
+            .map(|f| f.addr)
+    }
+
+    /// `addr` with the prefix in front.
+    pub fn prefixed(&self, addr: &str) -> String {
+        format!("{}{}", self.prefix, addr)
+    }
+
+    /// `addr` without the prefix, or `None` if it is not under the prefix and so not for autocrap.
+    #[cfg(feature = "osc")]
+    pub fn unprefixed<'a>(&self, addr: &'a str) -> Option<&'a str> {
+        addr.strip_prefix(self.prefix.as_str()).filter(|rest| rest.starts_with('/'))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -120,22 +830,636 @@ pub enum MidiPort {
 pub struct MidiInterface {
     pub client_name: String,
     pub out_port: MidiPort,
-    pub in_port: MidiPort
+    pub in_port: MidiPort,
+    /// output ports for pages 1, 2, and so on. pages without a port use `out_port`.
+    #[serde(default)]
+    pub page_out_ports: Vec<MidiPort>,
+    /// if given, channel messages on other channels are ignored.
+    pub in_channels: Option<Vec<u8>>,
+    /// channels (0-15) of incoming messages to treat as other channels.
+    #[serde(default)]
+    pub input_channel_map: BTreeMap<u8, u8>,
+    /// an input port whose messages are passed through to `out_port`, bypassing the mappings but not `out_filters`.
+    pub thru_port: Option<MidiPort>,
+    /// transforms applied to all outgoing MIDI, in order.
+    #[serde(default)]
+    pub out_filters: Vec<MidiFilter>,
+    #[serde(default)]
+    pub loop_detection: LoopDetectionConfig,
+    /// raw messages sent to `out_port` when this config is started or loaded, such as a program change.
+    #[serde(default)]
+    pub on_activate: Vec<Vec<u8>>
+}
+
+/// the ports of a controller which shows up as a MIDI device of its own.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MidiDeviceConfig {
+    pub client_name: String,
+    pub in_port: MidiPort,
+    pub out_port: MidiPort,
+    /// the channel the controller sends and listens on, 0-15.
+    #[serde(default)]
+    pub channel: u8,
+}
+
+/// how to deal with our own MIDI output coming back in on the input port.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LoopDetectionConfig {
+    /// how soon a sent message must arrive again to count as looped. 0, the default, turns detection off.
+    #[serde(default)]
+    pub window_ms: u64,
+    /// ignore looped messages instead of only warning about them.
+    #[serde(default)]
+    pub drop: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MidiFilter {
+    /// moves channel messages from one channel to another.
+    ChannelMap(BTreeMap<u8, u8>),
+    /// renumbers control changes.
+    CcMap(BTreeMap<u8, u8>),
+    /// reshapes note on velocities: 127 * (v/127)^exponent.
+    VelocityCurve { exponent: f32 },
+    /// clamps CC values and note on velocities.
+    Range { min: u8, max: u8 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObsInterface {
+    #[serde(default = "default_obs_url")]
+    pub url: String,
+    pub password: Option<String>
+}
+
+fn default_obs_url() -> String {
+    "ws://127.0.0.1:4455".to_string()
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Interface {
     Osc(OscInterface),
-    Midi(MidiInterface)
+    Midi(MidiInterface),
+    Obs(ObsInterface)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClockConfig {
+    pub bpm: f32,
+    #[serde(default)]
+    pub send_midi: bool,
+    pub ctrl_in_num: Option<u8>,
+    pub ctrl_out_num: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LfoConfig {
+    pub name: String,
+    pub shape: LfoShape,
+    #[serde(default = "default_lfo_rate")]
+    pub rate: f32,
+    pub depth: f32,
+    pub sync: Option<f32>,
+    pub rate_ctrl_in_num: Option<u8>,
+}
+
+fn default_lfo_rate() -> f32 {
+    1.0
+}
+
+/// how LED feedback is sent to the device.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FeedbackConfig {
+    #[serde(default)]
+    pub transfer: FeedbackTransfer,
+    /// address of the interrupt out endpoint, instead of looking it up by `out_endpoint`.
+    pub endpoint_address: Option<u8>,
+    /// bytes sent before every feedback message, such as a report ID.
+    #[serde(default)]
+    pub prefix: Vec<u8>,
+    /// pack several feedback messages into one packet, for devices which accept that.
+    #[serde(default)]
+    pub coalesce: bool,
+    /// the most LED updates per second for each control that feedback from the host may cause.
+    pub max_rate: Option<f32>,
+    /// how to move motorized faders, for devices which have them.
+    pub motor: Option<MotorConfig>,
+    /// how LED values and colors are put into feedback messages.
+    #[serde(default)]
+    pub codec: FeedbackCodec,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum FeedbackCodec {
+    /// `[ctrl_out_num, value]`, as the Nocturn has it. colors are left out.
+    #[default]
+    Nocturn,
+    /// `[ctrl_out_num, red, green, blue]`. LEDs without a color are white, as bright as their value.
+    Rgb {
+        /// halve each component, for devices which only take 7-bit bytes.
+        #[serde(default)]
+        seven_bit: bool,
+    },
+}
+
+impl FeedbackCodec {
+    /// the bytes one LED takes.
+    pub fn entry_size(self) -> usize {
+        match self {
+            FeedbackCodec::Nocturn => 2,
+            FeedbackCodec::Rgb { .. } => 4,
+        }
+    }
+}
+
+/// the messages which set a motorized fader's position. they are sent like LED feedback,
+/// as `prefix`, the motor number and the position.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MotorConfig {
+    /// bytes sent before every position message, instead of the feedback prefix.
+    #[serde(default)]
+    pub prefix: Vec<u8>,
+    /// send positions as two 7-bit bytes, most significant first, instead of one.
+    #[serde(default)]
+    pub fine: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum FeedbackTransfer {
+    #[default]
+    Interrupt,
+    Control {
+        request_type: u8,
+        request: u8,
+        #[serde(default)]
+        value: u16,
+        #[serde(default)]
+        index: u16,
+    },
+}
+
+/// where and how eagerly a thread runs, for low latency on a busy machine.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ThreadConfig {
+    /// the CPUs the thread may run on. if empty, any of them.
+    #[serde(default)]
+    pub cpus: Vec<usize>,
+    /// the niceness of the thread, from -20 (most eager) to 19. going below 0 usually needs privileges.
+    pub nice: Option<i32>,
+    /// ask for real-time scheduling, so that the thread runs ahead of everything that isn't.
+    #[serde(default)]
+    pub realtime: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// log levels by module path prefix, e.g. `"autocrap::autocrap::obs": "debug"`.
+    #[serde(default)]
+    pub levels: BTreeMap<String, String>,
+    /// size in bytes at which the log file is rotated.
+    #[serde(default = "default_log_max_size")]
+    pub max_size: u64,
+    /// number of rotated log files to keep.
+    #[serde(default = "default_log_keep")]
+    pub keep: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        LoggingConfig {
+            levels: BTreeMap::new(),
+            max_size: default_log_max_size(),
+            keep: default_log_keep(),
+        }
+    }
+}
+
+fn default_log_max_size() -> u64 {
+    1024 * 1024
+}
+
+fn default_log_keep() -> usize {
+    5
+}
+
+fn default_in_transfers() -> usize {
+    4
+}
+
+fn default_handshake_retries() -> u32 {
+    3
+}
+
+/// an extra endpoint a device sends controls on, such as one for buttons next to one for encoders.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InEndpointConfig {
+    /// the endpoint's address, e.g. `131` for 0x83.
+    pub address: u8,
+    #[serde(default)]
+    pub framing: Framing,
+}
+
+/// how to read (control number, value) pairs from an endpoint's packets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Framing {
+    /// bytes at the start of each packet to ignore, such as a report ID.
+    #[serde(default)]
+    pub skip: usize,
+    /// added to control numbers, to keep them apart from those on other endpoints.
+    #[serde(default)]
+    pub num_offset: u8,
+}
+
+/// what to do once reconnecting has failed `max_attempts` times.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnFail {
+    /// stop with an error.
+    #[default]
+    Exit,
+    /// keep trying at the longest delay.
+    KeepTrying
+}
+
+/// how to retry when the device, a MIDI port or the OSC destination goes away.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReconnectConfig {
+    /// delay before the first retry, doubled after every failed one.
+    #[serde(default = "default_reconnect_delay_ms")]
+    pub delay_ms: u64,
+    /// the longest delay between retries.
+    #[serde(default = "default_reconnect_max_delay_ms")]
+    pub max_delay_ms: u64,
+    /// how much delays are randomly varied, as a fraction of the delay.
+    #[serde(default = "default_reconnect_jitter")]
+    pub jitter: f32,
+    /// failed retries in a row before `on_fail` applies. by default there is no limit.
+    pub max_attempts: Option<u32>,
+    #[serde(default)]
+    pub on_fail: OnFail,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> ReconnectConfig {
+        ReconnectConfig {
+            delay_ms: default_reconnect_delay_ms(),
+            max_delay_ms: default_reconnect_max_delay_ms(),
+            jitter: default_reconnect_jitter(),
+            max_attempts: None,
+            on_fail: OnFail::default(),
+        }
+    }
+}
+
+fn default_reconnect_delay_ms() -> u64 {
+    500
+}
+
+fn default_reconnect_max_delay_ms() -> u64 {
+    8000
+}
+
+fn default_reconnect_jitter() -> f32 {
+    0.1
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PagesConfig {
+    pub count: u8,
+    #[serde(default)]
+    pub ctrl_in_nums: Vec<u8>,
+    #[serde(default)]
+    pub ctrl_out_nums: Vec<u8>,
+    /// buttons which switch to a page only while held.
+    #[serde(default)]
+    pub momentary: Vec<MomentaryPage>,
+    /// the LED value for controls of the page left which the new page doesn't use.
+    pub park: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MomentaryPage {
+    pub ctrl_in_num: u8,
+    pub page: u8,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FailsafeConfig {
+    /// seconds without any message from the host before the link counts as lost.
+    pub timeout_secs: f32,
+    /// LED which is lit while the link is lost.
+    pub ctrl_out_num: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdleConfig {
+    /// seconds without activity before the LEDs are switched off.
+    pub after_secs: u64,
+    /// tick less often while idle. this slows down LFOs and the clock.
+    #[serde(default)]
+    pub slow_tick: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SceneConfig {
+    pub name: String,
+    pub ctrl_in_num: Option<u8>,
+    pub ctrl_out_num: Option<u8>,
+    /// values (0-127) by mapping name.
+    pub values: BTreeMap<String, u8>,
+}
+
+/// something done to a group of mappings at once, with a button or over OSC.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupActionConfig {
+    pub name: String,
+    /// names of the mappings in the group.
+    pub controls: Vec<String>,
+    pub ctrl_in_num: Option<u8>,
+    pub action: GroupAction,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum GroupAction {
+    /// random values, within each mapping's `min` and `max`.
+    Randomize,
+    /// moves the values some of the way to a scene's.
+    Morph {
+        scene: String,
+        /// how far to go, in percent of the distance.
+        percent: f32,
+    },
+}
+
+/// an LED which flashes when MIDI/OSC goes in or out for some mappings.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrafficLedConfig {
+    pub ctrl_out_num: u8,
+    /// names of the mappings to watch. all of them if empty.
+    #[serde(default)]
+    pub controls: Vec<String>,
+    #[serde(default)]
+    pub direction: TrafficDirection,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TrafficDirection {
+    /// messages from the host.
+    In,
+    /// messages to the host.
+    Out,
+    #[default]
+    Both,
+}
+
+/// records the movements of some mappings and plays them back in a loop.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LooperConfig {
+    pub name: String,
+    /// names of the mappings to record.
+    pub controls: Vec<String>,
+    /// a button which goes from recording to playing to stopped.
+    pub ctrl_in_num: Option<u8>,
+    pub ctrl_out_num: Option<u8>,
+    /// an encoder which changes the playback speed.
+    pub speed_ctrl_in_num: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TouchConfig {
+    /// how long a control other than a button has to be left alone to count as released.
+    #[serde(default = "default_touch_release_ms")]
+    pub release_ms: u64,
+}
+
+fn default_touch_release_ms() -> u64 {
+    500
+}
+
+/// a button which sets a variable to a value.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VarButtonConfig {
+    pub var: String,
+    pub value: u8,
+    pub ctrl_in_num: Option<u8>,
+    pub ctrl_out_num: Option<u8>,
+    /// MIDI from the host which presses the button.
+    pub midi: Option<MidiSpec>,
+}
+
+/// a pair of buttons which shift the MIDI numbers of a group of mappings.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OffsetConfig {
+    pub name: String,
+    /// the kind of MIDI message whose numbers are shifted.
+    pub kind: MidiKind,
+    /// the mappings to shift, by name. if empty, all mappings sending `kind`.
+    #[serde(default)]
+    pub mappings: Vec<String>,
+    pub down_ctrl_in_num: Option<u8>,
+    pub down_ctrl_out_num: Option<u8>,
+    pub up_ctrl_in_num: Option<u8>,
+    pub up_ctrl_out_num: Option<u8>,
+    /// how far one button press shifts.
+    #[serde(default = "default_offset_step")]
+    pub step: u8,
+    #[serde(default = "default_offset_min")]
+    pub min: i8,
+    #[serde(default = "default_offset_max")]
+    pub max: i8,
+}
+
+fn default_offset_step() -> u8 {
+    1
+}
+
+fn default_offset_min() -> i8 {
+    -127
+}
+
+fn default_offset_max() -> i8 {
+    127
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub addr: SocketAddrV4,
+    pub ctrl_in_num: u8,
+    pub ctrl_out_num: Option<u8>,
+    #[serde(default = "default_plugin_count")]
+    pub count: u8,
+    pub prev_page_ctrl_in_num: Option<u8>,
+    pub prev_page_ctrl_out_num: Option<u8>,
+    pub next_page_ctrl_in_num: Option<u8>,
+    pub next_page_ctrl_out_num: Option<u8>,
+    #[serde(default)]
+    pub page_ctrl_out_nums: Vec<u8>,
+    /// addresses allowed to send to `addr`. if empty, anyone can.
+    #[serde(default)]
+    pub allow: Vec<IpAddr>,
+}
+
+impl PluginConfig {
+    /// whether messages from `from` are accepted, and so where replies may go.
+    pub fn allows(&self, from: SocketAddr) -> bool {
+        self.allow.is_empty() || self.allow.contains(&from.ip().to_canonical())
+    }
+}
+
+fn default_plugin_count() -> u8 {
+    8
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
-    pub vendor_id: u16,
-    pub product_id: u16,
-    pub in_endpoint: u8,
-    pub out_endpoint: u8,
+    /// a known device by name, such as `novation-nocturn`, instead of `vendor_id` and `product_id`.
+    pub device: Option<String>,
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    /// the config number of the interrupt IN endpoint. if not given, the first one is used.
+    pub in_endpoint: Option<u8>,
+    /// the config number of the interrupt OUT endpoint. if not given, the first one is used.
+    pub out_endpoint: Option<u8>,
+    #[serde(default)]
+    pub claim_retries: u32,
+    #[serde(default)]
+    pub force_detach: bool,
+    /// times to start over when opening the device fails part way.
+    #[serde(default = "default_handshake_retries")]
+    pub handshake_retries: u32,
+    /// feedback written right after opening the device, to check that it works. defaults to the
+    /// profile's LED reset. empty, or without a profile, the device isn't checked.
+    pub handshake: Option<Vec<u8>>,
+    /// more interrupt IN endpoints to read controls from, besides `in_endpoint`.
+    #[serde(default)]
+    pub in_endpoints: Vec<InEndpointConfig>,
+    /// interrupt transfers to keep waiting on the in endpoint at all times.
+    #[serde(default = "default_in_transfers")]
+    pub in_transfers: usize,
+    #[serde(default)]
+    pub feedback: FeedbackConfig,
+    pub remote: Option<String>,
+    /// where to listen for `autocrap ctl` commands, e.g. `127.0.0.1:9301`.
+    pub control_addr: Option<SocketAddr>,
+    /// addresses besides localhost allowed to send commands to `control_addr`.
+    #[serde(default)]
+    pub control_allow: Vec<IpAddr>,
+    /// a MIDI controller to use instead of the USB device.
+    pub midi_device: Option<MidiDeviceConfig>,
     pub interface: Interface,
+    /// a second interface, translated to and from the first.
+    pub bridge: Option<Interface>,
+    pub clock: Option<ClockConfig>,
+    #[serde(default)]
+    pub lfos: Vec<LfoConfig>,
+    pub plugin: Option<PluginConfig>,
+    pub pages: Option<PagesConfig>,
+    /// a file to remember the active page in, so that autocrap picks up where it left off after a restart.
+    pub state_file: Option<String>,
+    pub touch: Option<TouchConfig>,
+    pub idle: Option<IdleConfig>,
+    pub failsafe: Option<FailsafeConfig>,
+    #[serde(default)]
+    pub scenes: Vec<SceneConfig>,
+    #[serde(default)]
+    pub loopers: Vec<LooperConfig>,
+    #[serde(default)]
+    pub group_actions: Vec<GroupActionConfig>,
+    #[serde(default)]
+    pub traffic_leds: Vec<TrafficLedConfig>,
+    #[serde(default)]
+    pub offsets: Vec<OffsetConfig>,
+    /// variables and their initial values (0-127).
+    #[serde(default)]
+    pub vars: BTreeMap<String, u8>,
+    #[serde(default)]
+    pub var_buttons: Vec<VarButtonConfig>,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    /// settings for threads by name, such as `usb-reader`.
+    #[serde(default)]
+    pub threads: BTreeMap<String, ThreadConfig>,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+    /// refuse to start when a mapping would not work as written, rather than warning and leaving it out.
+    #[serde(default)]
+    pub strict: bool,
+    /// also send the `initial` values of mappings over MIDI/OSC on startup.
+    #[serde(default)]
+    pub announce_initial: bool,
     pub mappings: Vec<AbstractMapping>
 }
 
+impl Config {
+    /// what keeps mappings from working as written, naming the mapping and the field at fault.
+    pub fn mapping_problems(&self) -> Vec<String> {
+        let mappings: Vec<Mapping> = self.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+        let mut problems: Vec<String> = self.mappings.iter().filter_map(|m| m.expand().err()).collect();
+        for mapping in &mappings {
+            match (&mapping.ctrl_kind, mapping.ctrl_in_sequence.as_deref()) {
+                (CtrlKind::EightBit, None) => problems.push(format!(
+                    "{}: EightBit needs ctrl_in_sequence, the control numbers of the high and low parts, e.g. [72, 73]", mapping.name
+                )),
+                (CtrlKind::EightBit, Some(sequence)) if sequence.len() != 2 => problems.push(format!(
+                    "{}: ctrl_in_sequence must have 2 control numbers for EightBit, not {}", mapping.name, sequence.len()
+                )),
+                _ => {}
+            }
+            if let Some(ref lfo) = mapping.lfo {
+                if !self.lfos.iter().any(|l| &l.name == lfo) {
+                    problems.push(format!("{}: lfo {} is not in lfos", mapping.name, lfo));
+                }
+            }
+            if mapping.feedback_source.is_midi_transport() && !matches!(mapping.ctrl_kind, CtrlKind::OnOff { .. }) {
+                problems.push(format!("{}: feedback_source {:?} is only supported for OnOff controls", mapping.name, mapping.feedback_source));
+            }
+            for (i, raw) in mapping.raw_feedback.iter().enumerate() {
+                if raw.render(0x00).is_none() || raw.render(0x7f).is_none() {
+                    problems.push(format!("{}: raw_feedback[{}] has something which is neither a byte (0-255) nor a {{value}} placeholder", mapping.name, i));
+                }
+            }
+            if let Some(ref when) = mapping.when {
+                if !mappings.iter().any(|m| m.name == when.control) && !self.vars.contains_key(&when.control) {
+                    problems.push(format!("{}: when.control {} is neither a mapping nor a variable", mapping.name, when.control));
+                }
+            }
+        }
+        problems
+    }
+
+    /// every mapping the interpreter registers, with ranges, pages and links expanded, in the order it
+    /// tries them.
+    pub fn effective_mappings(&self) -> Vec<Mapping> {
+        let page_count = self.pages.as_ref().map_or(1, |p| p.count);
+        let mut mappings: Vec<Mapping> = self.mappings.iter()
+            .flat_map(|m| m.expand_iter())
+            .flat_map(|m| m.per_page(page_count))
+            .flat_map(|m| m.with_links())
+            .collect();
+        // stable, like the interpreter's
+        mappings.sort_by_key(|m| -m.priority);
+        mappings
+    }
+
+    /// conflicts between mappings of equal priority.
+    pub fn find_conflicts(&self) -> Vec<String> {
+        let mappings: Vec<Mapping> = self.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+        let mut conflicts = vec![];
+        for (i, a) in mappings.iter().enumerate() {
+            for b in mappings[i+1..].iter().filter(|b| b.priority == a.priority) {
+                if let Some(what) = a.conflict_with(b) {
+                    conflicts.push(format!("{} and {} both claim {}", a.name, b.name, what));
+                }
+            }
+        }
+        conflicts
+    }
+}
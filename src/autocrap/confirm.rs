@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use rosc::OscMessage;
+
+use super::{
+    config::{ConfirmConfig, Mapping},
+    determinism,
+    interpreter::{CtrlLogic, CtrlResponse, Response},
+    lfo::Lfo
+};
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(150);
+
+/// wraps a mapping which needs confirming. the first press only arms it, blinking its LED. pressing
+/// it again, or the confirm button, before the timeout passes the press on.
+#[derive(Debug)]
+pub struct ConfirmLogic {
+    inner: Box<dyn CtrlLogic>,
+    ctrl_in_num: u8,
+    ctrl_out_num: Option<u8>,
+    confirm_in_num: Option<u8>,
+    timeout: Duration,
+    /// when the first press was, while waiting for confirmation.
+    armed: Option<Instant>,
+    blink_on: bool,
+    /// whether a confirmed press was passed on, so its release should be too.
+    held: bool,
+}
+
+impl ConfirmLogic {
+    /// wraps `inner`, or returns it as is if the mapping has no control to confirm with.
+    pub fn wrap(inner: Box<dyn CtrlLogic>, mapping: &Mapping, config: &ConfirmConfig) -> Box<dyn CtrlLogic> {
+        let Some(ctrl_in_num) = mapping.ctrl_in_num else {
+            return inner;
+        };
+
+        Box::new(ConfirmLogic {
+            inner,
+            ctrl_in_num,
+            ctrl_out_num: mapping.ctrl_out_num,
+            confirm_in_num: config.ctrl_in_num,
+            timeout: Duration::from_millis(config.timeout_ms),
+            armed: None,
+            blink_on: false,
+            held: false,
+        })
+    }
+
+    /// passes on the press, and puts the LED back as the mapping has it.
+    fn confirm(&mut self, release: bool) -> Response {
+        self.armed = None;
+        let mut response = self.inner.handle_ctrl(self.ctrl_in_num, 0x7f).unwrap_or_else(Response::new);
+        if release {
+            response.extend(self.inner.handle_ctrl(self.ctrl_in_num, 0x00).unwrap_or_else(Response::new));
+        } else {
+            self.held = true;
+        }
+        response.ctrl = self.inner.leds();
+        response
+    }
+}
+
+impl CtrlLogic for ConfirmLogic {
+    fn from_mapping(_mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        // only made by wrapping another logic
+        None
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let pressed = val != 0x00;
+        if num == self.ctrl_in_num {
+            if !pressed {
+                if self.held {
+                    self.held = false;
+                    return self.inner.handle_ctrl(num, val);
+                }
+                return Some(Response::new());
+            }
+
+            if self.armed.is_some() && self.confirm_in_num.is_none() {
+                return Some(self.confirm(false));
+            }
+            self.armed = Some(determinism::now());
+            self.blink_on = true;
+            return Some(Response {
+                ctrl: self.ctrl_out_num.map(|num| CtrlResponse { data: vec![num, 0x7f], color: None, raw: false }).into_iter().collect(),
+                ..Response::new()
+            });
+        }
+
+        if Some(num) == self.confirm_in_num && self.armed.is_some() {
+            return Some(if pressed { self.confirm(true) } else { Response::new() });
+        }
+
+        self.inner.handle_ctrl(num, val)
+    }
+
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        self.inner.handle_osc(msg)
+    }
+
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        self.inner.handle_midi(msg)
+    }
+
+    fn init(&mut self) -> Vec<Response> {
+        self.inner.init()
+    }
+
+    fn tick(&mut self, now: Instant, lfos: &[Lfo]) -> Option<Response> {
+        let mut response = self.inner.tick(now, lfos);
+        let Some(armed) = self.armed else {
+            return response;
+        };
+
+        let elapsed = now.saturating_duration_since(armed);
+        let ctrl = if elapsed >= self.timeout {
+            self.armed = None;
+            self.inner.leds()
+        } else {
+            let blink_on = (elapsed.as_millis() / BLINK_INTERVAL.as_millis()).is_multiple_of(2);
+            if blink_on == self.blink_on {
+                return response;
+            }
+            self.blink_on = blink_on;
+            self.ctrl_out_num.map(|num| CtrlResponse { data: vec![num, if blink_on { 0x7f } else { 0x00 }], color: None, raw: false }).into_iter().collect()
+        };
+        response.get_or_insert_with(Response::new).ctrl.extend(ctrl);
+        response
+    }
+
+    fn ticks(&self) -> bool {
+        // the confirmation blinks and times out
+        true
+    }
+
+    fn apply(&mut self, name: &str, val: u8) -> Option<Response> {
+        self.inner.apply(name, val)
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        self.inner.leds()
+    }
+
+    fn led(&mut self, name: &str, val: u8) -> Option<Vec<CtrlResponse>> {
+        self.inner.led(name, val)
+    }
+
+    fn is_in_state(&self, name: &str, is: u8) -> Option<bool> {
+        self.inner.is_in_state(name, is)
+    }
+
+    fn value(&self, name: &str) -> Option<u8> {
+        self.inner.value(name)
+    }
+
+    #[cfg(feature = "osc")]
+    fn osc_value(&self, name: &str) -> Option<f32> {
+        self.inner.osc_value(name)
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::mpsc,
+    time::Duration
+};
+
+use log::{info, warn};
+
+use super::{
+    config::Config,
+    device::Result,
+    unhandled
+};
+
+/// how long a connection gets to send its command, so that one which sends nothing doesn't keep out the rest.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// listens for `autocrap ctl` commands. each connection sends one command on a line, and gets one
+/// line back: `ok`, or `error: ` and what went wrong. only localhost and the `allow` addresses are listened to.
+///
+/// `load <config json>` checks the config with `check` and hands it to `load_tx` to be swapped in.
+/// `unhandled` answers with how often each kind of unhandled input was seen since startup.
+pub fn serve(listener: TcpListener, allow: &[IpAddr], load_tx: mpsc::Sender<Config>, check: fn(&Config, &serde_json::Value) -> Result<()>) -> Result<()> {
+    info!("listening for control commands on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("could not accept control connection: {}", e);
+                continue;
+            }
+        };
+
+        let ip = match stream.peer_addr() {
+            Ok(peer) => peer.ip().to_canonical(),
+            Err(e) => {
+                warn!("could not get the address of a control connection: {}", e);
+                continue;
+            }
+        };
+        if !ip.is_loopback() && !allow.contains(&ip) {
+            warn!("refusing control connection from {}, which is not in control_allow", ip);
+            let _ = stream.shutdown(Shutdown::Both);
+            continue;
+        }
+
+        if let Err(e) = stream.set_read_timeout(Some(TIMEOUT)).and_then(|()| stream.set_write_timeout(Some(TIMEOUT))) {
+            warn!("could not set a timeout on the control connection: {}", e);
+            continue;
+        }
+
+        let mut line = String::new();
+        if let Err(e) = BufReader::new(&stream).read_line(&mut line) {
+            warn!("could not read control command: {}", e);
+            continue;
+        }
+
+        let reply = match command(line.trim_end(), &load_tx, check) {
+            Ok(reply) => reply,
+            Err(e) => format!("error: {}", e)
+        };
+        if let Err(e) = writeln!(stream, "{}", reply) {
+            warn!("could not answer control command: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn command(line: &str, load_tx: &mpsc::Sender<Config>, check: fn(&Config, &serde_json::Value) -> Result<()>) -> Result<String> {
+    let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+    match name {
+        "load" => {
+            let value: serde_json::Value = serde_json::from_str(arg)?;
+            let config: Config = serde_json::from_value(value.clone())?;
+            check(&config, &value)?;
+            info!("loading config from control socket");
+            load_tx.send(config).map_err(|_| "not running")?;
+            Ok("ok".to_string())
+        },
+        "unhandled" => {
+            let totals = unhandled::totals();
+            if totals.is_empty() {
+                return Ok("none".to_string());
+            }
+            Ok(totals.iter().map(|(what, count)| format!("{}: {}", what, count)).collect::<Vec<_>>().join(", "))
+        },
+        _ => Err(format!("unknown command: {}", name).into())
+    }
+}
+
+/// sends a command to a running instance, returning its answer.
+pub fn request(addr: SocketAddr, line: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("could not connect to {}: {}", addr, e))?;
+    writeln!(stream, "{}", line)?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+    let reply = reply.trim_end();
+    match reply.strip_prefix("error: ") {
+        Some(e) => Err(e.to_string().into()),
+        None => Ok(reply.to_string())
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant}
+};
+
+use super::reconnect;
+
+/// a fixed clock and seeded random numbers, for a thread that wants its runs to be repeatable.
+#[derive(Clone, Copy, Debug)]
+struct State {
+    now: Instant,
+    rng: u64,
+}
+
+thread_local! {
+    static STATE: Cell<Option<State>> = const { Cell::new(None) };
+}
+
+/// makes `now` and `random` deterministic on this thread, with the clock starting at the current time.
+pub fn enable(seed: u64) {
+    // xorshift gets stuck on 0
+    STATE.set(Some(State { now: Instant::now(), rng: seed.max(1) }));
+}
+
+/// moves the fixed clock forward. does nothing unless deterministic.
+pub fn advance(by: Duration) {
+    STATE.set(STATE.get().map(|s| State { now: s.now + by, ..s }));
+}
+
+/// the current time, or the fixed clock's time when deterministic.
+pub fn now() -> Instant {
+    STATE.get().map_or_else(Instant::now, |s| s.now)
+}
+
+/// a number in [0, 1), from the seeded generator when deterministic.
+pub fn random() -> f64 {
+    let Some(mut state) = STATE.get() else {
+        return reconnect::random();
+    };
+    state.rng ^= state.rng << 13;
+    state.rng ^= state.rng >> 7;
+    state.rng ^= state.rng << 17;
+    STATE.set(Some(state));
+    (state.rng >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,91 @@
+use std::{error::Error, thread, time::Duration};
+
+use super::config::Config;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// a connection to a control surface, speaking in control numbers and values.
+///
+/// the interpreter and the threads around it only talk to the device through this trait,
+/// so that backends other than raw USB can be plugged in.
+pub trait DeviceBackend: Send + Sync {
+    /// connects to the device described by the config, or returns `None` if it isn't there.
+    fn open(config: &Config) -> Result<Option<Self>> where Self: Sized;
+
+    /// waits up to `timeout` for data from the device, returning (control number, value) pairs.
+    /// returns nothing if the timeout passes.
+    fn read_events(&self, timeout: Duration) -> Result<Vec<[u8; 2]>>;
+
+    /// sends feedback, such as `[ctrl_out_num, value]`, to the device.
+    fn write_feedback(&self, data: &[u8]) -> Result<()>;
+
+    /// sends several feedback messages at once, which backends may combine into fewer transfers.
+    fn write_feedback_batch(&self, messages: &[Vec<u8>]) -> Result<()> {
+        for data in messages {
+            self.write_feedback(data)?;
+        }
+        Ok(())
+    }
+
+    /// sends feedback for one LED along with its color. devices without colors only get the value.
+    fn write_color(&self, data: &[u8], _color: [u8; 3]) -> Result<()> {
+        self.write_feedback(data)
+    }
+
+    /// sends bytes to the device exactly as they are, bypassing whatever encoding `write_feedback` does.
+    fn write_raw(&self, data: &[u8]) -> Result<()> {
+        self.write_feedback(data)
+    }
+
+    /// moves motorized fader `num` to a position (0-16383). devices without motors ignore this.
+    fn write_motor(&self, _num: u8, _position: u16) -> Result<()> {
+        Ok(())
+    }
+
+    /// returns the device to its initial state, with all LEDs off.
+    fn reset(&self) -> Result<()>;
+
+    /// whether the device has come back since the last call after going away, and needs its LEDs redrawn.
+    fn reconnected(&self) -> bool {
+        false
+    }
+}
+
+/// a stand-in for when there is no device, which never has anything to say.
+#[derive(Debug)]
+pub struct NullDevice;
+
+impl DeviceBackend for NullDevice {
+    fn open(_config: &Config) -> Result<Option<NullDevice>> {
+        Ok(Some(NullDevice))
+    }
+
+    fn read_events(&self, timeout: Duration) -> Result<Vec<[u8; 2]>> {
+        thread::sleep(timeout);
+        Ok(vec![])
+    }
+
+    fn write_feedback(&self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// splits data read from the device into (control number, value) pairs.
+pub fn parse_ctrls(data: &[u8]) -> Vec<[u8; 2]> {
+    let mut ctrls = vec![];
+    let mut i = 0;
+    while i+1 < data.len() {
+        if data[i] == 0xb0 {
+            i += 1;
+            continue
+        }
+
+        ctrls.push([data[i], data[i+1]]);
+        i += 2;
+    }
+    ctrls
+}
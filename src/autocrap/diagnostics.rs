@@ -0,0 +1,277 @@
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{fence, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Mutex
+    },
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use rosc::OscPacket;
+use serde_json::Value;
+
+use super::{config::Config, unhandled};
+
+/// how many USB packets and MIDI/OSC messages to remember for a report.
+const HISTORY_LEN: usize = 100;
+
+/// bytes of a packet or message kept for a report. longer ones are cut short.
+const ENTRY_BYTES: usize = 256;
+
+/// which way something recorded went.
+#[derive(Clone, Copy, Debug)]
+pub enum Direction {
+    In,
+    Out,
+    /// to or from a controller which is a MIDI device.
+    DeviceIn,
+    DeviceOut,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [Direction::In, Direction::Out, Direction::DeviceIn, Direction::DeviceOut];
+
+    fn name(self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+            Direction::DeviceIn => "device in",
+            Direction::DeviceOut => "device out",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Usb,
+    Midi,
+    Osc,
+}
+
+impl Kind {
+    const ALL: [Kind; 3] = [Kind::Usb, Kind::Midi, Kind::Osc];
+}
+
+/// one recorded packet or message, as its raw bytes. it is written and read without a lock, like a seqlock:
+/// `seq` is odd while the entry is being written, and changes with every write.
+struct Entry {
+    seq: AtomicU64,
+    /// seconds since the epoch, as the bits of an `f64`.
+    time: AtomicU64,
+    /// the `Kind` and `Direction`, as indices.
+    tag: AtomicU8,
+    /// the length of the whole packet, which may be more than was kept.
+    len: AtomicUsize,
+    bytes: [AtomicU8; ENTRY_BYTES],
+}
+
+struct Recorded {
+    seq: u64,
+    time: f64,
+    kind: Kind,
+    direction: Direction,
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+impl Entry {
+    const fn new() -> Entry {
+        Entry {
+            seq: AtomicU64::new(0),
+            time: AtomicU64::new(0),
+            tag: AtomicU8::new(0),
+            len: AtomicUsize::new(0),
+            bytes: [const { AtomicU8::new(0) }; ENTRY_BYTES],
+        }
+    }
+
+    fn read(&self) -> Option<Recorded> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq == 0 || seq % 2 == 1 {
+            return None;
+        }
+
+        let time = f64::from_bits(self.time.load(Ordering::Relaxed));
+        let tag = self.tag.load(Ordering::Relaxed) as usize;
+        let len = self.len.load(Ordering::Relaxed);
+        let bytes = self.bytes[..len.min(ENTRY_BYTES)].iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        fence(Ordering::Acquire);
+        // written to in the meantime
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+
+        Some(Recorded {
+            seq,
+            time,
+            kind: *Kind::ALL.get(tag / Direction::ALL.len())?,
+            direction: *Direction::ALL.get(tag % Direction::ALL.len())?,
+            len,
+            bytes,
+        })
+    }
+}
+
+/// the last `HISTORY_LEN` entries, overwriting the oldest.
+struct Ring {
+    next: AtomicU64,
+    entries: [Entry; HISTORY_LEN],
+}
+
+impl Ring {
+    const fn new() -> Ring {
+        Ring {
+            next: AtomicU64::new(0),
+            entries: [const { Entry::new() }; HISTORY_LEN],
+        }
+    }
+
+    fn push(&self, kind: Kind, direction: Direction, data: &[u8]) {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        let entry = &self.entries[(n % HISTORY_LEN as u64) as usize];
+        entry.seq.store(2 * n + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        entry.time.store(timestamp().to_bits(), Ordering::Relaxed);
+        entry.tag.store((kind as usize * Direction::ALL.len() + direction as usize) as u8, Ordering::Relaxed);
+        entry.len.store(data.len(), Ordering::Relaxed);
+        for (byte, &b) in entry.bytes.iter().zip(data) {
+            byte.store(b, Ordering::Relaxed);
+        }
+        entry.seq.store(2 * n + 2, Ordering::Release);
+    }
+
+    /// the entries, oldest first.
+    fn read(&self) -> Vec<Recorded> {
+        let mut entries: Vec<Recorded> = self.entries.iter().filter_map(Entry::read).collect();
+        entries.sort_by_key(|e| e.seq);
+        entries
+    }
+}
+
+static CONFIG: Mutex<Option<String>> = Mutex::new(None);
+static USB: Ring = Ring::new();
+static MESSAGES: Ring = Ring::new();
+
+fn timestamp() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+/// an entry as a line of the report.
+fn describe(entry: &Recorded) -> String {
+    let mut line = format!("{:.3}", entry.time);
+    let _ = match entry.kind {
+        Kind::Usb => write!(line, " {} {:02x?}", entry.direction.name(), entry.bytes),
+        Kind::Midi => write!(line, " midi {} {:02x?}", entry.direction.name(), entry.bytes),
+        Kind::Osc => match rosc::decoder::decode_udp(&entry.bytes) {
+            Ok((_, packet)) => write!(line, " osc {} {}", entry.direction.name(), describe_osc(&packet)),
+            Err(_) => write!(line, " osc {} {:02x?}", entry.direction.name(), entry.bytes),
+        }
+    };
+    if entry.len > entry.bytes.len() {
+        let _ = write!(line, " ... ({} bytes)", entry.len);
+    }
+    line
+}
+
+fn describe_osc(packet: &OscPacket) -> String {
+    match packet {
+        OscPacket::Message(msg) => format!("{} {:?}", msg.addr, msg.args),
+        OscPacket::Bundle(bundle) => format!("[{}]", bundle.content.iter().map(describe_osc).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// remembers the config, minus secrets.
+pub fn set_config(config: &Config) {
+    let summary = redacted(config, true);
+    // a poisoned lock only means another thread panicked while setting it, the config is still useful
+    *CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = Some(summary);
+}
+
+/// the config as JSON, with secrets such as passwords left out, e.g. for logging.
+pub fn redacted(config: &Config, pretty: bool) -> String {
+    serde_json::to_value(config)
+        .map(|mut value| {
+            strip_secrets(&mut value);
+            let json = if pretty { serde_json::to_string_pretty(&value) } else { serde_json::to_string(&value) };
+            json.unwrap_or_default()
+        })
+        .unwrap_or_else(|e| format!("unserializable config: {}", e))
+}
+
+/// replaces secrets such as passwords in a config's JSON.
+pub fn strip_secrets(value: &mut Value) {
+    match value {
+        Value::Object(fields) => for (key, field) in fields.iter_mut() {
+            if key == "password" && !field.is_null() {
+                *field = Value::String("<redacted>".to_string());
+            } else {
+                strip_secrets(field);
+            }
+        },
+        Value::Array(items) => items.iter_mut().for_each(strip_secrets),
+        _ => {}
+    }
+}
+
+/// records a USB packet for the report. this is on the hot path, so only the bytes are kept, and they are
+/// formatted when the report is written.
+pub fn record_usb(direction: Direction, data: &[u8]) {
+    USB.push(Kind::Usb, direction, data);
+}
+
+#[cfg(feature = "midi")]
+pub fn record_midi(direction: Direction, data: &[u8]) {
+    MESSAGES.push(Kind::Midi, direction, data);
+}
+
+/// records an encoded OSC packet.
+pub fn record_osc(direction: Direction, packet: &[u8]) {
+    MESSAGES.push(Kind::Osc, direction, packet);
+}
+
+/// writes a diagnostic report to the temp directory, returning its path.
+pub fn write_report(reason: &str) -> io::Result<PathBuf> {
+    let mut report = String::new();
+    let _ = writeln!(report, "autocrap {} crash report", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "time: {:.3}", timestamp());
+    let _ = writeln!(report, "platform: {} {}", env::consts::OS, env::consts::ARCH);
+    let _ = writeln!(report, "reason: {}", reason);
+
+    let config = CONFIG.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let _ = writeln!(report, "\n## config\n{}", config.as_deref().unwrap_or("(not loaded)"));
+    let usb = USB.read();
+    let _ = writeln!(report, "\n## last {} USB packets", usb.len());
+    for entry in &usb {
+        let _ = writeln!(report, "{}", describe(entry));
+    }
+    let messages = MESSAGES.read();
+    let _ = writeln!(report, "\n## last {} MIDI/OSC messages", messages.len());
+    for entry in &messages {
+        let _ = writeln!(report, "{}", describe(entry));
+    }
+    let totals = unhandled::totals();
+    let _ = writeln!(report, "\n## unhandled input ({} kinds)", totals.len());
+    for (what, count) in totals {
+        let _ = writeln!(report, "{}: {}", what, count);
+    }
+
+    let path = env::temp_dir().join(format!("autocrap-crash-{}.txt", timestamp() as u64));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// writes a report whenever a thread panics, in addition to the usual panic message.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        match write_report(&info.to_string()) {
+            Ok(path) => eprintln!("crash report written to {}", path.display()),
+            Err(e) => eprintln!("could not write crash report: {}", e),
+        }
+    }));
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use super::{
+    config::{Config, Mapping},
+    diagnostics
+};
+
+/// describes how `new` differs from `old`: settings by path, and mappings by name after expanding ranges and grids.
+pub fn diff(old: &Config, new: &Config) -> Vec<String> {
+    let mut lines = vec![];
+
+    let (Ok(mut old_value), Ok(mut new_value)) = (serde_json::to_value(old), serde_json::to_value(new)) else {
+        return vec!["could not compare configs".to_string()];
+    };
+    for value in [&mut old_value, &mut new_value] {
+        diagnostics::strip_secrets(value);
+        if let Value::Object(fields) = value {
+            fields.remove("mappings");
+        }
+    }
+    for (path, old_field, new_field) in changes("", &old_value, &new_value) {
+        lines.push(format!("~ {}: {} -> {}", path, show(&old_field), show(&new_field)));
+    }
+
+    let old_mappings: Vec<Mapping> = old.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+    let new_mappings: Vec<Mapping> = new.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+    let find = |mappings: &[Mapping], name: &str| mappings.iter().find(|m| m.name == name).cloned();
+
+    let mut seen = BTreeSet::new();
+    for mapping in old_mappings.iter().chain(new_mappings.iter()) {
+        if !seen.insert(mapping.name.clone()) {
+            continue;
+        }
+
+        match (find(&old_mappings, &mapping.name), find(&new_mappings, &mapping.name)) {
+            (Some(_), None) => lines.push(format!("- {}", mapping.name)),
+            (None, Some(added)) => lines.push(format!("+ {}: {}", added.name, summary(&added))),
+            (Some(old_mapping), Some(new_mapping)) => {
+                let (Ok(old_value), Ok(new_value)) = (serde_json::to_value(&old_mapping), serde_json::to_value(&new_mapping)) else {
+                    continue;
+                };
+                let fields: Vec<String> = changes("", &old_value, &new_value).into_iter()
+                    .map(|(path, old_field, new_field)| format!("{} {} -> {}", path, show(&old_field), show(&new_field)))
+                    .collect();
+                if !fields.is_empty() {
+                    lines.push(format!("~ {}: {}", mapping.name, fields.join(", ")));
+                }
+            },
+            (None, None) => {}
+        }
+    }
+
+    lines
+}
+
+/// the paths at which two values differ, descending into objects.
+fn changes(path: &str, old: &Value, new: &Value) -> Vec<(String, Value, Value)> {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let keys: BTreeSet<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+            keys.into_iter()
+                .flat_map(|key| {
+                    let sub_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                    changes(
+                        &sub_path,
+                        old_fields.get(key).unwrap_or(&Value::Null),
+                        new_fields.get(key).unwrap_or(&Value::Null)
+                    )
+                })
+                .collect()
+        },
+        _ if old == new => vec![],
+        _ => vec![(path.to_string(), old.clone(), new.clone())]
+    }
+}
+
+/// the interesting parts of a new mapping: what it listens to and what it sends.
+fn summary(mapping: &Mapping) -> String {
+    let mut parts = vec![];
+    let nums = mapping.ctrl_in_nums();
+    if !nums.is_empty() {
+        parts.push(format!("ctrl {:?}", nums));
+    }
+    if let Some(midi) = mapping.midi {
+        parts.push(format!("midi {}", show(&serde_json::to_value(midi).unwrap_or_default())));
+    }
+    if let Some(page) = mapping.page {
+        parts.push(format!("page {}", page));
+    }
+    parts.join(", ")
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => "none".to_string(),
+        _ => value.to_string()
+    }
+}
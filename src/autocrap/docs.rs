@@ -0,0 +1,164 @@
+use std::fmt::Write as _;
+
+use super::config::{Config, CtrlKind, Interface, Mapping, MidiKind, MidiSpec};
+
+/// one line of the cheat sheet.
+struct Row {
+    name: String,
+    kind: String,
+    device: String,
+    midi: String,
+    osc: String,
+    range: String,
+}
+
+/// mappings which share a page.
+struct Section {
+    title: Option<String>,
+    rows: Vec<Row>,
+}
+
+/// a Markdown cheat sheet of every mapping, grouped by page.
+pub fn markdown(config: &Config) -> String {
+    let (headers, sections) = sheet(config);
+    let mut out = String::new();
+    let _ = writeln!(out, "# autocrap mappings");
+    for section in sections {
+        if let Some(title) = section.title {
+            let _ = writeln!(out, "\n## {}", title);
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "| {} |", headers.join(" | "));
+        let _ = writeln!(out, "|{}", " --- |".repeat(headers.len()));
+        for row in section.rows {
+            let cells: Vec<String> = columns(&row, &headers).iter().map(|c| c.replace('|', "\\|")).collect();
+            let _ = writeln!(out, "| {} |", cells.join(" | "));
+        }
+    }
+    out
+}
+
+/// the same cheat sheet as a standalone HTML page, for printing.
+pub fn html(config: &Config) -> String {
+    let (headers, sections) = sheet(config);
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(out, "<html><head><meta charset=\"utf-8\"><title>autocrap mappings</title>");
+    let _ = writeln!(out, "<style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; margin-bottom: 1em; }} th, td {{ border: 1px solid #999; padding: 0.2em 0.5em; text-align: left; }}</style>");
+    let _ = writeln!(out, "</head><body>");
+    let _ = writeln!(out, "<h1>autocrap mappings</h1>");
+    for section in sections {
+        if let Some(title) = section.title {
+            let _ = writeln!(out, "<h2>{}</h2>", escape(&title));
+        }
+        let _ = writeln!(out, "<table>");
+        let _ = writeln!(out, "<tr>{}</tr>", headers.iter().map(|h| format!("<th>{}</th>", h)).collect::<String>());
+        for row in section.rows {
+            let _ = writeln!(out, "<tr>{}</tr>", columns(&row, &headers).iter().map(|c| format!("<td>{}</td>", escape(c))).collect::<String>());
+        }
+        let _ = writeln!(out, "</table>");
+    }
+    let _ = writeln!(out, "</body></html>");
+    out
+}
+
+/// the column headers, leaving out MIDI or OSC if the config doesn't use them, and the rows by page.
+fn sheet(config: &Config) -> (Vec<&'static str>, Vec<Section>) {
+    let interfaces: Vec<&Interface> = std::iter::once(&config.interface).chain(config.bridge.as_ref()).collect();
+    let mut headers = vec!["control", "kind", "device"];
+    if interfaces.iter().any(|i| matches!(i, Interface::Midi(_))) {
+        headers.push("MIDI");
+    }
+    if interfaces.iter().any(|i| matches!(i, Interface::Osc(_))) {
+        headers.push("OSC");
+    }
+    headers.push("range");
+
+    let mappings: Vec<Mapping> = config.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+    let page_count = config.pages.as_ref().map_or(0, |p| p.count);
+    let pages = std::iter::once(None).chain((1..=page_count).map(Some));
+    let sections = pages
+        .map(|page| Section {
+            title: match page {
+                _ if page_count == 0 => None,
+                None => Some("every page".to_string()),
+                Some(p) => Some(format!("page {}", p)),
+            },
+            rows: mappings.iter().filter(|m| m.page == page || page_count == 0).map(row).collect(),
+        })
+        .filter(|section| !section.rows.is_empty())
+        .collect();
+
+    (headers, sections)
+}
+
+fn columns(row: &Row, headers: &[&str]) -> Vec<String> {
+    headers.iter()
+        .map(|&header| match header {
+            "control" => row.name.clone(),
+            "kind" => row.kind.clone(),
+            "device" => row.device.clone(),
+            "MIDI" => row.midi.clone(),
+            "OSC" => row.osc.clone(),
+            _ => row.range.clone(),
+        })
+        .collect()
+}
+
+fn row(mapping: &Mapping) -> Row {
+    let kind = match mapping.ctrl_kind {
+        CtrlKind::OnOff { mode } => format!("button ({:?})", mode).to_lowercase(),
+        CtrlKind::EightBit => "fader".to_string(),
+        CtrlKind::Relative { mode, .. } => format!("encoder ({:?})", mode).to_lowercase(),
+        CtrlKind::Transport { action } => format!("transport ({:?})", action).to_lowercase(),
+        CtrlKind::Jog { .. } => "jog wheel".to_string(),
+    };
+
+    let mut device = vec![];
+    let ins = mapping.ctrl_in_nums();
+    if !ins.is_empty() {
+        device.push(format!("in {}", ins.iter().map(u8::to_string).collect::<Vec<_>>().join("+")));
+    }
+    if let Some(num) = mapping.ctrl_out_num {
+        device.push(format!("LED {}", num));
+    }
+
+    let midi: Vec<String> = mapping.midi.iter()
+        .chain(mapping.links.iter().filter_map(|l| l.midi.as_ref()))
+        .map(describe_midi)
+        .collect();
+
+    let range = match mapping.ctrl_kind {
+        CtrlKind::OnOff { .. } | CtrlKind::Transport { .. } => "on/off".to_string(),
+        CtrlKind::Jog { .. } => "relative".to_string(),
+        _ => {
+            let range = format!("{}-{}", mapping.min.unwrap_or(0), mapping.max.unwrap_or(127));
+            match mapping.steps {
+                Some(steps) => format!("{}, {} steps", range, steps),
+                None => range
+            }
+        }
+    };
+
+    Row {
+        name: mapping.name.clone(),
+        kind,
+        device: device.join(", "),
+        midi: midi.join(", "),
+        osc: mapping.osc_addr(),
+        range,
+    }
+}
+
+pub fn describe_midi(midi: &MidiSpec) -> String {
+    let kind = match midi.kind {
+        MidiKind::Cc => "CC",
+        MidiKind::Note => "note",
+    };
+    let omni = if midi.omni { " (omni)" } else { "" };
+    format!("ch {} {} {}{}", midi.channel + 1, kind, midi.num, omni)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
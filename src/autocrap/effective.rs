@@ -0,0 +1,66 @@
+use std::{error::Error, fmt::Write as _};
+
+use serde_json::Value;
+
+use super::{
+    config::{AbstractMapping, Config},
+    docs::describe_midi,
+    profile
+};
+
+/// the config as autocrap sees it once loaded: with every default spelled out, what the device's profile
+/// fills in, and the mappings expanded into the ones the interpreter registers, in the order it tries them.
+pub fn json(config: &Config) -> Result<String, Box<dyn Error>> {
+    let mut config = config.clone();
+    if let Some(profile) = profile::find(&config) {
+        config.vendor_id = config.vendor_id.or(Some(profile.vendor_id));
+        config.product_id = config.product_id.or(Some(profile.product_id));
+        config.in_endpoint = config.in_endpoint.or(Some(profile.in_endpoint));
+        config.out_endpoint = config.out_endpoint.or(Some(profile.out_endpoint));
+    }
+    let mappings = config.effective_mappings();
+    config.mappings = vec![];
+
+    let mut value = serde_json::to_value(&config)?;
+    value["mappings"] = mappings.into_iter()
+        .map(|m| {
+            let linked = m.linked;
+            let mut mapping = serde_json::to_value(AbstractMapping::Single(m))?;
+            // not a config field, but worth knowing
+            if linked {
+                mapping["Single"]["linked"] = Value::Bool(true);
+            }
+            Ok(mapping)
+        })
+        .collect::<Result<_, serde_json::Error>>()?;
+    Ok(format!("{}\n", serde_json::to_string_pretty(&value)?))
+}
+
+/// the expanded mappings, one per line, with what they read, write and send.
+pub fn table(config: &Config) -> String {
+    let headers = ["name", "page", "in", "out", "osc", "midi"];
+    let rows: Vec<[String; 6]> = config.effective_mappings().iter()
+        .map(|m| {
+            let nums = |nums: Vec<u8>| nums.iter().map(u8::to_string).collect::<Vec<_>>().join("+");
+            let name = if m.linked { format!("{} (link)", m.name) } else { m.name.clone() };
+            [
+                name,
+                m.page.map(|p| p.to_string()).unwrap_or_default(),
+                nums(m.ctrl_in_nums()),
+                nums(m.ctrl_out_num.into_iter().collect()),
+                m.osc_addr(),
+                m.midi.as_ref().map(describe_midi).unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| rows.iter().map(|r| r[i].len()).chain([headers[i].len()]).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in std::iter::once(headers.map(String::from)).chain(rows) {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(c, w)| format!("{:w$}", c, w = w)).collect();
+        let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+    }
+    out
+}
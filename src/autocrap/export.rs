@@ -0,0 +1,96 @@
+use std::fmt::Write as _;
+
+use super::config::{Config, CtrlKind, Interface, Mapping, MidiKind, MidiPort, MidiSpec, RelativeMode, TransportAction};
+
+/// how many encoders a Live user remote script has.
+const LIVE_ENCODERS: usize = 8;
+
+/// a `UserConfiguration.txt` for an Ableton Live user remote script, mapping Live's encoders to
+/// the first 8 encoder mappings with a CC, and its transport to the transport mappings.
+///
+/// Live uses one channel for all controls, taken from the first encoder. mappings on other channels are left out.
+pub fn live(config: &Config) -> String {
+    // with pages, only what is there on page 1 counts, as Live knows nothing about pages
+    let mappings: Vec<Mapping> = config.mappings.iter()
+        .flat_map(|m| m.expand_iter())
+        .filter(|m| m.page.is_none_or(|p| p == 1))
+        .collect();
+    let ccs: Vec<(&Mapping, MidiSpec)> = mappings.iter()
+        .filter_map(|m| Some((m, m.midi.filter(|midi| midi.kind == MidiKind::Cc)?)))
+        .collect();
+
+    let encoders: Vec<&(&Mapping, MidiSpec)> = ccs.iter()
+        .filter(|(m, _)| matches!(m.ctrl_kind, CtrlKind::Relative { mode: RelativeMode::Accumulate | RelativeMode::Bipolar, .. }))
+        .collect();
+    let channel = encoders.first().map_or(0, |(_, midi)| midi.channel);
+    let encoders: Vec<(&str, u8)> = encoders.into_iter()
+        .filter(|(_, midi)| midi.channel == channel)
+        .map(|(m, midi)| (m.name.as_str(), midi.num))
+        .collect();
+
+    let (input_name, output_name) = match config.interface {
+        Interface::Midi(ref midi) => (port_name(&midi.out_port), port_name(&midi.in_port)),
+        _ => (String::new(), String::new())
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# Config File for User-defined InstantMappings");
+    let _ = writeln!(out, "# generated by autocrap. put it in a folder under Live's User Remote Scripts folder.");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "[Globals]");
+    let _ = writeln!(out, "GlobalChannel: {}", channel);
+    let _ = writeln!(out, "InputName: {}", input_name);
+    let _ = writeln!(out, "OutputName: {}", output_name);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "[DeviceControls]");
+    for i in 0..LIVE_ENCODERS {
+        match encoders.get(i) {
+            Some((name, num)) => {
+                let _ = writeln!(out, "# {}", name);
+                let _ = writeln!(out, "Encoder{}: {}", i + 1, num);
+            },
+            None => {
+                let _ = writeln!(out, "Encoder{}: -1", i + 1);
+            }
+        }
+    }
+    let _ = writeln!(out, "EncoderMapMode: Absolute");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "[TransportControls]");
+    for (action, key) in [
+        (TransportAction::Stop, "StopButton"),
+        (TransportAction::Play, "PlayButton"),
+        (TransportAction::Record, "RecordButton"),
+        (TransportAction::Loop, "LoopButton"),
+    ] {
+        let num = ccs.iter()
+            .find(|(m, midi)| midi.channel == channel && matches!(m.ctrl_kind, CtrlKind::Transport { action: a } if a == action))
+            .map_or(-1, |(_, midi)| midi.num as i32);
+        let _ = writeln!(out, "{}: {}", key, num);
+    }
+    out
+}
+
+/// a plain list of every mapping's MIDI message, to map by hand in any DAW.
+pub fn csv(config: &Config) -> String {
+    let mut out = String::from("name,page,channel,type,number\n");
+    for mapping in config.mappings.iter().flat_map(|m| m.expand_iter()) {
+        let midis = mapping.midi.iter().chain(mapping.links.iter().filter_map(|l| l.midi.as_ref()));
+        for midi in midis {
+            let kind = match midi.kind {
+                MidiKind::Cc => "cc",
+                MidiKind::Note => "note",
+            };
+            let page = mapping.page.map(|p| p.to_string()).unwrap_or_default();
+            let _ = writeln!(out, "{},{},{},{},{}", mapping.name, page, midi.channel + 1, kind, midi.num);
+        }
+    }
+    out
+}
+
+fn port_name(port: &MidiPort) -> String {
+    match port {
+        MidiPort::Name(name) | MidiPort::Virtual(name) => name.clone(),
+        MidiPort::Index(_) => String::new()
+    }
+}
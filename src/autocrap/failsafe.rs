@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rosc::OscMessage;
+
+use super::{
+    config::{FailsafeConfig, Mapping},
+    determinism,
+    interpreter::{CtrlResponse, Response}
+};
+
+const OSC_HEARTBEAT_ADDR: &str = "/autocrap/heartbeat";
+
+/// watches for signs of life from the host. when it goes quiet for too long, mappings are set to
+/// their `failsafe` values.
+#[derive(Debug)]
+pub struct Failsafe {
+    timeout: Duration,
+    ctrl_out_num: Option<u8>,
+    /// mapping names and their failsafe values.
+    values: Vec<(String, u8)>,
+    last_heard: Instant,
+    lost: bool,
+}
+
+impl Failsafe {
+    pub fn new(config: &FailsafeConfig, mappings: &[Mapping]) -> Failsafe {
+        Failsafe {
+            timeout: Duration::from_secs_f32(config.timeout_secs.max(0.0)),
+            ctrl_out_num: config.ctrl_out_num,
+            values: mappings.iter()
+                .filter_map(|m| Some((m.name.clone(), m.failsafe?)))
+                .collect(),
+            last_heard: determinism::now(),
+            lost: false,
+        }
+    }
+
+    pub fn values(&self) -> &[(String, u8)] {
+        &self.values
+    }
+
+    /// lit while the link is lost.
+    fn led(&self) -> Option<CtrlResponse> {
+        Some(CtrlResponse {
+            data: vec![self.ctrl_out_num?, if self.lost { 0x7f } else { 0x00 }],
+            color: None,
+            raw: false
+        })
+    }
+
+    /// notes that the host said something, returning feedback if the link was lost until now.
+    pub fn heard(&mut self, now: Instant) -> Option<Response> {
+        self.last_heard = now;
+        if !self.lost {
+            return None;
+        }
+
+        info!("link to host restored");
+        self.lost = false;
+        Some(self.led().map(Response::from).unwrap_or_else(Response::new))
+    }
+
+    pub fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        if msg.addr != OSC_HEARTBEAT_ADDR {
+            return None;
+        }
+
+        Some(Response::new())
+    }
+
+    /// returns LED feedback when the link has just been lost. the caller applies the failsafe values.
+    pub fn tick(&mut self, now: Instant) -> Option<Response> {
+        if self.lost || now.duration_since(self.last_heard) < self.timeout {
+            return None;
+        }
+
+        warn!("nothing heard from host for {:?}, applying failsafe values", self.timeout);
+        self.lost = true;
+        Some(self.led().map(Response::from).unwrap_or_else(Response::new))
+    }
+}
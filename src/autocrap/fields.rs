@@ -0,0 +1,68 @@
+use serde_json::{Map, Value};
+
+use super::config::Config;
+
+/// fields in a config's JSON which autocrap doesn't know, and so ignores, each with the closest known field
+/// if one is near enough to be a typo.
+///
+/// serde drops unknown fields while parsing, so they are found by serializing the parsed config back and
+/// looking for what went missing on the way.
+pub fn unknown(value: &Value, config: &Config) -> Vec<String> {
+    let Ok(known) = serde_json::to_value(config) else {
+        return vec![];
+    };
+    let mut found = vec![];
+    walk(value, &known, "", &mut found);
+    found
+}
+
+fn walk(value: &Value, known: &Value, path: &str, found: &mut Vec<String>) {
+    match (value, known) {
+        (Value::Object(fields), Value::Object(known_fields)) => {
+            for (key, field) in fields {
+                let at = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match known_fields.get(key) {
+                    Some(known_field) => walk(field, known_field, &at, found),
+                    // a map keyed by numbers, which can be written more than one way
+                    None if key.parse::<f64>().is_ok() => {},
+                    None => found.push(match suggest(key, known_fields) {
+                        Some(s) => format!("unknown field {}, did you mean {}?", at, s),
+                        None => format!("unknown field {}", at)
+                    })
+                }
+            }
+        },
+        (Value::Array(items), Value::Array(known_items)) => {
+            for (i, (item, known_item)) in items.iter().zip(known_items).enumerate() {
+                walk(item, known_item, &format!("{}[{}]", path, i), found);
+            }
+        },
+        _ => {}
+    }
+}
+
+/// the known field closest to `key`, if it is only a few typos away.
+fn suggest<'a>(key: &str, known_fields: &'a Map<String, Value>) -> Option<&'a str> {
+    let max = (key.chars().count() / 3).max(1);
+    known_fields.keys()
+        .map(|k| (distance(key, k), k))
+        .filter(|(d, _)| *d <= max)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k.as_str())
+}
+
+/// levenshtein distance.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(diagonal + usize::from(ca != *cb));
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,44 @@
+use super::config::MidiFilter;
+
+const NOTE_ON: u8 = 0b10010000;
+const CC: u8 = 0b10110000;
+
+impl MidiFilter {
+    /// transforms an outgoing message. system messages pass through untouched.
+    pub fn apply(&self, mut msg: Vec<u8>) -> Vec<u8> {
+        let Some(&status) = msg.first() else {
+            return msg;
+        };
+        if status >= 0xf0 {
+            return msg;
+        }
+
+        let kind = status & 0xf0;
+        let channel = status & 0x0f;
+        match (self, msg.as_mut_slice()) {
+            (MidiFilter::ChannelMap(map), [status, ..]) => {
+                if let Some(&to) = map.get(&channel) {
+                    *status = kind | (to & 0x0f);
+                }
+            },
+            (MidiFilter::CcMap(map), [_, num, _]) if kind == CC => {
+                if let Some(&to) = map.get(num) {
+                    *num = to & 0x7f;
+                }
+            },
+            (MidiFilter::VelocityCurve { exponent }, [_, _, vel]) if kind == NOTE_ON && *vel > 0 => {
+                let curved = 127.0 * (*vel as f32 / 127.0).powf(*exponent);
+                *vel = (curved.round() as u8).clamp(1, 127);
+            },
+            (MidiFilter::Range { min, max }, [_, _, val]) if kind == CC || (kind == NOTE_ON && *val > 0) => {
+                *val = (*val).clamp(*min, (*max).max(*min));
+            },
+            _ => {}
+        }
+        msg
+    }
+}
+
+pub fn apply_all(filters: &[MidiFilter], msg: Vec<u8>) -> Vec<u8> {
+    filters.iter().fold(msg, |msg, filter| filter.apply(msg))
+}
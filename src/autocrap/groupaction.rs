@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+use log::{info, warn};
+use rosc::OscMessage;
+
+use super::{
+    config::{CtrlKind, GroupAction, GroupActionConfig, Mapping, SceneConfig},
+    determinism
+};
+
+const OSC_GROUP_PREFIX: &str = "/autocrap/group/";
+
+/// the range a mapping's value is kept in.
+#[derive(Clone, Copy, Debug)]
+struct Range {
+    min: u8,
+    max: u8,
+    /// only off (min) or on (max), like a button.
+    binary: bool,
+}
+
+#[derive(Debug)]
+struct Group {
+    name: String,
+    ctrl_in_num: Option<u8>,
+    controls: Vec<(String, Range)>,
+    action: Action,
+}
+
+#[derive(Debug)]
+enum Action {
+    Randomize,
+    Morph { target: BTreeMap<String, u8>, amount: f32 },
+}
+
+/// randomizes or morphs groups of mappings.
+#[derive(Debug)]
+pub struct GroupActions {
+    groups: Vec<Group>,
+}
+
+impl GroupActions {
+    pub fn new(configs: &[GroupActionConfig], mappings: &[Mapping], scenes: &[SceneConfig]) -> GroupActions {
+        let mut groups = vec![];
+        for config in configs {
+            let controls = config.controls.iter()
+                .filter_map(|name| {
+                    let Some(mapping) = mappings.iter().find(|m| &m.name == name) else {
+                        warn!("group action {} refers to unknown mapping {}", config.name, name);
+                        return None;
+                    };
+                    let range = Range {
+                        min: mapping.min.unwrap_or(0x00),
+                        max: mapping.max.unwrap_or(0x7f),
+                        binary: matches!(mapping.ctrl_kind, CtrlKind::OnOff { .. }),
+                    };
+                    Some((name.clone(), range))
+                })
+                .collect();
+
+            let action = match config.action {
+                GroupAction::Randomize => Action::Randomize,
+                GroupAction::Morph { ref scene, percent } => {
+                    let Some(scene) = scenes.iter().find(|s| &s.name == scene) else {
+                        warn!("group action {} refers to unknown scene {}, ignoring it", config.name, scene);
+                        continue;
+                    };
+                    Action::Morph { target: scene.values.clone(), amount: (percent / 100.0).clamp(0.0, 1.0) }
+                }
+            };
+
+            groups.push(Group { name: config.name.clone(), ctrl_in_num: config.ctrl_in_num, controls, action });
+        }
+        GroupActions { groups }
+    }
+
+    /// the group triggered by a control, if any.
+    pub fn ctrl_index(&self, num: u8) -> Option<usize> {
+        self.groups.iter().position(|g| g.ctrl_in_num == Some(num))
+    }
+
+    /// the group named by `/autocrap/group/<name>`, if the message is for one.
+    pub fn osc_index(&self, msg: &OscMessage) -> Option<usize> {
+        let name = msg.addr.strip_prefix(OSC_GROUP_PREFIX)?;
+        self.groups.iter().position(|g| g.name == name)
+    }
+
+    /// the new values for a group's mappings, given their current values.
+    pub fn run(&self, i: usize, value: impl Fn(&str) -> Option<u8>) -> Vec<(String, u8)> {
+        let group = &self.groups[i];
+        info!("group action {}", group.name);
+
+        group.controls.iter()
+            .filter_map(|(name, range)| {
+                let val = match group.action {
+                    Action::Randomize if range.binary => if determinism::random() < 0.5 { range.min } else { range.max },
+                    Action::Randomize => {
+                        let span = range.max.saturating_sub(range.min) as f64 + 1.0;
+                        range.min + (determinism::random() * span) as u8
+                    },
+                    Action::Morph { ref target, amount } => morph(value(name)?, *target.get(name)?, amount),
+                };
+                Some((name.clone(), val.clamp(range.min, range.max.max(range.min))))
+            })
+            .collect()
+    }
+}
+
+/// `amount` of the way from `from` to `to`, moving at least one step so that small amounts get there too.
+fn morph(from: u8, to: u8, amount: f32) -> u8 {
+    let val = (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+    match (val == from, to.cmp(&from)) {
+        (true, std::cmp::Ordering::Greater) if amount > 0.0 => from + 1,
+        (true, std::cmp::Ordering::Less) if amount > 0.0 => from - 1,
+        _ => val
+    }
+}
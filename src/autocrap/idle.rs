@@ -0,0 +1,95 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant}
+};
+
+use log::info;
+
+use super::{
+    config::{IdleConfig, Mapping},
+    determinism,
+    interpreter::{CtrlResponse, Response}
+};
+
+/// switches the LEDs off after a while without activity on the device or the host,
+/// and puts them back on the next activity.
+#[derive(Debug)]
+pub struct Idle {
+    after: Duration,
+    slow_tick: bool,
+    /// ring mode controls, which must not be "switched off".
+    mode_nums: Vec<u8>,
+    last_activity: Instant,
+    asleep: bool,
+    /// the last thing sent to each LED, to restore on wake up.
+    leds: BTreeMap<u8, CtrlResponse>,
+}
+
+impl Idle {
+    pub fn new(config: &IdleConfig, mappings: &[Mapping]) -> Idle {
+        Idle {
+            after: Duration::from_secs(config.after_secs),
+            slow_tick: config.slow_tick,
+            mode_nums: mappings.iter().filter_map(|m| m.ctrl_mode_num).collect(),
+            last_activity: determinism::now(),
+            asleep: false,
+            leds: BTreeMap::new(),
+        }
+    }
+
+    /// whether ticks can slow down, because we are asleep.
+    pub fn slow_tick(&self) -> bool {
+        self.asleep && self.slow_tick
+    }
+
+    fn remember(&mut self, response: &Response) {
+        // raw feedback isn't one LED, so it can't be put back like one
+        for ctrl in response.ctrl.iter().filter(|c| !c.raw) {
+            if let Some(&num) = ctrl.data.first() {
+                self.leds.insert(num, ctrl.clone());
+            }
+        }
+    }
+
+    /// passes on the response to something that happened on the device or the host,
+    /// restoring the LEDs if we were asleep.
+    pub fn activity(&mut self, now: Instant, response: Option<Response>) -> Option<Response> {
+        self.last_activity = now;
+        if let Some(ref response) = response {
+            self.remember(response);
+        }
+
+        if !self.asleep {
+            return response;
+        }
+
+        info!("waking up");
+        self.asleep = false;
+        let mut response = response.unwrap_or_else(Response::new);
+        response.ctrl = self.leds.values().cloned().collect();
+        Some(response)
+    }
+
+    /// output which is not caused by activity, such as LFO movement. its LED part is held back while asleep.
+    pub fn passive(&mut self, response: &mut Response) {
+        self.remember(response);
+        if self.asleep {
+            response.ctrl.clear();
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Option<Response> {
+        if self.asleep || now.duration_since(self.last_activity) < self.after {
+            return None;
+        }
+
+        info!("idle for {:?}, switching LEDs off", self.after);
+        self.asleep = true;
+        let mut response = Response::new();
+        response.ctrl = self.leds.keys()
+            .filter(|num| !self.mode_nums.contains(num))
+            .map(|&num| CtrlResponse { data: vec![num, 0x00], color: None, raw: false })
+            .collect();
+        Some(response)
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+/// control numbers of the Nocturn's encoders and buttons, which imported controls are assigned to in order.
+const ENCODER_NUMS: std::ops::Range<u8> = 64..72;
+const BUTTON_NUMS: std::ops::Range<u8> = 112..128;
+
+const NAME_ATTRS: [&str; 4] = ["name", "label", "paramname", "parametername"];
+const CC_ATTRS: [&str; 4] = ["cc", "ccnumber", "controller", "cc_number"];
+const CHANNEL_ATTRS: [&str; 3] = ["channel", "midichannel", "chan"];
+
+/// mappings converted from another program's mapping file.
+#[derive(Debug, Default)]
+pub struct Import {
+    pub mappings: Vec<Value>,
+    /// number of pages the mappings are spread over, if more than one.
+    pub pages: Option<u8>,
+    /// controls which did not fit on the device.
+    pub skipped: Vec<String>,
+}
+
+/// converts an Automap-style XML mapping. the format isn't documented, so this is lenient: every element
+/// with a CC number attribute becomes a mapping, and every `Page` element starts a new page.
+///
+/// on each page, the first 8 controls go to the encoders and the next 16 to the buttons.
+pub fn xml(text: &str) -> Import {
+    let mut import = Import::default();
+    let mut page: u8 = 0;
+    let mut on_page = 0usize;
+    let mut names: BTreeMap<String, usize> = BTreeMap::new();
+
+    let elements = elements(text);
+    let page_count = elements.iter().filter(|(tag, _)| tag.eq_ignore_ascii_case("page")).count();
+    for (tag, attrs) in elements {
+        if tag.eq_ignore_ascii_case("page") {
+            page += 1;
+            on_page = 0;
+            continue;
+        }
+
+        let Some(cc) = find_attr(&attrs, &CC_ATTRS).and_then(|v| v.parse::<u8>().ok()).filter(|&cc| cc < 128) else {
+            continue;
+        };
+        let channel = find_attr(&attrs, &CHANNEL_ATTRS)
+            .and_then(|v| v.parse::<u8>().ok())
+            // files count channels from 1
+            .map_or(0, |c| c.saturating_sub(1).min(15));
+        let name = unique_name(
+            &mut names,
+            &find_attr(&attrs, &NAME_ATTRS).map_or_else(|| format!("cc{}", cc), |n| sanitize(&n))
+        );
+
+        let (ctrl_num, ctrl_kind) = if let Some(num) = ENCODER_NUMS.clone().nth(on_page) {
+            (num, json!({ "Relative": { "mode": "Accumulate" } }))
+        } else if let Some(num) = BUTTON_NUMS.clone().nth(on_page - ENCODER_NUMS.len()) {
+            (num, json!({ "OnOff": { "mode": "Toggle" } }))
+        } else {
+            import.skipped.push(name);
+            continue;
+        };
+        on_page += 1;
+
+        let mut mapping = json!({
+            "name": name,
+            "ctrl_in_num": ctrl_num,
+            "ctrl_out_num": ctrl_num,
+            "ctrl_kind": ctrl_kind,
+            "midi": { "channel": channel, "kind": "Cc", "num": cc }
+        });
+        // controls outside of any page are on every page
+        if page_count > 1 && page > 0 {
+            mapping["page"] = json!(page);
+        }
+        import.mappings.push(json!({ "Single": mapping }));
+    }
+
+    if page_count > 1 {
+        import.pages = Some(page);
+    }
+    import
+}
+
+/// the start tags in an XML document, with their attributes.
+fn elements(text: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut elements = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        elements.push((tag[..name_end].to_string(), attributes(&tag[name_end..])));
+    }
+    elements
+}
+
+fn attributes(mut text: &str) -> Vec<(String, String)> {
+    let mut attrs = vec![];
+    while let Some(eq) = text.find('=') {
+        let key = text[..eq].trim().to_lowercase();
+        let value = text[eq + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some(close) = value[1..].find(quote) else {
+            break;
+        };
+        attrs.push((key, unescape(&value[1..close + 1])));
+        text = &value[close + 2..];
+    }
+    attrs
+}
+
+fn find_attr(attrs: &[(String, String)], keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.trim().to_string()))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+/// makes a name usable in an OSC address.
+fn sanitize(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if name.is_empty() { "control".to_string() } else { name }
+}
+
+fn unique_name(names: &mut BTreeMap<String, usize>, name: &str) -> String {
+    let count = names.entry(name.to_string()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        name.to_string()
+    } else {
+        format!("{}_{}", name, count)
+    }
+}
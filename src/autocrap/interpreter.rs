@@ -1,23 +1,114 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant}
+};
+
 use log::{warn, info};
 use rosc::{OscMessage, OscType};
 
-use super::config::{Config, CtrlKind, Mapping, MidiKind, MidiSpec, OnOffMode, RelativeMode};
+use super::{
+    bridge::Bridge,
+    clock::Clock,
+    color::ColorLogic,
+    confirm::ConfirmLogic,
+    determinism,
+    config::{AbstractMapping, Condition, Config, ConflictPolicy, CtrlKind, FeedbackSource, LimitNotify, Mapping, MidiSpec, OnOffMode, RelativeMode, Stage, ValueTable},
+    failsafe::Failsafe,
+    groupaction::GroupActions,
+    idle::Idle,
+    invert::InvertLogic,
+    jog::JogLogic,
+    lfo::Lfo,
+    looper::Looper,
+    miditransport::MidiTransport,
+    offset::Offset,
+    page::Pages,
+    plugin::Plugin,
+    rawfeedback::RawFeedbackLogic,
+    scene::Scenes,
+    throttle::Throttle,
+    touch::Touch,
+    traffic::TrafficLeds,
+    vars::Vars,
+    transport::TransportLogic
+};
+
+type LogicConstructor = Box<dyn Fn(&Mapping) -> Option<Box<dyn CtrlLogic>>>;
 
 #[derive(Debug)]
 pub struct Interpreter {
-    ctrls: Vec<Box<dyn CtrlLogic>>,
+    ctrls: Vec<Ctrl>,
+    on_conflict: ConflictPolicy,
+    announce_initial: bool,
+    state_file: Option<String>,
+    clock: Option<Clock>,
+    lfos: Vec<Lfo>,
+    plugin: Option<Plugin>,
+    pages: Option<Pages>,
+    offsets: Vec<Offset>,
+    vars: Vars,
+    touch: Option<Touch>,
+    idle: Option<Idle>,
+    failsafe: Option<Failsafe>,
+    scenes: Scenes,
+    loopers: Vec<Looper>,
+    group_actions: GroupActions,
+    traffic: TrafficLeds,
+    midi_transport: MidiTransport,
+    bridge: Option<Bridge>,
+    throttle: Option<Throttle>,
+    /// whether anything needs `tick`.
+    ticks: bool,
+}
+
+#[derive(Debug)]
+struct Ctrl {
+    /// the name of the mapping, unless the ctrl handles several.
+    name: Option<String>,
+    /// the page the ctrl is on, if any.
+    page: Option<u8>,
+    /// whether this is an extra output of the ctrl before it, which handles the same events.
+    linked: bool,
+    when: Option<When>,
+    logic: Box<dyn CtrlLogic>,
+}
+
+/// what decides whether a ctrl is active, and the value it needs to have.
+#[derive(Debug)]
+enum When {
+    /// the state of another ctrl, by index.
+    Ctrl(usize, Condition),
+    Var(Condition),
 }
 
 impl Interpreter {
     pub fn new(config: &Config) -> Interpreter {
-        let constructors: Vec<Box<dyn Fn(&Mapping) -> Option<Box<dyn CtrlLogic>>>> = vec![
+        let constructors: Vec<LogicConstructor> = vec![
             Box::new(OnOffLogic::from_mapping),
             Box::new(EightBitLogic::from_mapping),
             Box::new(RelativeLogic::from_mapping),
+            Box::new(TransportLogic::from_mapping),
+            Box::new(JogLogic::from_mapping),
         ];
-        let mut ctrls: Vec<Box<dyn CtrlLogic>> = vec![];
+        let page_count = config.pages.as_ref().map_or(1, |p| p.count);
+        let mut ctrls: Vec<(i32, Ctrl, Option<Condition>)> = vec![];
         for abstract_mapping in config.mappings.iter() {
-            for mapping in abstract_mapping.expand_iter() {
+            if let AbstractMapping::Transport(_) = abstract_mapping {
+                // the buttons of a block share their state
+                let mappings: Vec<Mapping> = abstract_mapping.expand_iter().collect();
+                let logic = TransportLogic::from_mappings(&mappings);
+                info!("adding {:?}", logic);
+                ctrls.push((0, Ctrl { name: None, page: None, linked: false, when: None, logic: Box::new(logic) }, None));
+                continue;
+            }
+
+            for mapping in abstract_mapping.expand_iter().flat_map(|m| m.per_page(page_count)).flat_map(|m| m.with_links()) {
+                if let Some(ref lfo) = mapping.lfo {
+                    if !config.lfos.iter().any(|l| &l.name == lfo) {
+                        warn!("mapping {} refers to unknown lfo {}", mapping.name, lfo);
+                    }
+                }
+
                 let mut logic_opt: Option<Box<dyn CtrlLogic>> = None;
 
                 for make_logic in &constructors {
@@ -33,53 +124,653 @@ impl Interpreter {
                     warn!("unhandled mapping {:?}", mapping);
                     continue;
                 };
+                let logic = match mapping.confirm {
+                    Some(ref confirm) => ConfirmLogic::wrap(logic, &mapping, confirm),
+                    None => logic
+                };
+                let logic = match mapping.color {
+                    Some(color) => ColorLogic::wrap(logic, &mapping, color),
+                    None => logic
+                };
+                let logic = if mapping.invert_led { InvertLogic::wrap(logic, &mapping) } else { logic };
+                let logic = RawFeedbackLogic::wrap(logic, &mapping);
 
                 info!("adding {:?}", logic);
-                ctrls.push(logic);
+                let ctrl = Ctrl { name: Some(mapping.name.clone()), page: mapping.page, linked: mapping.linked, when: None, logic };
+                ctrls.push((mapping.priority, ctrl, mapping.when));
             }
         }
 
-        let interp = Interpreter {
-            ctrls
+        // stable, so that mappings of equal priority stay in config order
+        ctrls.sort_by_key(|(priority, _, _)| -priority);
+
+        let (mut ctrls, conditions): (Vec<Ctrl>, Vec<Option<Condition>>) = ctrls.into_iter()
+            .map(|(_, ctrl, condition)| (ctrl, condition))
+            .unzip();
+        for (i, condition) in conditions.into_iter().enumerate() {
+            let Some(condition) = condition else {
+                continue;
+            };
+
+            if let Some(j) = ctrls.iter().position(|c| c.logic.is_in_state(&condition.control, 0).is_some()) {
+                ctrls[i].when = Some(When::Ctrl(j, condition));
+            } else if config.vars.contains_key(&condition.control) {
+                ctrls[i].when = Some(When::Var(condition));
+            } else {
+                warn!("condition refers to unknown mapping {}, ignoring it", condition.control);
+            }
+        }
+
+        if config.on_conflict == ConflictPolicy::First {
+            for conflict in config.find_conflicts() {
+                warn!("{}, only the first one will be used", conflict);
+            }
+        }
+
+        let mappings: Vec<Mapping> = config.mappings.iter()
+            .flat_map(|m| m.expand_iter())
+            .flat_map(|m| m.per_page(page_count))
+            .collect();
+        for scene in &config.scenes {
+            for name in scene.values.keys().filter(|&name| !mappings.iter().any(|m| &m.name == name)) {
+                warn!("scene {} refers to unknown mapping {}", scene.name, name);
+            }
+        }
+        for looper in &config.loopers {
+            for name in looper.controls.iter().filter(|&name| !mappings.iter().any(|m| &m.name == name)) {
+                warn!("looper {} refers to unknown mapping {}", looper.name, name);
+            }
+        }
+
+        let mut interpreter = Interpreter {
+            ctrls,
+            on_conflict: config.on_conflict,
+            announce_initial: config.announce_initial,
+            state_file: config.state_file.clone(),
+            clock: config.clock.as_ref().map(Clock::new),
+            lfos: config.lfos.iter().map(Lfo::new).collect(),
+            plugin: config.plugin.as_ref().map(Plugin::new),
+            pages: config.pages.as_ref().map(Pages::new),
+            offsets: config.offsets.iter().map(|offset| Offset::new(offset, &mappings)).collect(),
+            vars: Vars::new(&config.vars, &config.var_buttons, &mappings),
+            touch: config.touch.as_ref().map(|touch| Touch::new(touch, &mappings)),
+            idle: config.idle.as_ref().map(|idle| Idle::new(idle, &mappings)),
+            failsafe: config.failsafe.as_ref().map(|failsafe| Failsafe::new(failsafe, &mappings)),
+            scenes: Scenes::new(&config.scenes),
+            loopers: config.loopers.iter().map(Looper::new).collect(),
+            group_actions: GroupActions::new(&config.group_actions, &mappings, &config.scenes),
+            traffic: TrafficLeds::new(&config.traffic_leds),
+            midi_transport: MidiTransport::new(&mappings),
+            bridge: config.bridge.as_ref().map(|_| Bridge::new(&mappings)),
+            throttle: config.feedback.max_rate.map(Throttle::new),
+            ticks: false,
         };
+        interpreter.ticks = interpreter.clock.is_some()
+            || !interpreter.lfos.is_empty()
+            || interpreter.touch.is_some()
+            || !interpreter.traffic.is_empty()
+            || interpreter.throttle.is_some()
+            || interpreter.failsafe.is_some()
+            || !interpreter.loopers.is_empty()
+            || interpreter.idle.is_some()
+            || interpreter.ctrls.iter().any(|c| c.logic.ticks());
+        interpreter
+    }
 
-        interp
+    /// the page to remember across restarts, if there are pages. a momentary page which is held isn't.
+    pub fn latched_page(&self) -> Option<u8> {
+        self.pages.as_ref().map(|p| p.latched())
+    }
+
+    /// where the active page is remembered across restarts.
+    pub fn state_file(&self) -> Option<&str> {
+        self.state_file.as_deref()
+    }
+
+    /// goes to a page without any output, for picking up where a previous run left off before `init`.
+    pub fn restore_page(&mut self, page: u8) {
+        if let Some(pages) = self.pages.as_mut() {
+            pages.set(page);
+        }
+    }
+
+    /// whether ctrls on `page` currently own their controls.
+    fn is_active(&self, page: Option<u8>) -> bool {
+        self.pages.as_ref().is_none_or(|p| p.is_active(page))
+    }
+
+    /// whether a ctrl currently owns its controls, going by its page and condition.
+    fn ctrl_active(&self, i: usize) -> bool {
+        let ctrl = &self.ctrls[i];
+        self.is_active(ctrl.page) && ctrl.when.as_ref().is_none_or(|when| match when {
+            When::Ctrl(j, condition) => self.ctrls[*j].logic.is_in_state(&condition.control, condition.is).unwrap_or(true),
+            When::Var(condition) => self.vars.get(&condition.control) == Some(condition.is),
+        })
+    }
+
+    /// feedback to send once the device has been reset.
+    pub fn init(&mut self) -> Vec<Response> {
+        let mut responses: Vec<Response> = self.pages.iter().map(|p| p.feedback()).collect();
+        responses.extend(self.offsets.iter().map(|o| Response { ctrl: o.feedback().ctrl, ..Response::new() }));
+        responses.push(Response { ctrl: self.vars.leds(None), ..Response::new() });
+        for i in 0..self.ctrls.len() {
+            let active = self.ctrl_active(i);
+            for mut response in self.ctrls[i].logic.init() {
+                if !active {
+                    response.ctrl.clear();
+                }
+                if !self.announce_initial {
+                    response.osc.clear();
+                    response.midi.clear();
+                }
+                response.set_page(self.ctrls[i].page);
+                responses.push(response);
+            }
+        }
+        let values = self.midi_transport.values();
+        responses.extend(self.transport_leds(values));
+        if let Some(idle) = self.idle.as_mut() {
+            responses.iter_mut().for_each(|r| idle.passive(r));
+        }
+        responses
+    }
+
+    /// LED feedback for the current state, for a device that has lost it.
+    pub fn redraw(&mut self) -> Vec<Response> {
+        let mut responses = vec![];
+        if let Some(pages) = self.pages.as_ref() {
+            responses.push(Response { ctrl: pages.feedback().ctrl, ..Response::new() });
+        }
+        responses.extend(self.offsets.iter().map(|o| Response { ctrl: o.feedback().ctrl, ..Response::new() }));
+        responses.push(Response { ctrl: self.vars.leds(None), ..Response::new() });
+        for i in 0..self.ctrls.len() {
+            if self.ctrl_active(i) {
+                responses.push(Response { ctrl: self.ctrls[i].logic.leds(), ..Response::new() });
+            }
+        }
+        if let Some(idle) = self.idle.as_mut() {
+            responses.iter_mut().for_each(|r| idle.passive(r));
+        }
+        responses
+    }
+
+    /// whether `tick` can do anything at all.
+    pub fn ticks(&self) -> bool {
+        self.ticks
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Vec<Response> {
+        let mut responses = vec![];
+
+        if let Some(clock) = self.clock.as_mut() {
+            responses.extend(clock.tick(now));
+        }
+
+        for lfo in &mut self.lfos {
+            lfo.tick(now, self.clock.as_ref());
+        }
+
+        responses.extend(self.touch.as_mut().and_then(|t| t.tick(now)));
+        responses.extend(self.traffic.tick(now));
+        responses.extend(self.throttle.as_mut().and_then(|t| t.tick(now)));
+
+        for i in 0..self.ctrls.len() {
+            let active = self.ctrl_active(i);
+            let Some(mut response) = self.ctrls[i].logic.tick(now, &self.lfos) else {
+                continue;
+            };
+
+            if !active {
+                response.ctrl.clear();
+            }
+            response.set_page(self.ctrls[i].page);
+            responses.push(response);
+        }
+
+        if let Some(led) = self.failsafe.as_mut().and_then(|f| f.tick(now)) {
+            responses.push(led);
+            let values = self.failsafe.as_ref().map(|f| f.values().to_vec()).unwrap_or_default();
+            for (name, val) in values {
+                responses.extend(self.apply(&name, val));
+            }
+        }
+
+        for i in 0..self.loopers.len() {
+            let (led, values) = self.loopers[i].tick(now);
+            responses.extend(led);
+            for (name, val) in values {
+                responses.extend(self.apply(&name, val));
+            }
+        }
+
+        for offset in &mut self.offsets {
+            responses.iter_mut().for_each(|r| offset.outgoing(r));
+        }
+        responses.iter_mut().for_each(|r| self.vars.outgoing(r));
+        if let Some(idle) = self.idle.as_mut() {
+            responses.iter_mut().for_each(|r| idle.passive(r));
+            responses.extend(idle.tick(now));
+        }
+
+        responses
+    }
+
+    /// whether the ticker can slow down.
+    pub fn slow_tick(&self) -> bool {
+        !self.ticks || self.idle.as_ref().is_some_and(|i| i.slow_tick())
+    }
+
+    /// sets the mapping called `name` to a value, with full output.
+    fn apply(&mut self, name: &str, val: u8) -> Vec<Response> {
+        let before = self.conditions();
+        let mut responses = vec![];
+        for i in 0..self.ctrls.len() {
+            let active = self.ctrl_active(i);
+            let Some(mut response) = self.ctrls[i].logic.apply(name, val) else {
+                continue;
+            };
+
+            if !active {
+                response.ctrl.clear();
+            }
+            self.note_traffic(i, false, &mut response);
+            response.set_page(self.ctrls[i].page);
+            responses.push(response);
+        }
+        if let Some(response) = responses.last_mut() {
+            self.conditions_changed(before, response);
+        }
+        responses
+    }
+
+    fn apply_scene(&mut self, i: usize) -> Response {
+        let (mut response, values) = self.scenes.select(i);
+        for (name, val) in values {
+            self.apply(&name, val).into_iter().for_each(|r| response.extend(r));
+        }
+        response
+    }
+
+    fn run_group_action(&mut self, i: usize) -> Response {
+        let values = self.group_actions.run(i, |name| value(&self.ctrls, &self.pages, name));
+        let mut response = Response::new();
+        for (name, val) in values {
+            self.apply(&name, val).into_iter().for_each(|r| response.extend(r));
+        }
+        response
+    }
+
+    /// lets recording loopers see what the controls did.
+    fn record(&mut self, now: Instant) {
+        let (ctrls, pages) = (&self.ctrls, &self.pages);
+        for looper in &mut self.loopers {
+            looper.record(now, |name| value(ctrls, pages, name));
+        }
+    }
+
+    /// notes that the host is alive.
+    fn heard(&mut self) -> Option<Response> {
+        self.failsafe.as_mut().and_then(|f| f.heard(determinism::now()))
+    }
+
+    /// passes on the response to something that happened on the device or the host, with MIDI offsets applied.
+    fn activity(&mut self, mut response: Option<Response>) -> Option<Response> {
+        if let Some(response) = response.as_mut() {
+            self.offsets.iter_mut().for_each(|o| o.outgoing(response));
+            self.vars.outgoing(response);
+        }
+        match self.idle.as_mut() {
+            Some(idle) => idle.activity(determinism::now(), response),
+            None => response
+        }
     }
 
     pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
-        for ctrl in &mut self.ctrls {
-            let Some(response) = ctrl.handle_ctrl(num, val) else {
+        let pages = &self.pages;
+        let touch = self.touch.as_mut().and_then(|t| {
+            t.handle_ctrl(num, val, determinism::now(), |page| pages.as_ref().is_none_or(|p| p.is_active(page)))
+        });
+
+        let response = merge(touch, self.route_ctrl(num, val));
+        self.record(determinism::now());
+        if let (Some(throttle), Some(response)) = (self.throttle.as_mut(), response.as_ref()) {
+            throttle.direct(determinism::now(), response);
+        }
+        self.activity(response)
+    }
+
+    fn route_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        if let Some(response) = self.clock.as_mut().and_then(|c| c.handle_ctrl(num, val)) {
+            return Some(response);
+        }
+
+        for lfo in &mut self.lfos {
+            let Some(response) = lfo.handle_ctrl(num, val) else {
                 continue;
             };
 
             return Some(response);
         }
 
-        None
+        let page = self.pages.as_ref().map_or(1, |p| p.current());
+        if let Some(mut response) = self.pages.as_mut().and_then(|p| p.handle_ctrl(num, val)) {
+            self.page_changed(page, &mut response);
+            return Some(response);
+        }
+
+        if let Some(response) = self.offsets.iter_mut().find_map(|o| o.handle_ctrl(num, val)) {
+            return Some(response);
+        }
+
+        if let Some(response) = self.vars.handle_ctrl(num, val) {
+            return Some(self.var_changed(response));
+        }
+
+        let now = determinism::now();
+        let (ctrls, pages) = (&self.ctrls, &self.pages);
+        if let Some(response) = self.loopers.iter_mut().find_map(|l| l.handle_ctrl(num, val, now, |name| value(ctrls, pages, name))) {
+            return Some(response);
+        }
+
+        if let Some(i) = self.scenes.ctrl_index(num) {
+            if val == 0x00 {
+                return Some(Response::new());
+            }
+            return Some(self.apply_scene(i));
+        }
+
+        if let Some(i) = self.group_actions.ctrl_index(num) {
+            if val == 0x00 {
+                return Some(Response::new());
+            }
+            return Some(self.run_group_action(i));
+        }
+
+        // encoders only follow the plugin while they have a parameter assigned
+        if let Some(response) = self.plugin.as_mut().and_then(|p| p.handle_ctrl(num, val)) {
+            return Some(response);
+        }
+
+        self.dispatch(false, |ctrl| ctrl.handle_ctrl(num, val))
     }
 
     pub fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
-        for ctrl in &mut self.ctrls {
-            let Some(response) = ctrl.handle_osc(msg) else {
-                continue;
-            };
+        let mut msg = msg.clone();
+        self.vars.incoming(&mut msg);
+        let msg = &msg;
+
+        let heard = self.heard();
+        let response = match self.bridge.as_ref().and_then(|b| b.osc_value(msg)) {
+            Some((name, val)) => self.bridged(&name, val, |r| r.osc.clear()),
+            None => self.route_osc(msg)
+        };
+        let response = self.throttled(response);
+        self.activity(merge(heard, response))
+    }
 
+    /// sets a mapping from a message on one side of the bridge, passing the result on to the other side.
+    fn bridged(&mut self, name: &str, val: u8, drop_echo: impl Fn(&mut Response)) -> Option<Response> {
+        let mut response = self.apply(name, val).into_iter().reduce(|mut a, b| {
+            a.extend(b);
+            a
+        })?;
+        drop_echo(&mut response);
+        Some(response)
+    }
+
+    fn route_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        if let Some(response) = self.clock.as_mut().and_then(|c| c.handle_osc(msg)) {
             return Some(response);
         }
 
-        None
+        let page = self.pages.as_ref().map_or(1, |p| p.current());
+        if let Some(mut response) = self.pages.as_mut().and_then(|p| p.handle_osc(msg)) {
+            self.page_changed(page, &mut response);
+            return Some(response);
+        }
+
+        if let Some(response) = self.offsets.iter_mut().find_map(|o| o.handle_osc(msg)) {
+            return Some(response);
+        }
+
+        if let Some(response) = self.vars.handle_osc(msg) {
+            return Some(self.var_changed(response));
+        }
+
+        if let Some(response) = self.failsafe.as_mut().and_then(|f| f.handle_osc(msg)) {
+            return Some(response);
+        }
+
+        let now = determinism::now();
+        let (ctrls, pages) = (&self.ctrls, &self.pages);
+        if let Some(response) = self.loopers.iter_mut().find_map(|l| l.handle_osc(msg, now, |name| value(ctrls, pages, name))) {
+            return Some(response);
+        }
+
+        if let Some(scene) = self.scenes.osc_index(msg) {
+            let Some(i) = scene else {
+                warn!("unknown scene: {:?}", msg.args);
+                return Some(Response::new());
+            };
+            return Some(self.apply_scene(i));
+        }
+
+        if let Some(i) = self.group_actions.osc_index(msg) {
+            return Some(self.run_group_action(i));
+        }
+
+        self.dispatch(true, |ctrl| ctrl.handle_osc(msg))
     }
 
-    pub fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
-        for ctrl in &mut self.ctrls {
-            let Some(response) = ctrl.handle_midi(msg) else {
+    /// answers a query about the current state, such as `/autocrap/get knob1`, or `None` if the message is not a query.
+    #[cfg(feature = "osc")]
+    pub fn query(&self, msg: &OscMessage) -> Option<Vec<OscResponse>> {
+        if msg.addr != OSC_GET_ADDR {
+            return None;
+        }
+
+        let mut replies = vec![];
+        for arg in &msg.args {
+            let OscType::String(name) = arg else {
+                warn!("{} expects mapping names, got {:?}", OSC_GET_ADDR, arg);
+                continue;
+            };
+            let Some(val) = osc_value(&self.ctrls, &self.pages, name) else {
+                warn!("{}: unknown mapping {}", OSC_GET_ADDR, name);
                 continue;
             };
+            replies.push(OscResponse {
+                addr: OSC_VALUE_ADDR.to_string(),
+                args: vec![OscType::String(name.clone()), OscType::Float(val)]
+            });
+        }
+        Some(replies)
+    }
 
-            return Some(response);
+    pub fn handle_plugin(&mut self, msg: &OscMessage) -> Option<Response> {
+        let heard = self.heard();
+        let response = merge(heard, self.plugin.as_mut().and_then(|p| p.handle_message(msg)));
+        self.activity(response)
+    }
+
+    pub fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        let mut msg = msg.to_vec();
+        for offset in &self.offsets {
+            offset.incoming(&mut msg);
+        }
+        let msg = &msg[..];
+
+        let heard = self.heard();
+        // also for transport buttons, which follow start and stop too
+        let transport = self.midi_transport.handle_midi(msg).and_then(|values| self.transport_leds(values));
+        let response = match self.bridge.as_ref().and_then(|b| b.midi_value(msg)) {
+            Some((name, val)) => self.bridged(&name, val, |r| r.midi.clear()),
+            None => match self.vars.handle_midi(msg) {
+                Some(response) => Some(self.var_changed(response)),
+                None => self.dispatch(true, |ctrl| ctrl.handle_midi(msg))
+            }
+        };
+        let response = self.throttled(merge(transport, response));
+        self.activity(merge(heard, response))
+    }
+
+    /// lights the LEDs of the mappings following the MIDI transport, leaving their state alone.
+    fn transport_leds(&mut self, values: Vec<(String, u8)>) -> Option<Response> {
+        if values.is_empty() {
+            return None;
         }
 
-        None
+        let mut response = Response::new();
+        for (name, val) in values {
+            for i in 0..self.ctrls.len() {
+                let active = self.ctrl_active(i);
+                let Some(leds) = self.ctrls[i].logic.led(&name, val) else {
+                    continue;
+                };
+                if active {
+                    response.ctrl.extend(leds);
+                }
+            }
+        }
+        Some(response)
+    }
+
+    /// which of the ctrls depending on the state of another ctrl are active.
+    fn conditions(&self) -> Vec<(usize, bool)> {
+        (0..self.ctrls.len())
+            .filter(|&i| matches!(self.ctrls[i].when, Some(When::Ctrl(..))))
+            .map(|i| (i, self.ctrl_active(i)))
+            .collect()
+    }
+
+    /// adds the LEDs of ctrls which have become active since `before`, as the ctrl they depend on changed.
+    fn conditions_changed(&mut self, before: Vec<(usize, bool)>, response: &mut Response) {
+        for (i, was_active) in before {
+            if !was_active && self.ctrl_active(i) {
+                response.ctrl.extend(self.ctrls[i].logic.leds());
+            }
+        }
+    }
+
+    /// adds the LEDs of ctrls which depend on a variable, as they may have become active.
+    fn var_changed(&mut self, mut response: Response) -> Response {
+        for i in 0..self.ctrls.len() {
+            if matches!(self.ctrls[i].when, Some(When::Var(_))) && self.ctrl_active(i) {
+                response.ctrl.extend(self.ctrls[i].logic.leds());
+            }
+        }
+        response
+    }
+
+    /// after switching away from page `from`: drops held back feedback, which belongs to the LEDs of the
+    /// page that was left, and draws the new page from the state its mappings kept. LEDs of the page left
+    /// which the new page doesn't draw are parked, if so configured.
+    fn page_changed(&mut self, from: u8, response: &mut Response) {
+        if self.pages.as_ref().is_none_or(|p| p.current() == from) {
+            return;
+        }
+        if let Some(throttle) = self.throttle.as_mut() {
+            throttle.clear();
+        }
+
+        // the redraw has the page LEDs too
+        response.ctrl.clear();
+        let redraw = self.redraw();
+        if let Some(park) = self.pages.as_ref().and_then(|p| p.park()) {
+            let drawn: HashSet<u8> = redraw.iter().flat_map(|r| &r.ctrl).filter(|c| !c.raw).filter_map(|c| c.data.first().copied()).collect();
+            let mut parked = HashSet::new();
+            for i in 0..self.ctrls.len() {
+                if self.ctrls[i].page != Some(from) {
+                    continue;
+                }
+                for led in self.ctrls[i].logic.leds().into_iter().filter(|l| !l.raw) {
+                    let Some(&num) = led.data.first() else {
+                        continue;
+                    };
+                    if !drawn.contains(&num) && parked.insert(num) {
+                        response.ctrl.push(CtrlResponse { data: vec![num, park], color: None, raw: false });
+                    }
+                }
+            }
+        }
+        redraw.into_iter().for_each(|r| response.extend(r));
+    }
+
+    /// holds back LED output of feedback which comes in faster than `max_rate`.
+    fn throttled(&mut self, mut response: Option<Response>) -> Option<Response> {
+        if let (Some(throttle), Some(response)) = (self.throttle.as_mut(), response.as_mut()) {
+            throttle.feedback(determinism::now(), response);
+        }
+        response
+    }
+
+    /// passes an event to the first ctrl that handles it and its links, or to all of them if so configured.
+    /// ctrls on the active page come first. feedback also reaches the other pages, so that their
+    /// state is up to date when switched to, but their LED output is dropped. ctrls whose `when` condition
+    /// starts to hold are redrawn.
+    fn dispatch(&mut self, feedback: bool, handle: impl FnMut(&mut Box<dyn CtrlLogic>) -> Option<Response>) -> Option<Response> {
+        let before = self.conditions();
+        let mut response = self.dispatch_to(feedback, handle)?;
+        self.conditions_changed(before, &mut response);
+        Some(response)
+    }
+
+    /// `dispatch`, without the redraw.
+    fn dispatch_to(&mut self, feedback: bool, mut handle: impl FnMut(&mut Box<dyn CtrlLogic>) -> Option<Response>) -> Option<Response> {
+        let mut result: Option<Response> = None;
+        for active_pass in [true, false] {
+            if !active_pass && !feedback {
+                break;
+            }
+
+            for i in 0..self.ctrls.len() {
+                if self.ctrl_active(i) != active_pass {
+                    continue;
+                }
+
+                let Some(response) = self.handle_one(i, active_pass, feedback, &mut handle) else {
+                    continue;
+                };
+
+                if self.on_conflict != ConflictPolicy::All {
+                    let mut response = response;
+                    let links = self.ctrls[i + 1..].iter().take_while(|c| c.linked).count();
+                    for j in i + 1..=i + links {
+                        response.extend(self.handle_one(j, active_pass, feedback, &mut handle).unwrap_or_else(Response::new));
+                    }
+                    return Some(response);
+                }
+
+                result.get_or_insert_with(Response::new).extend(response);
+            }
+        }
+
+        result
+    }
+
+    fn handle_one(
+        &mut self,
+        i: usize,
+        active: bool,
+        feedback: bool,
+        handle: &mut impl FnMut(&mut Box<dyn CtrlLogic>) -> Option<Response>
+    ) -> Option<Response> {
+        let mut response = handle(&mut self.ctrls[i].logic)?;
+        if !active {
+            response.ctrl.clear();
+        }
+        self.note_traffic(i, feedback, &mut response);
+        response.set_page(self.ctrls[i].page);
+        Some(response)
+    }
+
+    /// flashes the traffic LEDs for a message from the host to ctrl `i`, or for what it sends to the host.
+    fn note_traffic(&mut self, i: usize, incoming: bool, response: &mut Response) {
+        if !incoming && response.osc.is_empty() && response.midi.is_empty() {
+            return;
+        }
+        let leds = self.traffic.traffic(self.ctrls[i].name.as_deref(), incoming, determinism::now());
+        response.ctrl.extend(leds);
     }
 }
 
@@ -88,15 +779,68 @@ pub trait CtrlLogic: core::fmt::Debug + Send + Sync {
     fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response>;
     fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response>;
     fn handle_midi(&mut self, msg: &[u8]) -> Option<Response>;
+
+    fn init(&mut self) -> Vec<Response> {
+        vec![]
+    }
+
+    fn tick(&mut self, _now: Instant, _lfos: &[Lfo]) -> Option<Response> {
+        None
+    }
+
+    /// whether `tick` can ever do anything, so that the ticker can stay away when nothing does.
+    fn ticks(&self) -> bool {
+        false
+    }
+
+    /// sets the control called `name` to a value (0-127), as if it had been moved there.
+    fn apply(&mut self, _name: &str, _val: u8) -> Option<Response> {
+        None
+    }
+
+    /// LED output for the current state.
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        vec![]
+    }
+
+    /// LED output showing `val` (0-127) on the control called `name`, without changing its state, or `None` if
+    /// this is not that control.
+    fn led(&mut self, _name: &str, _val: u8) -> Option<Vec<CtrlResponse>> {
+        None
+    }
+
+    /// whether the control called `name` has the value `is`, or `None` if this is not that control.
+    fn is_in_state(&self, _name: &str, _is: u8) -> Option<bool> {
+        None
+    }
+
+    /// the current value (0-127) of the control called `name`, or `None` if this is not that control.
+    fn value(&self, _name: &str) -> Option<u8> {
+        None
+    }
+
+    /// the current value of the control called `name` as it is sent over OSC.
+    #[cfg(feature = "osc")]
+    fn osc_value(&self, name: &str) -> Option<f32> {
+        self.value(name).map(|val| val as f32 / 127.0)
+    }
 }
 
 #[derive(Debug)]
 pub struct OnOffLogic {
+    name: String,
     mode: OnOffMode,
     ctrl_in_num: Option<u8>,
     ctrl_out_num: Option<u8>,
     midi: Option<MidiSpec>,
+    midi_table: Option<ValueTable>,
     osc_addr: String,
+    initial: Option<u8>,
+    feedback_source: FeedbackSource,
+    /// how long a press lasts, whenever the button is released.
+    pulse: Option<Duration>,
+    /// when the current pulse ends.
+    pulse_until: Option<Instant>,
     state: bool
 }
 
@@ -112,57 +856,47 @@ impl OnOffLogic {
         }
 
         Response {
-            osc: Some(OscResponse {
+            osc: vec![OscResponse {
                 addr: self.osc_addr.clone(),
                 args: vec![OscType::Float(if new_state { 1.0 } else { 0.0 })]
-            }),
+            }],
             ctrl: self.ctrl_out_num.map(|num| CtrlResponse {
-                data: vec![num, if new_state { 0x7f } else { 0x00 }]
-            }),
-            midi: self.midi.map(|midi| {
-                let data = match midi.kind {
-                    MidiKind::Cc => {
-                        vec![
-                            0b10110000 | midi.channel,
-                            midi.num,
-                            if new_state { 0x7f } else { 0x00 }
-                        ]
-                    }
-                };
-                MidiResponse {
-                    data
-                }
-            })
+                data: vec![num, if new_state { 0x7f } else { 0x00 }],
+                color: None,
+                raw: false
+            }).into_iter().collect(),
+            midi: self.midi.map(|midi| MidiResponse {
+                data: midi.message(midi_out(&self.midi_table, if new_state { 0x7f } else { 0x00 })),
+                page: None
+            }).into_iter().collect(),
+            plugin: vec![],
+            received: None,
+            motor: vec![]
         }
     }
 }
 
-impl CtrlLogic for OnOffLogic {
-    fn from_mapping(mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
-        let CtrlKind::OnOff { mode } = mapping.ctrl_kind else {
-            return None;
-        };
-
-        Some(Box::new(OnOffLogic {
-            mode: mode,
-            ctrl_in_num: mapping.ctrl_in_num,
-            ctrl_out_num: mapping.ctrl_out_num,
-            midi: mapping.midi,
-            osc_addr: mapping.osc_addr(),
-            state: false
-        }))
-    }
-
-    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
-        let Some(ctrl_in_num) = self.ctrl_in_num else {
-            return None;
-        };
+impl OnOffLogic {
+    /// LED output for a state received from the host.
+    fn feedback(&mut self, new_state: bool) -> Response {
+        // the LED belongs to the MIDI transport
+        if self.feedback_source.is_midi_transport() {
+            return Response::new();
+        }
 
-        if num != ctrl_in_num {
-            return None;
+        // with external feedback, the LED was not lit on press, so it must follow even if the state is unchanged
+        let remember = self.feedback_source == FeedbackSource::Internal;
+        if !remember {
+            self.state = new_state;
         }
 
-        let pressed = val != 0x00;
+        let mut response = Response::new();
+        response.ctrl = self.update(new_state, remember).ctrl;
+        response
+    }
+
+    /// output for the button being pressed or released.
+    fn press(&mut self, pressed: bool) -> Response {
         let mut new_state = self.state;
         let mut send_ctrl = true;
         let mut send_osc = true;
@@ -188,67 +922,130 @@ impl CtrlLogic for OnOffLogic {
 
         let mut response = self.update(new_state, remember);
 
-        if !send_ctrl {
-            response.ctrl = None;
+        if !send_ctrl || self.feedback_source != FeedbackSource::Internal {
+            response.ctrl.clear();
         }
 
         if !send_osc {
-            response.osc = None;
+            response.osc.clear();
         }
 
-        Some(response)
+        response
     }
+}
 
-    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
-        let Some(_num) = self.ctrl_out_num else {
+impl CtrlLogic for OnOffLogic {
+    fn from_mapping(mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        let CtrlKind::OnOff { mode } = mapping.ctrl_kind else {
             return None;
         };
 
-        if msg.addr != self.osc_addr {
+        Some(Box::new(OnOffLogic {
+            name: mapping.name.clone(),
+            mode,
+            ctrl_in_num: mapping.ctrl_in_num,
+            ctrl_out_num: mapping.ctrl_out_num,
+            midi: mapping.midi,
+            midi_table: mapping.midi_table.clone(),
+            osc_addr: mapping.osc_addr(),
+            initial: mapping.initial,
+            feedback_source: mapping.feedback_source,
+            pulse: mapping.pulse_ms.map(Duration::from_millis),
+            pulse_until: None,
+            state: mapping.initial.is_some_and(|val| val != 0x00)
+        }))
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let ctrl_in_num = self.ctrl_in_num?;
+
+        if num != ctrl_in_num {
             return None;
         }
 
-        if msg.args.len() < 1 {
-            return None;
+        let pressed = val != 0x00;
+        if let (Some(pulse), OnOffMode::Momentary | OnOffMode::Raw) = (self.pulse, self.mode) {
+            // the release comes from tick instead
+            if !pressed {
+                return Some(Response::new());
+            }
+            self.pulse_until = Some(determinism::now() + pulse);
         }
+        Some(self.press(pressed))
+    }
+
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        self.ctrl_out_num?;
 
-        let OscType::Float(val) = msg.args[0] else {
+        if msg.addr != self.osc_addr {
             return None;
-        };
+        }
 
-        let mut response = Response::new();
-        response.ctrl = self.update(val != 0.0, true).ctrl;
-        Some(response)
+        let on = osc_on(msg.args.first()?)?;
+
+        Some(self.feedback(on))
     }
 
     fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
-        let Some(_num) = self.ctrl_out_num else {
-            return None;
-        };
+        self.ctrl_out_num?;
 
-        let Some(midi_spec) = self.midi else {
-            return None;
-        };
+        let val = midi_in(&self.midi_table, self.midi?.value(msg)?);
+
+        Some(self.feedback(val != 0))
+    }
+
+    fn init(&mut self) -> Vec<Response> {
+        if self.initial.is_none() {
+            return vec![];
+        }
+
+        vec![self.update(self.state, false)]
+    }
 
-        if msg.len() != 3 {
+    fn tick(&mut self, now: Instant, _lfos: &[Lfo]) -> Option<Response> {
+        if self.pulse_until.is_none_or(|until| now < until) {
             return None;
         }
 
-        let status = msg[0];
-        let num = msg[1];
-        let val = msg[2];
+        self.pulse_until = None;
+        Some(self.press(false))
+    }
+
+    fn ticks(&self) -> bool {
+        self.pulse.is_some()
+    }
 
-        if status != 0b10110000 | midi_spec.channel {
+    fn apply(&mut self, name: &str, val: u8) -> Option<Response> {
+        if name != self.name {
             return None;
         }
 
-        if num != midi_spec.num {
+        self.state = val != 0x00;
+        Some(self.update(self.state, false))
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        if let OnOffMode::Raw = self.mode {
+            return vec![];
+        }
+
+        self.update(self.state, false).ctrl
+    }
+
+    fn led(&mut self, name: &str, val: u8) -> Option<Vec<CtrlResponse>> {
+        if name != self.name {
             return None;
         }
 
-        let mut response = Response::new();
-        response.ctrl = self.update(val != 0, true).ctrl;
-        Some(response)
+        Some(self.update(val != 0x00, false).ctrl)
+    }
+
+    fn is_in_state(&self, name: &str, is: u8) -> Option<bool> {
+        (name == self.name).then_some(self.state == (is != 0x00))
+    }
+
+    fn value(&self, name: &str) -> Option<u8> {
+        (name == self.name).then_some(if self.state { 0x7f } else { 0x00 })
     }
 }
 
@@ -256,9 +1053,25 @@ impl CtrlLogic for OnOffLogic {
 pub struct EightBitLogic {
     ctrl_in_hi_num: u8,
     ctrl_in_lo_num: u8,
+    ctrl_motor_num: Option<u8>,
     midi: Option<MidiSpec>,
+    midi_table: Option<ValueTable>,
     osc_addr: String,
-    state: [u8;2]
+    state: [u8;2],
+    /// when the fader was last moved by hand, so that the motor leaves it alone for a moment.
+    moved: Option<Instant>
+}
+
+impl EightBitLogic {
+    /// moves the motor to a position from the host, unless the fader is in someone's hand.
+    fn motor(&self, position: u16) -> Response {
+        let mut response = Response::new();
+        if self.moved.is_some_and(|moved| moved.elapsed() < MOTOR_HOLD) {
+            return response;
+        }
+        response.motor = self.ctrl_motor_num.map(|num| MotorResponse { num, position }).into_iter().collect();
+        response
+    }
 }
 
 impl CtrlLogic for EightBitLogic {
@@ -267,16 +1080,19 @@ impl CtrlLogic for EightBitLogic {
             return None;
         };
 
-        let Some(ref ctrl_in_sequence) = mapping.ctrl_in_sequence else {
+        let &[ctrl_in_hi_num, ctrl_in_lo_num] = mapping.ctrl_in_sequence.as_deref()? else {
             return None;
         };
 
         Some(Box::new(EightBitLogic {
-            ctrl_in_hi_num: ctrl_in_sequence[0],
-            ctrl_in_lo_num: ctrl_in_sequence[1],
+            ctrl_in_hi_num,
+            ctrl_in_lo_num,
+            ctrl_motor_num: mapping.ctrl_motor_num,
             midi: mapping.midi,
+            midi_table: mapping.midi_table.clone(),
             osc_addr: format!("/{}", mapping.name),
-            state: [0x00,0x00]
+            state: [0x00,0x00],
+            moved: None
         }))
     }
 
@@ -288,91 +1104,297 @@ impl CtrlLogic for EightBitLogic {
 
         if num == self.ctrl_in_lo_num {
             self.state[1] = val;
+            self.moved = Some(determinism::now());
             let val8 = self.state[0] << 1 | (if self.state[1] != 0x00 { 1 } else { 0 });
             return Some(Response {
-                ctrl: None,
-                osc: Some(OscResponse {
+                ctrl: vec![],
+                osc: vec![OscResponse {
                     addr: self.osc_addr.clone(),
                     args: vec![OscType::Float(val8 as f32 / 255.0)]
-                }),
-                midi: self.midi.map(|midi| {
-                    let data = match midi.kind {
-                        MidiKind::Cc => {
-                            vec![
-                                0b10110000 | midi.channel,
-                                midi.num,
-                                val8 >> 1
-                            ]
-                        }
-                    };
-                    MidiResponse {
-                        data
-                    }
-                })
+                }],
+                midi: self.midi.map(|midi| MidiResponse {
+                    data: midi.message(midi_out(&self.midi_table, val8 >> 1)),
+                    page: None
+                }).into_iter().collect(),
+                plugin: vec![],
+                received: None,
+                motor: vec![]
             })
         }
 
         None
     }
 
-    fn handle_osc(&mut self, _msg: &OscMessage) -> Option<Response> {
-        None
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        self.ctrl_motor_num?;
+
+        if msg.addr != self.osc_addr {
+            return None;
+        }
+
+        let val = osc_number(msg.args.first()?)?.clamp(0.0, 1.0);
+        Some(self.motor((val * MOTOR_MAX as f32).round() as u16))
     }
 
-    fn handle_midi(&mut self, _msg: &[u8]) -> Option<Response> {
-        None
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        self.ctrl_motor_num?;
+
+        let val = midi_in(&self.midi_table, self.midi?.value(msg)?);
+        Some(self.motor((val as u16) << 7 | val as u16))
     }
 }
 
+/// the highest motor position.
+const MOTOR_MAX: u16 = 0x3fff;
+/// how long after being moved by hand a fader ignores positions from the host.
+const MOTOR_HOLD: Duration = Duration::from_millis(500);
+
+const CENTER: u8 = 0x40;
+
+/// asks for the values of the mappings named in the arguments.
+#[cfg(feature = "osc")]
+const OSC_GET_ADDR: &str = "/autocrap/get";
+/// the answer to a get: the name of a mapping and its value (0-1).
+#[cfg(feature = "osc")]
+const OSC_VALUE_ADDR: &str = "/autocrap/value";
+
+/// LED ring display mode which lights the ring from the center outwards.
+const RING_MODE_CENTER: u8 = 0x20;
+
+const LIMIT_BLINK_DURATION: Duration = Duration::from_millis(400);
+const LIMIT_BLINK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// how long a `Raw` encoder has to rest for its speed to drop to 0.
+const SPEED_REST: Duration = Duration::from_millis(150);
+/// time constant of the smoothing of encoder speeds.
+const SPEED_SMOOTHING: Duration = Duration::from_millis(100);
+
+/// how many values sent by a `follow_host` encoder are remembered while waiting for the host to echo them.
+const MAX_IN_FLIGHT: usize = 16;
+
 #[derive(Debug)]
 pub struct RelativeLogic {
+    name: String,
     mode: RelativeMode,
+    detent: u8,
+    wrap: bool,
+    follow_host: bool,
+    /// values sent to the host which it has not echoed back yet.
+    in_flight: VecDeque<u8>,
     ctrl_in_num: Option<u8>,
     ctrl_out_num: Option<u8>,
+    ctrl_mode_num: Option<u8>,
     midi: Option<MidiSpec>,
+    midi_table: Option<ValueTable>,
     osc_addr: String,
-    state: u8
+    lfo: Option<String>,
+    steps: Option<u8>,
+    stages: Vec<Stage>,
+    min: u8,
+    max: u8,
+    limit_notify: Option<LimitNotify>,
+    initial: Option<u8>,
+    feedback_source: FeedbackSource,
+    state: u8,
+    modulation: f32,
+    blink_until: Option<Instant>,
+    blink_lit: bool,
+    /// how fast a `Raw` encoder is being turned, in steps per second, smoothed.
+    speed: f32,
+    last_turn: Option<Instant>
 }
 
 impl RelativeLogic {
     fn update(&mut self, new_state: u8) -> Response {
-        let changed = new_state != self.state;
-        let new_encoder_led_val = Self::encoder_led_val(new_state);
-        let encoder_led_val_changed = new_encoder_led_val != Self::encoder_led_val(self.state);
+        self.set(new_state, self.modulation)
+    }
+
+    fn set(&mut self, new_state: u8, new_modulation: f32) -> Response {
+        let old_val = self.output_val();
         self.state = new_state;
+        self.modulation = new_modulation;
+        let new_val = self.output_val();
 
-        if !changed {
+        if new_val == old_val {
             return Response::new();
         }
 
-        let ctrl = if encoder_led_val_changed {
-            self.ctrl_out_num.map(|num| CtrlResponse {
-                data: vec![num, self.state]
-            })
-        } else {
-            None
-        };
+        let mut response = self.value_response(new_val);
+        if Self::encoder_led_val(new_val) == Self::encoder_led_val(old_val) {
+            response.ctrl.clear();
+        }
+        response
+    }
+
+    /// LED output for a value received from the host.
+    fn feedback(&mut self, val: u8) -> Response {
+        if self.follow_host {
+            // an echo of something we sent lags behind the turns made since. anything else was changed on the host
+            if let Some(i) = self.in_flight.iter().position(|&v| v == val) {
+                self.in_flight.drain(..=i);
+                let mut response = Response::new();
+                if self.feedback_source == FeedbackSource::External {
+                    response.ctrl = self.value_response(val).ctrl;
+                }
+                return response;
+            }
+            self.in_flight.clear();
+        }
+
+        let mut response = Response::new();
+        response.ctrl = self.update(self.state_for_val(val)).ctrl;
+        // with external feedback, the LED was not updated on turn, so it must follow even if the value is unchanged
+        if self.feedback_source == FeedbackSource::External {
+            response.ctrl = self.value_response(self.output_val()).ctrl;
+        }
+        response
+    }
+
+    /// a value as it is sent over OSC.
+    fn osc_val(&self, val: u8) -> f32 {
+        match self.mode {
+            RelativeMode::Bipolar => bipolar_from_7bit(val),
+            _ => val as f32 / 127.0
+        }
+    }
+
+    /// LED, OSC and MIDI output for a value.
+    fn value_response(&self, val: u8) -> Response {
+        let osc_val = self.osc_val(val);
 
         Response {
-            ctrl,
-            osc: Some(OscResponse {
+            ctrl: self.ctrl_out_num.map(|num| CtrlResponse {
+                data: vec![num, val],
+                color: None,
+                raw: false
+            }).into_iter().collect(),
+            osc: vec![OscResponse {
                 addr: self.osc_addr.clone(),
-                args: vec![OscType::Float(self.state as f32 / 127.0)]
-            }),
-            midi: self.midi.map(|midi| {
-                let data = match midi.kind {
-                    MidiKind::Cc => {
-                        vec![
-                            0b10110000 | midi.channel,
-                            midi.num,
-                            self.state
-                        ]
-                    }
-                };
-                MidiResponse {
-                    data
-                }
-            })
+                args: vec![OscType::Float(osc_val)]
+            }],
+            midi: self.midi.map(|midi| MidiResponse {
+                data: midi.message(midi_out(&self.midi_table, val)),
+                page: None
+            }).into_iter().collect(),
+            plugin: vec![],
+            received: None,
+            motor: vec![]
+        }
+    }
+
+    /// the state with the stages applied, by default detent, lfo modulation, quantization and limits.
+    fn output_val(&self) -> u8 {
+        let val = self.stages.iter().fold(self.state as f32, |val, stage| match stage {
+            Stage::Detent => self.detent_val(val),
+            Stage::Lfo => (val + self.modulation * 127.0).clamp(0.0, 127.0),
+            Stage::Steps => match self.steps {
+                Some(steps) if steps >= 2 => {
+                    let max_step = (steps - 1) as f32;
+                    (val * max_step / 127.0).round() * 127.0 / max_step
+                },
+                _ => val
+            },
+            Stage::Limits => val.clamp(self.min as f32, self.max as f32),
+        });
+        val.clamp(0.0, 127.0).round() as u8
+    }
+
+    /// called when the control is turned further while already at a limit.
+    fn notify_limit(&mut self, at_max: bool) -> Response {
+        match self.limit_notify {
+            Some(LimitNotify::Osc) => OscResponse {
+                addr: format!("{}/limit", self.osc_addr),
+                args: vec![OscType::Float(if at_max { 1.0 } else { 0.0 })]
+            }.into(),
+            Some(LimitNotify::Blink) => {
+                self.blink_until = Some(determinism::now() + LIMIT_BLINK_DURATION);
+                Response::new()
+            },
+            None => Response::new()
+        }
+    }
+
+    /// LED feedback for an ongoing limit blink, if it needs to change.
+    fn blink(&mut self, now: Instant) -> Option<CtrlResponse> {
+        let num = self.ctrl_out_num?;
+        let until = self.blink_until?;
+
+        let lit = if now >= until {
+            self.blink_until = None;
+            true
+        } else {
+            let remaining = until.duration_since(now).as_millis();
+            remaining.div_ceil(LIMIT_BLINK_INTERVAL.as_millis()) % 2 == 1
+        };
+
+        if lit == self.blink_lit {
+            return None;
+        }
+
+        self.blink_lit = lit;
+        Some(CtrlResponse {
+            data: vec![num, if lit { self.output_val() } else { 0x00 }],
+            color: None,
+            raw: false
+        })
+    }
+
+    /// in bipolar mode, states within `detent` steps of the center snap to it.
+    /// the rest of the range is stretched so that the extremes are still reachable.
+    fn detent_val(&self, state: f32) -> f32 {
+        let RelativeMode::Bipolar = self.mode else {
+            return state;
+        };
+
+        let center = CENTER as f32;
+        let detent = self.detent as f32;
+        let offset = state - center;
+        if offset.abs() <= detent {
+            center
+        } else if offset > 0.0 {
+            center + (offset - detent) * (127.0 - center) / (127.0 - center - detent)
+        } else {
+            center + (offset + detent) * center / (center - detent)
+        }
+    }
+
+    /// inverse of `detent_val`, for values received as feedback.
+    fn state_for_val(&self, val: u8) -> u8 {
+        let RelativeMode::Bipolar = self.mode else {
+            return val;
+        };
+
+        let center = CENTER as f32;
+        let detent = self.detent as f32;
+        let offset = val as f32 - center;
+        let state = if offset == 0.0 {
+            center
+        } else if offset > 0.0 {
+            center + detent + offset * (127.0 - center - detent) / (127.0 - center)
+        } else {
+            center - detent + offset * (center - detent) / center
+        };
+        state.round().clamp(0.0, 127.0) as u8
+    }
+
+    /// updates the speed of a `Raw` encoder with a turn.
+    fn turned(&mut self, delta: i8, now: Instant) -> OscResponse {
+        // the first step after resting counts as if it came after a rest
+        let dt = self.last_turn
+            .map_or(SPEED_REST, |t| now.saturating_duration_since(t).min(SPEED_REST))
+            .as_secs_f32()
+            .max(0.001);
+        self.last_turn = Some(now);
+
+        let alpha = 1.0 - (-dt / SPEED_SMOOTHING.as_secs_f32()).exp();
+        self.speed += alpha * (delta as f32 / dt - self.speed);
+        self.speed_response()
+    }
+
+    fn speed_response(&self) -> OscResponse {
+        OscResponse {
+            addr: format!("{}/speed", self.osc_addr),
+            args: vec![OscType::Float(self.speed)]
         }
     }
 
@@ -387,103 +1409,230 @@ impl RelativeLogic {
 
 impl CtrlLogic for RelativeLogic {
     fn from_mapping(mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
-        let CtrlKind::Relative { mode } = mapping.ctrl_kind else {
+        let CtrlKind::Relative { mode, detent, wrap, follow_host } = mapping.ctrl_kind else {
             return None;
         };
 
-        Some(Box::new(RelativeLogic {
-            mode: mode,
+        let min = mapping.min.unwrap_or(0).min(127);
+        let max = mapping.max.unwrap_or(127).clamp(min, 127);
+        let state = match mode {
+            RelativeMode::Bipolar => CENTER,
+            _ => 0x00
+        }.clamp(min, max);
+
+        let mut logic = Box::new(RelativeLogic {
+            name: mapping.name.clone(),
+            mode,
+            detent: detent.min(CENTER / 2),
+            wrap,
+            follow_host,
+            in_flight: VecDeque::new(),
             ctrl_in_num: mapping.ctrl_in_num,
             ctrl_out_num: mapping.ctrl_out_num,
+            ctrl_mode_num: mapping.ctrl_mode_num,
             midi: mapping.midi,
+            midi_table: mapping.midi_table.clone(),
             osc_addr: mapping.osc_addr(),
-            state: 0x00
-        }))
+            lfo: mapping.lfo.clone(),
+            steps: mapping.steps,
+            stages: mapping.stages.clone(),
+            min,
+            max,
+            limit_notify: mapping.limit_notify,
+            initial: mapping.initial,
+            feedback_source: mapping.feedback_source,
+            state,
+            modulation: 0.0,
+            blink_until: None,
+            blink_lit: true,
+            speed: 0.0,
+            last_turn: None
+        });
+        if let Some(val) = mapping.initial {
+            logic.state = logic.state_for_val(val.min(127)).clamp(min, max);
+        }
+        Some(logic)
     }
 
     fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
-        let Some(ctrl_in_num) = self.ctrl_in_num else {
-            return None;
-        };
+        let ctrl_in_num = self.ctrl_in_num?;
 
         if num != ctrl_in_num {
             return None;
         }
 
         let delta: i8 = if val < 0x40 { val as i8 } else { val as i8 + i8::MIN };
-        let response = match self.mode {
+        let mut response = match self.mode {
             RelativeMode::Raw => {
-                OscResponse {
+                let mut response: Response = OscResponse {
                     addr: self.osc_addr.clone(),
                     args: vec![OscType::Float(delta as f32)]
-                }.into()
+                }.into();
+                response.osc.push(self.turned(delta, determinism::now()));
+                response
             },
-            RelativeMode::Accumulate => {
-                self.update(self.state.saturating_add_signed(delta).min(127))
+            RelativeMode::Accumulate | RelativeMode::Bipolar => {
+                let min = self.min as i16;
+                let max = self.max as i16;
+                let target = self.state as i16 + delta as i16;
+                let old_val = self.output_val();
+                let response = if self.wrap {
+                    self.update(((target - min).rem_euclid(max - min + 1) + min) as u8)
+                } else if target != target.clamp(min, max) && self.state as i16 == target.clamp(min, max) {
+                    self.notify_limit(target > max)
+                } else {
+                    self.update(target.clamp(min, max) as u8)
+                };
+                if self.follow_host && self.output_val() != old_val {
+                    if self.in_flight.len() == MAX_IN_FLIGHT {
+                        self.in_flight.pop_front();
+                    }
+                    self.in_flight.push_back(self.output_val());
+                }
+                response
             }
         };
 
+        if self.feedback_source == FeedbackSource::External {
+            response.ctrl.clear();
+        }
+
         Some(response)
     }
 
     fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
-        let Some(_num) = self.ctrl_out_num else {
-            return None;
-        };
+        self.ctrl_out_num?;
 
         if msg.addr != self.osc_addr {
             return None;
         }
 
-        if msg.args.len() < 1 {
-            return None;
+        let new_val = osc_7bit(msg.args.first()?, matches!(self.mode, RelativeMode::Bipolar))?;
+
+        // the host echoing our own modulated output must not move the base value
+        if self.modulation != 0.0 && new_val == self.output_val() {
+            return Some(Response::new());
         }
 
-        let OscType::Float(val) = msg.args[0] else {
-            return None;
-        };
+        Some(self.feedback(new_val))
+    }
 
-        let new_state = float_to_7bit(val);
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        self.ctrl_out_num?;
 
-        let mut response = Response::new();
-        response.ctrl = self.update(new_state).ctrl;
-        Some(response)
+        let val = midi_in(&self.midi_table, self.midi?.value(msg)?);
+
+        if self.modulation != 0.0 && val == self.output_val() {
+            return Some(Response::new());
+        }
+
+        Some(self.feedback(val))
     }
 
-    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
-        let Some(_num) = self.ctrl_out_num else {
-            return None;
-        };
+    fn init(&mut self) -> Vec<Response> {
+        let mut responses = vec![];
+        if let (RelativeMode::Bipolar, Some(num)) = (self.mode, self.ctrl_mode_num) {
+            responses.push(CtrlResponse {
+                data: vec![num, RING_MODE_CENTER],
+                color: None,
+                raw: false
+            }.into());
+        }
+        if self.initial.is_some() && !matches!(self.mode, RelativeMode::Raw) {
+            responses.push(self.value_response(self.output_val()));
+        } else if let (RelativeMode::Bipolar, Some(num)) = (self.mode, self.ctrl_out_num) {
+            responses.push(CtrlResponse {
+                data: vec![num, self.output_val()],
+                color: None,
+                raw: false
+            }.into());
+        }
+        responses
+    }
 
-        let Some(midi_spec) = self.midi else {
-            return None;
+    fn tick(&mut self, now: Instant, lfos: &[Lfo]) -> Option<Response> {
+        let lfo = self.lfo.as_ref().and_then(|name| lfos.iter().find(|l| l.name() == name));
+
+        let mut response = match lfo {
+            Some(lfo) => self.set(self.state, lfo.value()),
+            None => Response::new()
         };
 
-        if msg.len() != 3 {
-            return None;
+        if self.blink_until.is_some() {
+            response.ctrl = self.blink(now).into_iter().collect();
         }
 
-        let status = msg[0];
-        let num = msg[1];
-        let val = msg[2];
+        if self.last_turn.is_some_and(|t| now.saturating_duration_since(t) >= SPEED_REST) {
+            self.last_turn = None;
+            self.speed = 0.0;
+            response.osc.push(self.speed_response());
+        }
 
-        if status != 0b10110000 | midi_spec.channel {
+        if response.is_empty() {
             return None;
         }
 
-        if num != midi_spec.num {
+        Some(response)
+    }
+
+    fn ticks(&self) -> bool {
+        self.lfo.is_some()
+            || matches!(self.limit_notify, Some(LimitNotify::Blink))
+            || matches!(self.mode, RelativeMode::Raw)
+    }
+
+    fn apply(&mut self, name: &str, val: u8) -> Option<Response> {
+        if name != self.name || matches!(self.mode, RelativeMode::Raw) {
             return None;
         }
 
-        let mut response = Response::new();
-        response.ctrl = self.update(val).ctrl;
-        Some(response)
+        self.state = self.state_for_val(val.min(127)).clamp(self.min, self.max);
+        Some(self.value_response(self.output_val()))
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        let mut leds = vec![];
+        if let (RelativeMode::Bipolar, Some(num)) = (self.mode, self.ctrl_mode_num) {
+            leds.push(CtrlResponse {
+                data: vec![num, RING_MODE_CENTER],
+                color: None,
+                raw: false
+            });
+        }
+        if !matches!(self.mode, RelativeMode::Raw) {
+            leds.extend(self.value_response(self.output_val()).ctrl);
+        }
+        leds
+    }
+
+    fn is_in_state(&self, name: &str, is: u8) -> Option<bool> {
+        (name == self.name).then_some(self.output_val() == is)
+    }
+
+    fn value(&self, name: &str) -> Option<u8> {
+        (name == self.name).then(|| self.output_val())
+    }
+
+    #[cfg(feature = "osc")]
+    fn osc_value(&self, name: &str) -> Option<f32> {
+        (name == self.name).then(|| self.osc_val(self.output_val()))
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct CtrlResponse {
-    pub data: Vec<u8>
+    pub data: Vec<u8>,
+    /// what color the LED in `data` should be, on devices which can show colors.
+    pub color: Option<[u8; 3]>,
+    /// `data` goes to the device exactly as it is, without the codec or the feedback prefix.
+    pub raw: bool
+}
+
+/// moves a motorized fader on the device to a position (0-16383).
+#[derive(Clone, Copy, Debug)]
+pub struct MotorResponse {
+    pub num: u8,
+    pub position: u16
 }
 
 #[derive(Debug)]
@@ -494,56 +1643,175 @@ pub struct OscResponse {
 
 #[derive(Debug)]
 pub struct MidiResponse {
-    pub data: Vec<u8>
+    pub data: Vec<u8>,
+    /// the page of the mapping which sent this, for routing to per-page ports.
+    pub page: Option<u8>
 }
 
 #[derive(Debug)]
 pub struct Response {
-    pub ctrl: Option<CtrlResponse>,
-    pub osc: Option<OscResponse>,
-    pub midi: Option<MidiResponse>
+    pub ctrl: Vec<CtrlResponse>,
+    pub osc: Vec<OscResponse>,
+    pub midi: Vec<MidiResponse>,
+    /// messages for the companion plugin.
+    pub plugin: Vec<OscResponse>,
+    /// when the event this answers entered autocrap, if it answers one.
+    pub received: Option<Instant>,
+    /// fader positions for the device, which unlike `ctrl` move something physically.
+    pub motor: Vec<MotorResponse>
 }
 
 impl Response {
     pub fn new() -> Response {
         Response {
-            ctrl: None,
-            osc: None,
-            midi: None
+            ctrl: vec![],
+            osc: vec![],
+            midi: vec![],
+            plugin: vec![],
+            received: None,
+            motor: vec![]
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ctrl.is_empty() && self.osc.is_empty() && self.midi.is_empty() && self.plugin.is_empty() && self.motor.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Response) {
+        self.ctrl.extend(other.ctrl);
+        self.osc.extend(other.osc);
+        self.midi.extend(other.midi);
+        self.plugin.extend(other.plugin);
+        self.received = self.received.or(other.received);
+        self.motor.extend(other.motor);
+    }
+
+    fn set_page(&mut self, page: Option<u8>) {
+        for midi in &mut self.midi {
+            midi.page = page;
         }
     }
 }
 
-impl Into<Response> for CtrlResponse {
-    fn into(self) -> Response {
+impl From<CtrlResponse> for Response {
+    fn from(ctrl: CtrlResponse) -> Response {
         Response {
-            ctrl: Some(self),
-            osc: None,
-            midi: None
+            ctrl: vec![ctrl],
+            osc: vec![],
+            midi: vec![],
+            plugin: vec![],
+            received: None,
+            motor: vec![]
         }
     }
 }
 
-impl Into<Response> for OscResponse {
-    fn into(self) -> Response {
+impl From<OscResponse> for Response {
+    fn from(osc: OscResponse) -> Response {
         Response {
-            ctrl: None,
-            osc: Some(self),
-            midi: None
+            ctrl: vec![],
+            osc: vec![osc],
+            midi: vec![],
+            plugin: vec![],
+            received: None,
+            motor: vec![]
         }
     }
 }
 
-impl Into<Response> for MidiResponse {
-    fn into(self) -> Response {
+impl From<MidiResponse> for Response {
+    fn from(midi: MidiResponse) -> Response {
         Response {
-            ctrl: None,
-            osc: None,
-            midi: Some(self)
+            ctrl: vec![],
+            osc: vec![],
+            midi: vec![midi],
+            plugin: vec![],
+            received: None,
+            motor: vec![]
+        }
+    }
+}
+
+/// the value of the mapping called `name`. a page-local mapping's copy on the active page comes first.
+fn value(ctrls: &[Ctrl], pages: &Option<Pages>, name: &str) -> Option<u8> {
+    let on_page = |ctrl: &&Ctrl| pages.as_ref().is_none_or(|p| p.is_active(ctrl.page));
+    ctrls.iter().filter(on_page).chain(ctrls.iter()).find_map(|ctrl| ctrl.logic.value(name))
+}
+
+/// like `value`, as it is sent over OSC.
+#[cfg(feature = "osc")]
+fn osc_value(ctrls: &[Ctrl], pages: &Option<Pages>, name: &str) -> Option<f32> {
+    let on_page = |ctrl: &&Ctrl| pages.as_ref().is_none_or(|p| p.is_active(ctrl.page));
+    ctrls.iter().filter(on_page).chain(ctrls.iter()).find_map(|ctrl| ctrl.logic.osc_value(name))
+}
+
+/// combines two optional responses, keeping `a`'s messages first.
+fn merge(a: Option<Response>, b: Option<Response>) -> Option<Response> {
+    match (a, b) {
+        (Some(mut a), Some(b)) => {
+            a.extend(b);
+            Some(a)
+        },
+        (a, b) => a.or(b)
+    }
+}
+
+/// a number from an OSC argument of any numeric type, or a bool as 0 or 1.
+pub fn osc_number(arg: &OscType) -> Option<f32> {
+    match *arg {
+        OscType::Float(val) => Some(val),
+        OscType::Double(val) => Some(val as f32),
+        OscType::Int(val) => Some(val as f32),
+        OscType::Long(val) => Some(val as f32),
+        OscType::Bool(val) => Some(if val { 1.0 } else { 0.0 }),
+        _ => None
+    }
+}
+
+/// whether an OSC argument means on. any number other than 0 does.
+pub fn osc_on(arg: &OscType) -> Option<bool> {
+    osc_number(arg).map(|val| val != 0.0)
+}
+
+/// an OSC argument as a 7 bit value. floats are 0 to 1, or -1 to 1 if `bipolar`, while ints are taken
+/// to be 0 to 127 already, as senders of ints usually mean MIDI-style values. bools are 0 or 127.
+pub fn osc_7bit(arg: &OscType, bipolar: bool) -> Option<u8> {
+    match *arg {
+        OscType::Int(val) => Some(val.clamp(0, 127) as u8),
+        OscType::Long(val) => Some(val.clamp(0, 127) as u8),
+        OscType::Bool(val) => Some(if val { 127 } else { 0 }),
+        _ => {
+            let val = osc_number(arg)?;
+            Some(if bipolar { bipolar_to_7bit(val) } else { float_to_7bit(val) })
         }
     }
 }
 
-fn float_to_7bit(val: f32) -> u8 {
-    (val.max(0.0).min(1.0) * 127.0).round() as u8
+/// the value to send over MIDI, looked up in the mapping's table if it has one.
+fn midi_out(table: &Option<ValueTable>, val: u8) -> u8 {
+    table.as_ref().map_or(val, |t| t.output(val))
+}
+
+/// the value for MIDI received from the host, looked up in reverse in the mapping's table if it has one.
+fn midi_in(table: &Option<ValueTable>, val: u8) -> u8 {
+    table.as_ref().map_or(val, |t| t.input(val))
+}
+
+pub fn float_to_7bit(val: f32) -> u8 {
+    (val.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+/// maps -1..1 to 0..127, with 0 at the center value 64.
+pub fn bipolar_to_7bit(val: f32) -> u8 {
+    let val = val.clamp(-1.0, 1.0);
+    let center = CENTER as f32;
+    let range = if val < 0.0 { center } else { 127.0 - center };
+    (center + val * range).round() as u8
+}
+
+fn bipolar_from_7bit(val: u8) -> f32 {
+    let center = CENTER as f32;
+    let offset = val as f32 - center;
+    let range = if offset < 0.0 { center } else { 127.0 - center };
+    offset / range
 }
@@ -0,0 +1,108 @@
+use std::time::Instant;
+
+use rosc::OscMessage;
+
+use super::{
+    config::Mapping,
+    interpreter::{CtrlLogic, CtrlResponse, Response},
+    lfo::Lfo
+};
+
+/// wraps a mapping whose LED is lit by 0, turning the values for its LED upside down on the way out.
+/// MIDI and OSC values are left alone.
+#[derive(Debug)]
+pub struct InvertLogic {
+    inner: Box<dyn CtrlLogic>,
+    ctrl_out_num: u8,
+}
+
+impl InvertLogic {
+    /// wraps `inner`, or returns it as is if the mapping has no LED.
+    pub fn wrap(inner: Box<dyn CtrlLogic>, mapping: &Mapping) -> Box<dyn CtrlLogic> {
+        let Some(ctrl_out_num) = mapping.ctrl_out_num else {
+            return inner;
+        };
+
+        Box::new(InvertLogic { inner, ctrl_out_num })
+    }
+
+    fn invert(&self, mut leds: Vec<CtrlResponse>) -> Vec<CtrlResponse> {
+        for led in &mut leds {
+            if let [num, ref mut val] = led.data[..] {
+                if num == self.ctrl_out_num {
+                    *val = 0x7f - (*val).min(0x7f);
+                }
+            }
+        }
+        leds
+    }
+
+    fn invert_response(&self, response: Option<Response>) -> Option<Response> {
+        let mut response = response?;
+        response.ctrl = self.invert(response.ctrl);
+        Some(response)
+    }
+}
+
+impl CtrlLogic for InvertLogic {
+    fn from_mapping(_mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        // only made by wrapping another logic
+        None
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let response = self.inner.handle_ctrl(num, val);
+        self.invert_response(response)
+    }
+
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        let response = self.inner.handle_osc(msg);
+        self.invert_response(response)
+    }
+
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        let response = self.inner.handle_midi(msg);
+        self.invert_response(response)
+    }
+
+    fn init(&mut self) -> Vec<Response> {
+        self.inner.init().into_iter().filter_map(|r| self.invert_response(Some(r))).collect()
+    }
+
+    fn tick(&mut self, now: Instant, lfos: &[Lfo]) -> Option<Response> {
+        let response = self.inner.tick(now, lfos);
+        self.invert_response(response)
+    }
+
+    fn ticks(&self) -> bool {
+        self.inner.ticks()
+    }
+
+    fn apply(&mut self, name: &str, val: u8) -> Option<Response> {
+        let response = self.inner.apply(name, val);
+        self.invert_response(response)
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        let leds = self.inner.leds();
+        self.invert(leds)
+    }
+
+    fn led(&mut self, name: &str, val: u8) -> Option<Vec<CtrlResponse>> {
+        let leds = self.inner.led(name, val)?;
+        Some(self.invert(leds))
+    }
+
+    fn is_in_state(&self, name: &str, is: u8) -> Option<bool> {
+        self.inner.is_in_state(name, is)
+    }
+
+    fn value(&self, name: &str) -> Option<u8> {
+        self.inner.value(name)
+    }
+
+    #[cfg(feature = "osc")]
+    fn osc_value(&self, name: &str) -> Option<f32> {
+        self.inner.osc_value(name)
+    }
+}
@@ -0,0 +1,105 @@
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::{CtrlKind, JogEncoding, Mapping, MidiSpec},
+    interpreter::{CtrlLogic, MidiResponse, OscResponse, Response}
+};
+
+/// a jog wheel: every encoder tick is sent on as a relative CC, without accumulating.
+/// an optional touch control enables scratching.
+#[derive(Debug)]
+pub struct JogLogic {
+    encoding: JogEncoding,
+    ctrl_in_num: Option<u8>,
+    touch_ctrl_in_num: Option<u8>,
+    midi: Option<MidiSpec>,
+    scratch_midi: Option<MidiSpec>,
+    osc_addr: String,
+    touched: bool,
+}
+
+impl JogLogic {
+    fn encode(&self, delta: i8) -> u8 {
+        let delta = delta.clamp(-63, 63);
+        match self.encoding {
+            JogEncoding::TwosComplement => (delta as u8) & 0x7f,
+            JogEncoding::Offset => (0x40 + delta as i16) as u8,
+            JogEncoding::SignMagnitude =>
+                if delta < 0 { 0x40 | delta.unsigned_abs() } else { delta as u8 },
+        }
+    }
+
+    fn touch(&mut self, touched: bool) -> Response {
+        if touched == self.touched {
+            return Response::new();
+        }
+        self.touched = touched;
+
+        Response {
+            ctrl: vec![],
+            osc: vec![OscResponse {
+                addr: format!("{}/touch", self.osc_addr),
+                args: vec![OscType::Float(if touched { 1.0 } else { 0.0 })]
+            }],
+            midi: self.scratch_midi.map(|midi| MidiResponse {
+                data: midi.message(if touched { 0x7f } else { 0x00 }),
+                page: None
+            }).into_iter().collect(),
+            plugin: vec![],
+            received: None,
+            motor: vec![]
+        }
+    }
+}
+
+impl CtrlLogic for JogLogic {
+    fn from_mapping(mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        let CtrlKind::Jog { encoding, touch_ctrl_in_num, scratch_midi } = mapping.ctrl_kind else {
+            return None;
+        };
+
+        Some(Box::new(JogLogic {
+            encoding,
+            ctrl_in_num: mapping.ctrl_in_num,
+            touch_ctrl_in_num,
+            midi: mapping.midi,
+            scratch_midi,
+            osc_addr: mapping.osc_addr(),
+            touched: false,
+        }))
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        if self.touch_ctrl_in_num == Some(num) {
+            return Some(self.touch(val != 0x00));
+        }
+
+        if self.ctrl_in_num != Some(num) {
+            return None;
+        }
+
+        let delta: i8 = if val < 0x40 { val as i8 } else { val as i8 + i8::MIN };
+        Some(Response {
+            ctrl: vec![],
+            osc: vec![OscResponse {
+                addr: self.osc_addr.clone(),
+                args: vec![OscType::Float(delta as f32)]
+            }],
+            midi: self.midi.map(|midi| MidiResponse {
+                data: midi.message(self.encode(delta)),
+                page: None
+            }).into_iter().collect(),
+            plugin: vec![],
+            received: None,
+            motor: vec![]
+        })
+    }
+
+    fn handle_osc(&mut self, _msg: &OscMessage) -> Option<Response> {
+        None
+    }
+
+    fn handle_midi(&mut self, _msg: &[u8]) -> Option<Response> {
+        None
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    f64::consts::TAU,
+    time::Instant
+};
+
+use log::info;
+use rosc::OscType;
+
+use super::{
+    clock::Clock,
+    config::{LfoConfig, LfoShape},
+    interpreter::{OscResponse, Response}
+};
+
+const MIN_RATE: f32 = 0.01;
+const MAX_RATE: f32 = 50.0;
+
+/// number of encoder steps needed to double or halve the rate.
+const RATE_STEPS_PER_OCTAVE: f32 = 24.0;
+
+#[derive(Debug)]
+pub struct Lfo {
+    name: String,
+    shape: LfoShape,
+    rate: f32,
+    depth: f32,
+    sync: Option<f32>,
+    rate_ctrl_in_num: Option<u8>,
+    phase: f64,
+    last_tick: Option<Instant>,
+    value: f32,
+}
+
+impl Lfo {
+    pub fn new(config: &LfoConfig) -> Lfo {
+        Lfo {
+            name: config.name.clone(),
+            shape: config.shape,
+            rate: config.rate.clamp(MIN_RATE, MAX_RATE),
+            depth: config.depth,
+            sync: config.sync,
+            rate_ctrl_in_num: config.rate_ctrl_in_num,
+            phase: 0.0,
+            last_tick: None,
+            value: 0.0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// current output, scaled by depth. 1.0 corresponds to the full range of a control.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn tick(&mut self, now: Instant, clock: Option<&Clock>) {
+        match (self.sync, clock) {
+            (Some(beats_per_cycle), Some(clock)) => {
+                self.phase = (clock.beats(now) / beats_per_cycle as f64).fract();
+            },
+            _ => {
+                let dt = self.last_tick
+                    .map(|t| now.saturating_duration_since(t).as_secs_f64())
+                    .unwrap_or(0.0);
+                self.phase = (self.phase + dt * self.rate as f64).fract();
+            }
+        }
+        self.last_tick = Some(now);
+
+        let phase = self.phase;
+        let wave = match self.shape {
+            LfoShape::Sine => (phase * TAU).sin(),
+            LfoShape::Triangle =>
+                if phase < 0.25 {
+                    4.0 * phase
+                } else if phase < 0.75 {
+                    2.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 4.0
+                },
+            LfoShape::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+        };
+
+        self.value = wave as f32 * self.depth;
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        if self.rate_ctrl_in_num != Some(num) {
+            return None;
+        }
+
+        let delta: i8 = if val < 0x40 { val as i8 } else { val as i8 + i8::MIN };
+        self.rate = (self.rate * (delta as f32 / RATE_STEPS_PER_OCTAVE).exp2())
+            .clamp(MIN_RATE, MAX_RATE);
+        info!("lfo {}: rate {:.3} Hz", self.name, self.rate);
+
+        Some(OscResponse {
+            addr: format!("/autocrap/lfo/{}/rate", self.name),
+            args: vec![OscType::Float(self.rate)]
+        }.into())
+    }
+}
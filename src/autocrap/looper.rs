@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant}
+};
+
+use log::{info, warn};
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::LooperConfig,
+    determinism,
+    interpreter::{osc_number, CtrlResponse, OscResponse, Response}
+};
+
+const OSC_LOOPER_PREFIX: &str = "/autocrap/looper/";
+
+const MIN_SPEED: f32 = 0.125;
+const MAX_SPEED: f32 = 8.0;
+
+/// number of encoder steps needed to double or halve the speed.
+const SPEED_STEPS_PER_OCTAVE: f32 = 24.0;
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(150);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Stopped,
+    Recording,
+    Playing,
+}
+
+impl State {
+    fn name(self) -> &'static str {
+        match self {
+            State::Stopped => "stop",
+            State::Recording => "record",
+            State::Playing => "play",
+        }
+    }
+}
+
+/// records how some mappings are moved, and plays it back in a loop.
+#[derive(Debug)]
+pub struct Looper {
+    name: String,
+    controls: Vec<String>,
+    ctrl_in_num: Option<u8>,
+    ctrl_out_num: Option<u8>,
+    speed_ctrl_in_num: Option<u8>,
+    state: State,
+    /// recorded values, by time since the start of the loop.
+    events: Vec<(f64, String, u8)>,
+    /// the length of the loop in seconds.
+    length: f64,
+    /// when recording started.
+    started: Instant,
+    /// the last recorded value of each control, so that only changes are recorded.
+    last: BTreeMap<String, u8>,
+    /// where playback is in the loop, in seconds.
+    position: f64,
+    last_tick: Option<Instant>,
+    speed: f32,
+    blink_on: bool,
+}
+
+impl Looper {
+    pub fn new(config: &LooperConfig) -> Looper {
+        Looper {
+            name: config.name.clone(),
+            controls: config.controls.clone(),
+            ctrl_in_num: config.ctrl_in_num,
+            ctrl_out_num: config.ctrl_out_num,
+            speed_ctrl_in_num: config.speed_ctrl_in_num,
+            state: State::Stopped,
+            events: vec![],
+            length: 0.0,
+            started: determinism::now(),
+            last: BTreeMap::new(),
+            position: 0.0,
+            last_tick: None,
+            speed: 1.0,
+            blink_on: false,
+        }
+    }
+
+    /// the button goes from recording to playing to stopped, the encoder changes the speed.
+    pub fn handle_ctrl(&mut self, num: u8, val: u8, now: Instant, value: impl Fn(&str) -> Option<u8>) -> Option<Response> {
+        if self.speed_ctrl_in_num == Some(num) {
+            let delta: i8 = if val < 0x40 { val as i8 } else { val as i8 + i8::MIN };
+            return Some(self.set_speed(self.speed * (delta as f32 / SPEED_STEPS_PER_OCTAVE).exp2()));
+        }
+
+        if self.ctrl_in_num != Some(num) {
+            return None;
+        }
+        if val == 0x00 {
+            return Some(Response::new());
+        }
+
+        let next = match self.state {
+            State::Stopped => State::Recording,
+            State::Recording => State::Playing,
+            State::Playing => State::Stopped,
+        };
+        Some(self.set_state(next, now, value))
+    }
+
+    /// `/autocrap/looper/<name>` with `"record"`, `"play"` or `"stop"`, and `/autocrap/looper/<name>/speed`.
+    pub fn handle_osc(&mut self, msg: &OscMessage, now: Instant, value: impl Fn(&str) -> Option<u8>) -> Option<Response> {
+        let rest = msg.addr.strip_prefix(OSC_LOOPER_PREFIX)?.strip_prefix(self.name.as_str())?;
+        match rest {
+            "" => {
+                let state = match msg.args.first() {
+                    Some(OscType::String(s)) if s == "record" => State::Recording,
+                    Some(OscType::String(s)) if s == "play" => State::Playing,
+                    Some(OscType::String(s)) if s == "stop" => State::Stopped,
+                    arg => {
+                        warn!("looper {}: expected record, play or stop, got {:?}", self.name, arg);
+                        return Some(Response::new());
+                    }
+                };
+                Some(self.set_state(state, now, value))
+            },
+            "/speed" => {
+                let Some(speed) = msg.args.first().and_then(osc_number) else {
+                    warn!("looper {}: expected a speed, got {:?}", self.name, msg.args);
+                    return Some(Response::new());
+                };
+                Some(self.set_speed(speed))
+            },
+            _ => None
+        }
+    }
+
+    fn set_speed(&mut self, speed: f32) -> Response {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        info!("looper {}: speed {:.3}", self.name, self.speed);
+        OscResponse {
+            addr: format!("{}{}/speed", OSC_LOOPER_PREFIX, self.name),
+            args: vec![OscType::Float(self.speed)]
+        }.into()
+    }
+
+    fn set_state(&mut self, state: State, now: Instant, value: impl Fn(&str) -> Option<u8>) -> Response {
+        match state {
+            State::Recording => {
+                self.events.clear();
+                self.last.clear();
+                self.started = now;
+                self.state = State::Recording;
+                // start from where the controls are now
+                self.record(now, value);
+            },
+            State::Playing => {
+                if self.state == State::Recording {
+                    self.length = now.saturating_duration_since(self.started).as_secs_f64();
+                }
+                if self.events.is_empty() || self.length <= 0.0 {
+                    warn!("looper {}: nothing recorded", self.name);
+                    self.state = State::Stopped;
+                } else {
+                    self.state = State::Playing;
+                    self.position = 0.0;
+                    self.last_tick = Some(now);
+                }
+            },
+            State::Stopped => self.state = State::Stopped,
+        }
+        info!("looper {}: {}", self.name, self.state.name());
+
+        let mut response: Response = OscResponse {
+            addr: format!("{}{}", OSC_LOOPER_PREFIX, self.name),
+            args: vec![OscType::String(self.state.name().to_string())]
+        }.into();
+        self.blink_on = self.state != State::Stopped;
+        response.ctrl = self.led(self.blink_on);
+        response
+    }
+
+    /// notes the values of the controls which changed, while recording.
+    pub fn record(&mut self, now: Instant, value: impl Fn(&str) -> Option<u8>) {
+        if self.state != State::Recording {
+            return;
+        }
+
+        let at = now.saturating_duration_since(self.started).as_secs_f64();
+        for name in &self.controls {
+            let Some(val) = value(name) else {
+                continue;
+            };
+            if self.last.insert(name.clone(), val) != Some(val) {
+                self.events.push((at, name.clone(), val));
+            }
+        }
+    }
+
+    /// the LED blinking while recording, and the recorded values which are due while playing.
+    pub fn tick(&mut self, now: Instant) -> (Option<Response>, Vec<(String, u8)>) {
+        match self.state {
+            State::Stopped => (None, vec![]),
+            State::Recording => {
+                let elapsed = now.saturating_duration_since(self.started);
+                let blink_on = (elapsed.as_millis() / BLINK_INTERVAL.as_millis()).is_multiple_of(2);
+                if blink_on == self.blink_on {
+                    return (None, vec![]);
+                }
+                self.blink_on = blink_on;
+                (Some(Response { ctrl: self.led(blink_on), ..Response::new() }), vec![])
+            },
+            State::Playing => {
+                let dt = self.last_tick
+                    .map(|t| now.saturating_duration_since(t).as_secs_f64())
+                    .unwrap_or(0.0);
+                self.last_tick = Some(now);
+
+                let mut from = self.position;
+                let mut to = from + (dt * self.speed as f64).min(self.length);
+                let mut values = vec![];
+                loop {
+                    values.extend(self.events.iter()
+                        .filter(|(at, _, _)| *at >= from && *at < to)
+                        .map(|(_, name, val)| (name.clone(), *val)));
+                    if to < self.length {
+                        break;
+                    }
+                    from = 0.0;
+                    to -= self.length;
+                }
+                self.position = to;
+                (None, values)
+            }
+        }
+    }
+
+    fn led(&self, on: bool) -> Vec<CtrlResponse> {
+        self.ctrl_out_num
+            .map(|num| CtrlResponse { data: vec![num, if on { 0x7f } else { 0x00 }], color: None, raw: false })
+            .into_iter()
+            .collect()
+    }
+}
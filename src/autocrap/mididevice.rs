@@ -0,0 +1,173 @@
+use std::{
+    sync::{mpsc, Mutex},
+    time::Duration
+};
+
+use log::{debug, info, warn};
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+#[cfg(unix)]
+use midir::os::unix::{VirtualInput, VirtualOutput};
+
+use super::{
+    config::{Config, MidiDeviceConfig, MidiPort},
+    device::{parse_ctrls, DeviceBackend, Result},
+    diagnostics::{self, Direction}
+};
+
+/// a controller which is itself a MIDI device. CCs and notes on its channel become control
+/// numbers, and feedback goes back to it as CCs, so any class compliant controller can be used.
+///
+/// notes share control numbers with CCs: note on is the velocity, note off is 0. motorized faders
+/// are moved with pitch bend on the motor number's channel.
+pub struct MidiDevice {
+    channel: u8,
+    events: Mutex<mpsc::Receiver<[u8; 2]>>,
+    out: Mutex<MidiOutputConnection>,
+    // kept open for as long as the device is
+    _in: Mutex<MidiInputConnection<mpsc::Sender<[u8; 2]>>>,
+}
+
+impl DeviceBackend for MidiDevice {
+    fn open(config: &Config) -> Result<Option<MidiDevice>> {
+        let Some(MidiDeviceConfig { ref client_name, ref in_port, ref out_port, channel }) = config.midi_device else {
+            return Ok(None);
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let Some(input) = connect_in(client_name, in_port, channel, tx)? else {
+            return Ok(None);
+        };
+        let Some(out) = connect_out(client_name, out_port)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(MidiDevice {
+            channel,
+            events: Mutex::new(rx),
+            out: Mutex::new(out),
+            _in: Mutex::new(input),
+        }))
+    }
+
+    fn read_events(&self, timeout: Duration) -> Result<Vec<[u8; 2]>> {
+        let events = self.events.lock().unwrap();
+        match events.recv_timeout(timeout) {
+            Ok(event) => Ok(std::iter::once(event).chain(events.try_iter()).collect()),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(vec![]),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err("midi device input went away".into())
+        }
+    }
+
+    fn write_feedback(&self, data: &[u8]) -> Result<()> {
+        let mut out = self.out.lock().unwrap();
+        for [num, value] in parse_ctrls(data) {
+            let msg = [0xb0 | self.channel, num & 0x7f, value.min(0x7f)];
+            debug!("send midi to device: {:02x?}", msg);
+            diagnostics::record_midi(Direction::DeviceOut, &msg);
+            if let Err(e) = out.send(&msg) {
+                warn!("could not send to midi device: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_raw(&self, data: &[u8]) -> Result<()> {
+        debug!("send midi to device: {:02x?}", data);
+        diagnostics::record_midi(Direction::DeviceOut, data);
+        if let Err(e) = self.out.lock().unwrap().send(data) {
+            warn!("could not send to midi device: {}", e);
+        }
+        Ok(())
+    }
+
+    fn write_motor(&self, num: u8, position: u16) -> Result<()> {
+        // pitch bend on the motor's channel, as Mackie Control does it
+        let msg = [0xe0 | (num & 0x0f), position as u8 & 0x7f, (position >> 7) as u8 & 0x7f];
+        debug!("send midi to device: {:02x?}", msg);
+        diagnostics::record_midi(Direction::DeviceOut, &msg);
+        if let Err(e) = self.out.lock().unwrap().send(&msg) {
+            warn!("could not send to midi device: {}", e);
+        }
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        // there is no telling which controls have LEDs, so turn them all off
+        let data: Vec<u8> = (0..0x80).flat_map(|num| [num, 0x00]).collect();
+        self.write_feedback(&data)
+    }
+}
+
+impl std::fmt::Debug for MidiDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MidiDevice").field("channel", &self.channel).finish_non_exhaustive()
+    }
+}
+
+/// a (control number, value) pair for a CC or note on `channel`.
+fn parse_event(msg: &[u8], channel: u8) -> Option<[u8; 2]> {
+    let &[status, num, value] = msg else {
+        return None;
+    };
+    if status & 0x0f != channel {
+        return None;
+    }
+    match status & 0xf0 {
+        0xb0 | 0x90 => Some([num, value]),
+        0x80 => Some([num, 0x00]),
+        _ => None
+    }
+}
+
+fn connect_in(
+    client_name: &str,
+    port: &MidiPort,
+    channel: u8,
+    tx: mpsc::Sender<[u8; 2]>
+) -> Result<Option<MidiInputConnection<mpsc::Sender<[u8; 2]>>>> {
+    let midi_in = MidiInput::new(client_name)?;
+    let callback = move |_time: u64, msg: &[u8], tx: &mut mpsc::Sender<[u8; 2]>| {
+        diagnostics::record_midi(Direction::DeviceIn, msg);
+        if let Some(event) = parse_event(msg, channel) {
+            let _ = tx.send(event);
+        }
+    };
+    let found = match port {
+        MidiPort::Index(index) => midi_in.ports().get(*index).cloned(),
+        MidiPort::Name(name) => midi_in.ports().into_iter().find(|p| midi_in.port_name(p).is_ok_and(|n| &n == name)),
+        #[cfg(unix)]
+        MidiPort::Virtual(name) => {
+            info!("midi device in port: {}", name);
+            return Ok(Some(midi_in.create_virtual(name, callback, tx).map_err(|e| e.to_string())?));
+        },
+        #[cfg(not(unix))]
+        MidiPort::Virtual(_) => return Err("virtual midi ports are currently unsupported on non-unix systems".into())
+    };
+    let Some(found) = found else {
+        return Ok(None);
+    };
+
+    info!("midi device in port: {}", midi_in.port_name(&found)?);
+    Ok(Some(midi_in.connect(&found, client_name, callback, tx).map_err(|e| e.to_string())?))
+}
+
+fn connect_out(client_name: &str, port: &MidiPort) -> Result<Option<MidiOutputConnection>> {
+    let midi_out = MidiOutput::new(client_name)?;
+    let found = match port {
+        MidiPort::Index(index) => midi_out.ports().get(*index).cloned(),
+        MidiPort::Name(name) => midi_out.ports().into_iter().find(|p| midi_out.port_name(p).is_ok_and(|n| &n == name)),
+        #[cfg(unix)]
+        MidiPort::Virtual(name) => {
+            info!("midi device out port: {}", name);
+            return Ok(Some(midi_out.create_virtual(name).map_err(|e| e.to_string())?));
+        },
+        #[cfg(not(unix))]
+        MidiPort::Virtual(_) => return Err("virtual midi ports are currently unsupported on non-unix systems".into())
+    };
+    let Some(found) = found else {
+        return Ok(None);
+    };
+
+    info!("midi device out port: {}", midi_out.port_name(&found)?);
+    Ok(Some(midi_out.connect(&found, client_name).map_err(|e| e.to_string())?))
+}
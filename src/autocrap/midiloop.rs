@@ -0,0 +1,78 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+
+use log::warn;
+
+use super::config::LoopDetectionConfig;
+
+/// how many sent MIDI messages to remember.
+const HISTORY_LEN: usize = 64;
+/// the least time between two warnings about looped messages.
+const WARN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// MIDI sent lately, to recognize it if it comes straight back in, such as when the same loopMIDI
+/// port is both the output and the input.
+struct Recent {
+    sent: VecDeque<(Instant, Vec<u8>)>,
+    /// looped messages since the last warning.
+    looped: usize,
+    last_warning: Option<Instant>,
+}
+
+static RECENT: Mutex<Recent> = Mutex::new(Recent {
+    sent: VecDeque::new(),
+    looped: 0,
+    last_warning: None,
+});
+
+fn with_recent<T>(f: impl FnOnce(&mut Recent) -> T) -> T {
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut recent)
+}
+
+/// remembers a message sent to a MIDI output port.
+pub fn sent(data: &[u8]) {
+    let now = Instant::now();
+    with_recent(|r| {
+        if r.sent.len() == HISTORY_LEN {
+            r.sent.pop_front();
+        }
+        r.sent.push_back((now, data.to_vec()));
+    });
+}
+
+/// whether a message from the MIDI input port is one of ours, sent within the window.
+/// warns every now and then while that keeps happening.
+pub fn looped(config: &LoopDetectionConfig, data: &[u8]) -> bool {
+    if config.window_ms == 0 {
+        return false;
+    }
+
+    let now = Instant::now();
+    let window = Duration::from_millis(config.window_ms);
+    with_recent(|r| {
+        while r.sent.front().is_some_and(|(at, _)| now.duration_since(*at) > window) {
+            r.sent.pop_front();
+        }
+        let Some(i) = r.sent.iter().position(|(_, sent)| sent == data) else {
+            return false;
+        };
+        // each message sent can only come back once
+        r.sent.remove(i);
+
+        r.looped += 1;
+        if r.last_warning.is_none_or(|at| now.duration_since(at) >= WARN_INTERVAL) {
+            warn!(
+                "{} midi messages came back in right after being sent, the output port seems to be connected to the input port{}",
+                r.looped,
+                if config.drop { ". ignoring them" } else { "" }
+            );
+            r.looped = 0;
+            r.last_warning = Some(now);
+        }
+        true
+    })
+}
@@ -0,0 +1,94 @@
+use super::{
+    clock::PPQN,
+    config::{FeedbackSource, Mapping}
+};
+
+const MIDI_SONG_POSITION: u8 = 0xf2;
+const MIDI_CLOCK: u8 = 0xf8;
+const MIDI_START: u8 = 0xfa;
+const MIDI_CONTINUE: u8 = 0xfb;
+const MIDI_STOP: u8 = 0xfc;
+
+/// MIDI clock pulses in a song position step, which is a sixteenth note.
+const PULSES_PER_STEP: u32 = PPQN / 4;
+
+/// follows the host's MIDI transport (start/stop/continue, song position and clock), for the mappings whose
+/// `feedback_source` is one of the MIDI transport sources.
+#[derive(Debug)]
+pub struct MidiTransport {
+    /// mappings following the transport, and whether their LED is lit.
+    mappings: Vec<(String, FeedbackSource, bool)>,
+    playing: bool,
+    /// clock pulses since the start of the song.
+    position: u32,
+}
+
+impl MidiTransport {
+    pub fn new(mappings: &[Mapping]) -> MidiTransport {
+        MidiTransport {
+            mappings: mappings.iter()
+                .filter(|m| m.feedback_source.is_midi_transport())
+                .map(|m| (m.name.clone(), m.feedback_source, false))
+                .collect(),
+            playing: false,
+            position: 0,
+        }
+    }
+
+    /// takes in a MIDI message, returning `None` if it is not about the transport, or else the new values of
+    /// the mappings whose LED it switched.
+    pub fn handle_midi(&mut self, msg: &[u8]) -> Option<Vec<(String, u8)>> {
+        match *msg {
+            [MIDI_START] => {
+                self.playing = true;
+                self.position = 0;
+            },
+            [MIDI_CONTINUE] => self.playing = true,
+            [MIDI_STOP] => self.playing = false,
+            [MIDI_SONG_POSITION, lsb, msb] => {
+                let steps = (msb as u32 & 0x7f) << 7 | (lsb as u32 & 0x7f);
+                self.position = steps * PULSES_PER_STEP;
+            },
+            [MIDI_CLOCK] => {
+                if !self.playing {
+                    return Some(vec![]);
+                }
+                // the position is that of the pulse being played, which moves on once it is over
+                let changes = self.changes();
+                self.position += 1;
+                return Some(changes);
+            },
+            _ => return None
+        }
+        Some(self.changes())
+    }
+
+    fn is_lit(&self, source: FeedbackSource) -> bool {
+        match source {
+            FeedbackSource::MidiPlaying => self.playing,
+            FeedbackSource::MidiStopped => !self.playing,
+            FeedbackSource::MidiBeat => self.playing && self.position % PPQN < PPQN / 2,
+            FeedbackSource::Internal | FeedbackSource::External => false,
+        }
+    }
+
+    fn changes(&mut self) -> Vec<(String, u8)> {
+        let lit: Vec<bool> = self.mappings.iter().map(|(_, source, _)| self.is_lit(*source)).collect();
+        self.mappings.iter_mut()
+            .zip(lit)
+            .filter(|((_, _, was_lit), lit)| was_lit != lit)
+            .map(|((name, _, was_lit), lit)| {
+                *was_lit = lit;
+                (name.clone(), if lit { 0x7f } else { 0x00 })
+            })
+            .collect()
+    }
+
+    /// the values of all mappings following the transport, e.g. to light the `MidiStopped` ones on startup.
+    pub fn values(&mut self) -> Vec<(String, u8)> {
+        for i in 0..self.mappings.len() {
+            self.mappings[i].2 = self.is_lit(self.mappings[i].1);
+        }
+        self.mappings.iter().map(|(name, _, lit)| (name.clone(), if *lit { 0x7f } else { 0x00 })).collect()
+    }
+}
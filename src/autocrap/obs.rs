@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use log::{debug, warn};
+use rosc::{OscMessage, OscType};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::{
+    config::{Config, ObsAction},
+    interpreter::OscResponse
+};
+
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_EVENT: u64 = 5;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+
+const RPC_VERSION: u64 = 1;
+
+/// all event categories except the high-volume ones.
+const EVENT_SUBSCRIPTIONS: u64 = 0x7ff;
+
+/// translates between controls and obs-websocket v5 messages.
+///
+/// controls are identified by their OSC address, and their values are the same as would be sent over OSC.
+/// outgoing OSC responses become OBS requests, and OBS events become incoming OSC messages, which the
+/// interpreter treats as feedback.
+#[derive(Debug)]
+pub struct ObsBridge {
+    password: Option<String>,
+    controls: Vec<(String, ObsAction)>,
+    /// input names of pending get requests, by request id.
+    pending_inputs: HashMap<String, String>,
+    next_request_id: u64,
+}
+
+impl ObsBridge {
+    pub fn new(config: &Config, password: Option<String>) -> ObsBridge {
+        let controls = config.mappings.iter()
+            .flat_map(|m| m.expand_iter())
+            .filter_map(|m| Some((m.osc_addr(), m.obs.clone()?)))
+            .collect();
+
+        ObsBridge {
+            password,
+            controls,
+            pending_inputs: HashMap::new(),
+            next_request_id: 0,
+        }
+    }
+
+    /// handles a message from OBS, returning messages to send back and feedback for the interpreter.
+    pub fn handle_message(&mut self, msg: &Value) -> (Vec<Value>, Vec<OscMessage>) {
+        let data = &msg["d"];
+        match msg["op"].as_u64() {
+            Some(OP_HELLO) => (vec![self.identify(data)], vec![]),
+            Some(OP_IDENTIFIED) => (self.state_requests(), vec![]),
+            Some(OP_EVENT) => {
+                let event_type = data["eventType"].as_str().unwrap_or_default();
+                (vec![], self.feedback(event_type, &data["eventData"]))
+            },
+            Some(OP_REQUEST_RESPONSE) => {
+                let request_type = data["requestType"].as_str().unwrap_or_default();
+                let input_name = data["requestId"].as_str().and_then(|id| self.pending_inputs.remove(id));
+                if data["requestStatus"]["result"] != json!(true) {
+                    warn!("obs request {} failed: {}", request_type, data["requestStatus"]["comment"]);
+                    return (vec![], vec![]);
+                }
+
+                let mut response_data = data["responseData"].clone();
+                if let (Some(name), Some(fields)) = (input_name, response_data.as_object_mut()) {
+                    fields.insert("inputName".to_string(), json!(name));
+                }
+                (vec![], self.feedback(request_type, &response_data))
+            },
+            _ => {
+                debug!("unhandled obs message: {}", msg);
+                (vec![], vec![])
+            }
+        }
+    }
+
+    /// OBS requests for a value sent by a control.
+    pub fn requests_for(&mut self, osc: &OscResponse) -> Vec<Value> {
+        let Some(&OscType::Float(val)) = osc.args.first() else {
+            return vec![];
+        };
+
+        let actions: Vec<ObsAction> = self.controls.iter()
+            .filter(|(addr, _)| addr == &osc.addr)
+            .map(|(_, action)| action.clone())
+            .collect();
+
+        let on = val != 0.0;
+        actions.into_iter()
+            .map(|action| match action {
+                ObsAction::Scene(name) if on =>
+                    self.request("SetCurrentProgramScene", json!({ "sceneName": name })),
+                // a scene can't be switched off, but its button may have been. have the LEDs catch up.
+                ObsAction::Scene(_) =>
+                    self.request("GetCurrentProgramScene", json!({})),
+                ObsAction::Stream =>
+                    self.request(if on { "StartStream" } else { "StopStream" }, json!({})),
+                ObsAction::Record =>
+                    self.request(if on { "StartRecord" } else { "StopRecord" }, json!({})),
+                ObsAction::InputMute(name) =>
+                    self.request("SetInputMute", json!({ "inputName": name, "inputMuted": on })),
+                ObsAction::InputVolume(name) =>
+                    self.request("SetInputVolume", json!({ "inputName": name, "inputVolumeMul": val })),
+            })
+            .collect()
+    }
+
+    fn identify(&self, hello: &Value) -> Value {
+        let mut identify = json!({
+            "rpcVersion": RPC_VERSION,
+            "eventSubscriptions": EVENT_SUBSCRIPTIONS
+        });
+
+        let auth = &hello["authentication"];
+        if let (Some(challenge), Some(salt)) = (auth["challenge"].as_str(), auth["salt"].as_str()) {
+            match self.password {
+                Some(ref password) => {
+                    let secret = BASE64.encode(Sha256::digest(format!("{}{}", password, salt)));
+                    let response = BASE64.encode(Sha256::digest(format!("{}{}", secret, challenge)));
+                    identify["authentication"] = json!(response);
+                },
+                None => warn!("obs requires a password, but none is configured"),
+            }
+        }
+
+        json!({ "op": OP_IDENTIFY, "d": identify })
+    }
+
+    /// requests for the current state of everything we have controls for.
+    fn state_requests(&mut self) -> Vec<Value> {
+        let actions: Vec<ObsAction> = self.controls.iter().map(|(_, a)| a.clone()).collect();
+        let mut requests = vec![];
+        let mut scene_requested = false;
+        for action in actions {
+            let request = match action {
+                ObsAction::Scene(_) if scene_requested => continue,
+                ObsAction::Scene(_) => {
+                    scene_requested = true;
+                    self.request("GetCurrentProgramScene", json!({}))
+                },
+                ObsAction::Stream => self.request("GetStreamStatus", json!({})),
+                ObsAction::Record => self.request("GetRecordStatus", json!({})),
+                ObsAction::InputMute(ref name) => self.request("GetInputMute", json!({ "inputName": name })),
+                ObsAction::InputVolume(ref name) => self.request("GetInputVolume", json!({ "inputName": name })),
+            };
+
+            if let ObsAction::InputMute(name) | ObsAction::InputVolume(name) = action {
+                self.pending_inputs.insert(self.next_request_id.to_string(), name);
+            }
+            requests.push(request);
+        }
+        requests
+    }
+
+    /// feedback from an event, or from the response to a state request.
+    fn feedback(&self, kind: &str, data: &Value) -> Vec<OscMessage> {
+        if let Some(scene) = data["sceneName"].as_str().or(data["currentProgramSceneName"].as_str()) {
+            if matches!(kind, "CurrentProgramSceneChanged" | "GetCurrentProgramScene") {
+                return self.controls.iter()
+                    .filter_map(|(addr, action)| match action {
+                        ObsAction::Scene(name) => Some(feedback_msg(addr, (name == scene) as u8 as f32)),
+                        _ => None
+                    })
+                    .collect();
+            }
+        }
+
+        let active = data["outputActive"].as_bool().map(|a| a as u8 as f32);
+        let input_name = data["inputName"].as_str();
+        self.controls.iter()
+            .filter_map(|(addr, action)| {
+                let val = match (action, kind) {
+                    (ObsAction::Stream, "StreamStateChanged" | "GetStreamStatus") => active?,
+                    (ObsAction::Record, "RecordStateChanged" | "GetRecordStatus") => active?,
+                    (ObsAction::InputMute(name), "InputMuteStateChanged" | "GetInputMute")
+                        if Some(name.as_str()) == input_name =>
+                        data["inputMuted"].as_bool()? as u8 as f32,
+                    (ObsAction::InputVolume(name), "InputVolumeChanged" | "GetInputVolume")
+                        if Some(name.as_str()) == input_name =>
+                        data["inputVolumeMul"].as_f64()? as f32,
+                    _ => return None
+                };
+                Some(feedback_msg(addr, val))
+            })
+            .collect()
+    }
+
+    fn request(&mut self, request_type: &str, request_data: Value) -> Value {
+        self.next_request_id += 1;
+        json!({
+            "op": OP_REQUEST,
+            "d": {
+                "requestType": request_type,
+                "requestId": self.next_request_id.to_string(),
+                "requestData": request_data
+            }
+        })
+    }
+}
+
+fn feedback_msg(addr: &str, val: f32) -> OscMessage {
+    OscMessage {
+        addr: addr.to_string(),
+        args: vec![OscType::Float(val)]
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeSet;
+
+use log::info;
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::{Mapping, MidiSpec, OffsetConfig},
+    interpreter::{osc_number, CtrlResponse, MidiResponse, OscResponse, Response}
+};
+
+/// shifts the MIDI numbers of a group of mappings while running, such as transposing all notes or
+/// switching a bank of knobs to other CCs. stepped with a pair of buttons, whose LEDs show which way
+/// it is shifted, or set over OSC as `/autocrap/offset/<name>`.
+#[derive(Debug)]
+pub struct Offset {
+    name: String,
+    specs: Vec<MidiSpec>,
+    down_ctrl_in_num: Option<u8>,
+    down_ctrl_out_num: Option<u8>,
+    up_ctrl_in_num: Option<u8>,
+    up_ctrl_out_num: Option<u8>,
+    step: i16,
+    min: i16,
+    max: i16,
+    current: i16,
+    /// notes sent with the offset which are still sounding, as channel and number.
+    held: BTreeSet<(u8, u8)>,
+    /// note offs for the notes held when the offset changed, to be sent as they are.
+    releases: Vec<MidiResponse>,
+}
+
+impl Offset {
+    pub fn new(config: &OffsetConfig, mappings: &[Mapping]) -> Offset {
+        let specs = mappings.iter()
+            .filter(|m| config.mappings.is_empty() || config.mappings.contains(&m.name))
+            .flat_map(|m| m.midi.into_iter().chain(m.links.iter().filter_map(|l| l.midi)))
+            .filter(|midi| midi.kind == config.kind)
+            .collect();
+
+        Offset {
+            name: config.name.clone(),
+            specs,
+            down_ctrl_in_num: config.down_ctrl_in_num,
+            down_ctrl_out_num: config.down_ctrl_out_num,
+            up_ctrl_in_num: config.up_ctrl_in_num,
+            up_ctrl_out_num: config.up_ctrl_out_num,
+            step: config.step as i16,
+            min: config.min.min(0) as i16,
+            max: config.max.max(0) as i16,
+            current: 0,
+            held: BTreeSet::new(),
+            releases: vec![],
+        }
+    }
+
+    fn osc_addr(&self) -> String {
+        format!("/autocrap/offset/{}", self.name)
+    }
+
+    fn set(&mut self, offset: i16) -> Response {
+        let offset = offset.clamp(self.min, self.max);
+        let mut response = Response::new();
+        if offset == self.current {
+            return response;
+        }
+
+        // notes sent before the change would otherwise never be released
+        for (channel, num) in std::mem::take(&mut self.held) {
+            self.releases.push(MidiResponse {
+                data: vec![0b10000000 | channel, num, 0x00],
+                page: None
+            });
+        }
+
+        self.current = offset;
+        info!("offset {}: {}", self.name, self.current);
+        response.extend(self.feedback());
+        response
+    }
+
+    /// LEDs of the buttons, lit for the way the numbers are shifted, and the offset over OSC.
+    pub fn feedback(&self) -> Response {
+        let mut response = Response::from(OscResponse {
+            addr: self.osc_addr(),
+            args: vec![OscType::Int(self.current as i32)]
+        });
+        for (num, lit) in [(self.down_ctrl_out_num, self.current < 0), (self.up_ctrl_out_num, self.current > 0)] {
+            if let Some(num) = num {
+                response.ctrl.push(CtrlResponse {
+                    data: vec![num, if lit { 0x7f } else { 0x00 }],
+                    color: None,
+                    raw: false
+                });
+            }
+        }
+        response
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let direction = if Some(num) == self.down_ctrl_in_num {
+            -1
+        } else if Some(num) == self.up_ctrl_in_num {
+            1
+        } else {
+            return None;
+        };
+        if val == 0x00 {
+            return Some(Response::new());
+        }
+
+        Some(self.set(self.current + direction * self.step))
+    }
+
+    pub fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        if msg.addr != self.osc_addr() {
+            return None;
+        }
+
+        let Some(offset) = msg.args.first().and_then(osc_number) else {
+            return Some(self.feedback());
+        };
+        Some(self.set(offset.round() as i16))
+    }
+
+    /// shifts MIDI sent by the group's mappings. messages shifted out of the MIDI range are dropped.
+    pub fn outgoing(&mut self, response: &mut Response) {
+        response.midi.retain_mut(|midi| {
+            if !self.specs.iter().any(|spec| spec.value(&midi.data).is_some()) {
+                return true;
+            }
+            let Ok(num) = u8::try_from(midi.data[1] as i16 + self.current) else {
+                return false;
+            };
+            if num > 127 {
+                return false;
+            }
+
+            midi.data[1] = num;
+            let channel = midi.data[0] & 0x0f;
+            match midi.data[0] & 0xf0 {
+                0b10010000 if midi.data[2] != 0 => { self.held.insert((channel, num)); },
+                0b10010000 | 0b10000000 => { self.held.remove(&(channel, num)); },
+                _ => {}
+            }
+            true
+        });
+        response.midi.append(&mut self.releases);
+    }
+
+    /// shifts MIDI received for the group's mappings back, so that they recognize it.
+    pub fn incoming(&self, msg: &mut [u8]) {
+        if self.current == 0 {
+            return;
+        }
+
+        let Some(spec) = self.specs.iter().find(|spec| {
+            let num = spec.num as i16 + self.current;
+            (0..=127).contains(&num) && MidiSpec { num: num as u8, ..**spec }.value(msg).is_some()
+        }) else {
+            return;
+        };
+        msg[1] = spec.num;
+    }
+}
+
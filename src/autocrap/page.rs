@@ -0,0 +1,134 @@
+use log::info;
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::PagesConfig,
+    interpreter::{osc_number, CtrlResponse, OscResponse, Response}
+};
+
+const OSC_PAGE_ADDR: &str = "/autocrap/page";
+
+/// the active page, selected with buttons or over OSC. pages are numbered from 1.
+#[derive(Debug)]
+pub struct Pages {
+    count: u8,
+    ctrl_in_nums: Vec<u8>,
+    ctrl_out_nums: Vec<u8>,
+    /// buttons and the pages they switch to while held.
+    momentary: Vec<(u8, u8)>,
+    /// the momentary button being held, and the page to go back to.
+    held: Option<(u8, u8)>,
+    current: u8,
+    park: Option<u8>,
+}
+
+impl Pages {
+    pub fn new(config: &PagesConfig) -> Pages {
+        Pages {
+            count: config.count.max(1),
+            ctrl_in_nums: config.ctrl_in_nums.clone(),
+            ctrl_out_nums: config.ctrl_out_nums.clone(),
+            momentary: config.momentary.iter().map(|m| (m.ctrl_in_num, m.page)).collect(),
+            held: None,
+            current: 1,
+            park: config.park,
+        }
+    }
+
+    pub fn current(&self) -> u8 {
+        self.current
+    }
+
+    /// the page switched to for good: the current one, or while a momentary page is held, the one to go back to.
+    pub fn latched(&self) -> u8 {
+        self.held.map_or(self.current, |(_, back)| back)
+    }
+
+    /// the LED value for controls left behind by a page switch, if they are to be turned off.
+    pub fn park(&self) -> Option<u8> {
+        self.park
+    }
+
+    /// whether a mapping on `page` is currently active. mappings without a page are always active.
+    pub fn is_active(&self, page: Option<u8>) -> bool {
+        page.is_none_or(|p| p == self.current)
+    }
+
+    pub fn set(&mut self, page: u8) -> Response {
+        self.current = page.clamp(1, self.count);
+        info!("page {}/{}", self.current, self.count);
+        self.feedback()
+    }
+
+    /// page LEDs, and the current page over OSC.
+    pub fn feedback(&self) -> Response {
+        let mut response = Response::from(self.announce());
+        for (i, &num) in self.ctrl_out_nums.iter().enumerate() {
+            let lit = i + 1 == self.current as usize;
+            response.ctrl.push(CtrlResponse {
+                data: vec![num, if lit { 0x7f } else { 0x00 }],
+                color: None,
+                raw: false
+            });
+        }
+        response
+    }
+
+    fn announce(&self) -> OscResponse {
+        OscResponse {
+            addr: OSC_PAGE_ADDR.to_string(),
+            args: vec![OscType::Int(self.current as i32)]
+        }
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        if let Some(&(_, page)) = self.momentary.iter().find(|&&(n, _)| n == num) {
+            return Some(self.hold(num, page, val != 0x00));
+        }
+
+        let i = self.ctrl_in_nums.iter().position(|&n| n == num)?;
+        if val == 0x00 {
+            return Some(Response::new());
+        }
+
+        // switching while a page is held makes it the one to go back to
+        if let Some((_, ref mut back)) = self.held {
+            *back = (i as u8 + 1).clamp(1, self.count);
+            return Some(Response::new());
+        }
+        Some(self.set(i as u8 + 1))
+    }
+
+    /// goes to `page` while a momentary button is held, and back when it is released.
+    fn hold(&mut self, num: u8, page: u8, pressed: bool) -> Response {
+        match (pressed, self.held) {
+            (true, None) => {
+                self.held = Some((num, self.current));
+                self.set(page)
+            },
+            (false, Some((held, back))) if held == num => {
+                self.held = None;
+                self.set(back)
+            },
+            // another momentary button is already held
+            _ => Response::new()
+        }
+    }
+
+    pub fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        if msg.addr != OSC_PAGE_ADDR {
+            return None;
+        }
+
+        let Some(page) = msg.args.first().and_then(osc_number) else {
+            return Some(self.announce().into());
+        };
+        let page = page.round().clamp(1.0, u8::MAX as f32) as u8;
+
+        if page == self.current {
+            return Some(Response::new());
+        }
+
+        Some(self.set(page))
+    }
+}
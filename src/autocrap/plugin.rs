@@ -0,0 +1,182 @@
+use log::{info, warn};
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::PluginConfig,
+    interpreter::{CtrlResponse, OscResponse, Response}
+};
+
+const OSC_FOCUS_ADDR: &str = "/plugin/focus";
+const OSC_PARAM_ADDR: &str = "/plugin/param";
+const OSC_VALUE_ADDR: &str = "/plugin/value";
+const OSC_SET_ADDR: &str = "/plugin/set";
+
+/// the most parameters followed, so that a stray index doesn't make room for billions.
+const MAX_PARAMS: usize = 4096;
+
+#[derive(Clone, Debug)]
+struct Param {
+    name: String,
+    value: f32,
+}
+
+/// follows the parameters of whatever device a companion plugin reports as focused,
+/// assigning them to a row of encoders in order. longer parameter lists are split into pages.
+#[derive(Debug)]
+pub struct Plugin {
+    ctrl_in_num: u8,
+    ctrl_out_num: Option<u8>,
+    count: u8,
+    prev_page_ctrl_in_num: Option<u8>,
+    prev_page_ctrl_out_num: Option<u8>,
+    next_page_ctrl_in_num: Option<u8>,
+    next_page_ctrl_out_num: Option<u8>,
+    page_ctrl_out_nums: Vec<u8>,
+    device: Option<String>,
+    params: Vec<Option<Param>>,
+    page: usize,
+}
+
+impl Plugin {
+    pub fn new(config: &PluginConfig) -> Plugin {
+        Plugin {
+            ctrl_in_num: config.ctrl_in_num,
+            ctrl_out_num: config.ctrl_out_num,
+            count: config.count.max(1),
+            prev_page_ctrl_in_num: config.prev_page_ctrl_in_num,
+            prev_page_ctrl_out_num: config.prev_page_ctrl_out_num,
+            next_page_ctrl_in_num: config.next_page_ctrl_in_num,
+            next_page_ctrl_out_num: config.next_page_ctrl_out_num,
+            page_ctrl_out_nums: config.page_ctrl_out_nums.clone(),
+            device: None,
+            params: vec![],
+            page: 0,
+        }
+    }
+
+    fn page_count(&self) -> usize {
+        self.params.len().div_ceil(self.count as usize).max(1)
+    }
+
+    /// the parameter assigned to an encoder on the current page, if any.
+    fn param_index(&self, num: u8) -> Option<usize> {
+        let j = num.checked_sub(self.ctrl_in_num)?;
+        if j >= self.count {
+            return None;
+        }
+        let i = self.page * self.count as usize + j as usize;
+        self.params.get(i)?.as_ref()?;
+        Some(i)
+    }
+
+    /// LED ring feedback for a parameter, if it is on the current page.
+    fn led(&self, i: usize) -> Option<CtrlResponse> {
+        let j = i.checked_sub(self.page * self.count as usize)?;
+        if j >= self.count as usize {
+            return None;
+        }
+        let num = self.ctrl_out_num?.checked_add(j as u8)?;
+        let val = self.params.get(i)
+            .and_then(|p| p.as_ref())
+            .map(|p| (p.value.clamp(0.0, 1.0) * 127.0).round() as u8)
+            .unwrap_or(0x00);
+        Some(CtrlResponse { data: vec![num, val], color: None, raw: false })
+    }
+
+    /// LED feedback for the whole current page, including page navigation.
+    fn leds(&self) -> Response {
+        let first = self.page * self.count as usize;
+        let mut response = Response::new();
+        response.ctrl = (first..first + self.count as usize).filter_map(|i| self.led(i)).collect();
+
+        let page_leds = [
+            (self.prev_page_ctrl_out_num, self.page > 0),
+            (self.next_page_ctrl_out_num, self.page + 1 < self.page_count()),
+        ].into_iter()
+            .filter_map(|(num, lit)| Some((num?, lit)))
+            .chain(self.page_ctrl_out_nums.iter().enumerate().map(|(p, &num)| (num, p == self.page)));
+        for (num, lit) in page_leds {
+            response.ctrl.push(CtrlResponse {
+                data: vec![num, if lit { 0x7f } else { 0x00 }],
+                color: None,
+                raw: false
+            });
+        }
+        response
+    }
+
+    fn set_page(&mut self, page: usize) -> Response {
+        self.page = page.min(self.page_count() - 1);
+        info!("plugin page {}/{}", self.page + 1, self.page_count());
+        self.leds()
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let pressed = val != 0x00;
+        if self.prev_page_ctrl_in_num == Some(num) {
+            return Some(if pressed { self.set_page(self.page.saturating_sub(1)) } else { Response::new() });
+        }
+        if self.next_page_ctrl_in_num == Some(num) {
+            return Some(if pressed { self.set_page(self.page + 1) } else { Response::new() });
+        }
+
+        let i = self.param_index(num)?;
+
+        let delta: i8 = if val < 0x40 { val as i8 } else { val as i8 + i8::MIN };
+        let param = self.params[i].as_mut()?;
+        param.value = (param.value + delta as f32 / 127.0).clamp(0.0, 1.0);
+
+        let set = OscResponse {
+            addr: OSC_SET_ADDR.to_string(),
+            args: vec![
+                OscType::Int(i as i32),
+                OscType::String(param.name.clone()),
+                OscType::Float(param.value)
+            ]
+        };
+
+        let mut response = Response::new();
+        response.ctrl = self.led(i).into_iter().collect();
+        response.plugin.push(set);
+        Some(response)
+    }
+
+    /// handles a message from the companion plugin.
+    pub fn handle_message(&mut self, msg: &OscMessage) -> Option<Response> {
+        match (msg.addr.as_str(), msg.args.as_slice()) {
+            (OSC_FOCUS_ADDR, [OscType::String(device)]) => {
+                info!("plugin focus: {}", device);
+                self.device = Some(device.clone());
+                self.params.clear();
+                Some(self.set_page(0))
+            },
+            (OSC_PARAM_ADDR, [OscType::Int(index), OscType::String(name), OscType::Float(value)]) => {
+                let i = usize::try_from(*index).ok()?;
+                if i >= MAX_PARAMS {
+                    warn!("ignoring plugin param {}, only {} are followed", i, MAX_PARAMS);
+                    return Some(Response::new());
+                }
+                let page_count = self.page_count();
+                if self.params.len() <= i {
+                    self.params.resize(i + 1, None);
+                }
+                info!("{}: param {} -> {}", self.device.as_deref().unwrap_or("plugin"), i, name);
+                self.params[i] = Some(Param { name: name.clone(), value: *value });
+                if self.page_count() != page_count {
+                    // the page buttons may have become useful
+                    return Some(self.leds());
+                }
+                Some(self.led(i).map(Response::from).unwrap_or_else(Response::new))
+            },
+            (OSC_VALUE_ADDR, [OscType::Int(index), OscType::Float(value)]) => {
+                let i = usize::try_from(*index).ok()?;
+                let Some(Some(param)) = self.params.get_mut(i) else {
+                    return Some(Response::new());
+                };
+                param.value = *value;
+                Some(self.led(i).map(Response::from).unwrap_or_else(Response::new))
+            },
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::{error::Error, ops::RangeInclusive};
+
+use super::config::{Config, Framing, Mapping};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// what is known about a device: how to find and talk to it, and its controls, for catching mistakes in configs.
+#[derive(Debug)]
+pub struct Profile {
+    /// the name to give as `device` in configs.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// used when the config gives no `in_endpoint`/`out_endpoint`.
+    pub in_endpoint: u8,
+    pub out_endpoint: u8,
+    pub framing: Framing,
+    /// control numbers the device sends.
+    pub ctrl_in: &'static [RangeInclusive<u8>],
+    /// control numbers the device has LEDs or LED ring modes on.
+    pub ctrl_out: &'static [RangeInclusive<u8>],
+    /// control numbers which the device sends one after another, for 8-bit values.
+    pub sequences: &'static [&'static [u8]],
+    /// feedback which turns all LEDs off.
+    pub reset: &'static [u8],
+}
+
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        id: "novation-nocturn",
+        name: "Nocturn",
+        vendor_id: 0x1235,
+        product_id: 0x000a,
+        in_endpoint: 1,
+        out_endpoint: 2,
+        framing: Framing { skip: 0, num_offset: 0 },
+        // encoders, crossfader, speed dial, touch sensors and buttons
+        ctrl_in: &[64..=74, 81..=83, 96..=103, 112..=127],
+        // encoder rings, their modes, the speed dial ring and buttons
+        ctrl_out: &[64..=80, 112..=127],
+        sequences: &[&[72, 73]],
+        reset: &[0xb0, 0x00, 0x00],
+    },
+];
+
+/// the profile of the device a config is for, if it is a known USB device.
+pub fn find(config: &Config) -> Option<&'static Profile> {
+    if config.midi_device.is_some() {
+        return None;
+    }
+    match config.device {
+        Some(ref id) => PROFILES.iter().find(|p| p.id == id.as_str()),
+        None => PROFILES.iter().find(|p| (Some(p.vendor_id), Some(p.product_id)) == (config.vendor_id, config.product_id))
+    }
+}
+
+/// the vendor and product ID of the device a config is for. IDs given in the config win over `device`.
+pub fn ids(config: &Config) -> Result<(u16, u16)> {
+    if let (Some(vendor_id), Some(product_id)) = (config.vendor_id, config.product_id) {
+        return Ok((vendor_id, product_id));
+    }
+    let Some(ref id) = config.device else {
+        return Err("the config needs either a device or a vendor_id and product_id".into());
+    };
+    let Some(profile) = PROFILES.iter().find(|p| p.id == id.as_str()) else {
+        let known: Vec<&str> = PROFILES.iter().map(|p| p.id).collect();
+        return Err(format!("unknown device {}, known devices are: {}", id, known.join(", ")).into());
+    };
+    Ok((config.vendor_id.unwrap_or(profile.vendor_id), config.product_id.unwrap_or(profile.product_id)))
+}
+
+/// warnings about things in a config which are allowed, but unlikely to be what was meant.
+pub fn lint(config: &Config) -> Vec<String> {
+    let mappings: Vec<Mapping> = config.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+    let mut lints = vec![];
+
+    if let Some(profile) = find(config) {
+        for mapping in &mappings {
+            for num in mapping.ctrl_in_nums() {
+                if !profile.ctrl_in.iter().any(|r| r.contains(&num)) {
+                    lints.push(format!("{} reads control {}, which the {} never sends", mapping.name, num, profile.name));
+                }
+            }
+            for num in mapping.ctrl_out_num.into_iter().chain(mapping.ctrl_mode_num) {
+                if !profile.ctrl_out.iter().any(|r| r.contains(&num)) {
+                    lints.push(format!("{} writes control {}, which the {} has no LED for", mapping.name, num, profile.name));
+                }
+            }
+            if let Some(ref sequence) = mapping.ctrl_in_sequence {
+                if !profile.sequences.contains(&sequence.as_slice()) {
+                    lints.push(format!("{} reads the sequence {:?}, which the {} never sends", mapping.name, sequence, profile.name));
+                }
+            }
+        }
+    }
+
+    for (i, a) in mappings.iter().enumerate() {
+        let Some(num) = a.ctrl_out_num else {
+            continue;
+        };
+        for b in mappings[i+1..].iter().filter(|b| b.ctrl_out_num == Some(num)) {
+            let same_page = a.page.is_none() || b.page.is_none() || a.page == b.page;
+            // never active at the same time
+            let exclusive = matches!((&a.when, &b.when), (Some(x), Some(y)) if x.control == y.control && x.is != y.is);
+            if same_page && !exclusive {
+                lints.push(format!("{} and {} both drive LED {}", a.name, b.name, num));
+            }
+        }
+    }
+
+    lints
+}
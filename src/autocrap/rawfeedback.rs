@@ -0,0 +1,139 @@
+use std::time::Instant;
+
+use log::warn;
+use rosc::OscMessage;
+
+use super::{
+    config::{Mapping, RawFeedback},
+    interpreter::{CtrlLogic, CtrlResponse, Response},
+    lfo::Lfo
+};
+
+/// wraps a mapping with `raw_feedback`, adding its messages, filled in with the mapping's value, to the
+/// feedback on the way out.
+#[derive(Debug)]
+pub struct RawFeedbackLogic {
+    inner: Box<dyn CtrlLogic>,
+    name: String,
+    raw_feedback: Vec<RawFeedback>,
+    /// the value the `OnValue` messages were last sent for.
+    sent: Option<u8>,
+}
+
+impl RawFeedbackLogic {
+    /// wraps `inner`, or returns it as is if the mapping has no raw feedback or no value to fill it in with.
+    pub fn wrap(inner: Box<dyn CtrlLogic>, mapping: &Mapping) -> Box<dyn CtrlLogic> {
+        if mapping.raw_feedback.is_empty() {
+            return inner;
+        }
+        if inner.value(&mapping.name).is_none() {
+            warn!("mapping {} has no value, ignoring its raw_feedback", mapping.name);
+            return inner;
+        }
+
+        let raw_feedback = mapping.raw_feedback.iter()
+            .filter(|raw| {
+                let ok = raw.render(0x00).is_some() && raw.render(0x7f).is_some();
+                if !ok {
+                    warn!("mapping {}: ignoring raw_feedback {:?}, which is not all bytes and {{value}} placeholders", mapping.name, raw.template());
+                }
+                ok
+            })
+            .cloned()
+            .collect();
+        Box::new(RawFeedbackLogic { inner, name: mapping.name.clone(), raw_feedback, sent: None })
+    }
+
+    fn messages(&self, val: u8, moved: bool, changed: bool) -> Vec<CtrlResponse> {
+        self.raw_feedback.iter()
+            .filter(|raw| match raw {
+                RawFeedback::OnCtrl(_) => moved,
+                RawFeedback::OnValue(_) => changed,
+            })
+            .filter_map(|raw| raw.render(val))
+            .map(|data| CtrlResponse { data, color: None, raw: true })
+            .collect()
+    }
+
+    fn add_messages(&mut self, response: Option<Response>, moved: bool) -> Option<Response> {
+        let mut response = response?;
+        let Some(val) = self.inner.value(&self.name) else {
+            return Some(response);
+        };
+
+        let changed = self.sent != Some(val);
+        self.sent = Some(val);
+        response.ctrl.extend(self.messages(val, moved, changed));
+        Some(response)
+    }
+}
+
+impl CtrlLogic for RawFeedbackLogic {
+    fn from_mapping(_mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        // only made by wrapping another logic
+        None
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let response = self.inner.handle_ctrl(num, val);
+        self.add_messages(response, true)
+    }
+
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        let response = self.inner.handle_osc(msg);
+        self.add_messages(response, false)
+    }
+
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        let response = self.inner.handle_midi(msg);
+        self.add_messages(response, false)
+    }
+
+    fn init(&mut self) -> Vec<Response> {
+        self.inner.init().into_iter().filter_map(|r| self.add_messages(Some(r), false)).collect()
+    }
+
+    fn tick(&mut self, now: Instant, lfos: &[Lfo]) -> Option<Response> {
+        let response = self.inner.tick(now, lfos);
+        self.add_messages(response, false)
+    }
+
+    fn ticks(&self) -> bool {
+        self.inner.ticks()
+    }
+
+    fn apply(&mut self, name: &str, val: u8) -> Option<Response> {
+        let response = self.inner.apply(name, val);
+        self.add_messages(response, false)
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        let mut leds = self.inner.leds();
+        if let Some(val) = self.inner.value(&self.name) {
+            self.sent = Some(val);
+            leds.extend(self.messages(val, false, true));
+        }
+        leds
+    }
+
+    fn led(&mut self, name: &str, val: u8) -> Option<Vec<CtrlResponse>> {
+        let mut leds = self.inner.led(name, val)?;
+        if name == self.name {
+            leds.extend(self.messages(val, false, true));
+        }
+        Some(leds)
+    }
+
+    fn is_in_state(&self, name: &str, is: u8) -> Option<bool> {
+        self.inner.is_in_state(name, is)
+    }
+
+    fn value(&self, name: &str) -> Option<u8> {
+        self.inner.value(name)
+    }
+
+    #[cfg(feature = "osc")]
+    fn osc_value(&self, name: &str) -> Option<f32> {
+        self.inner.osc_value(name)
+    }
+}
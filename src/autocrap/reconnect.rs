@@ -0,0 +1,90 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant}
+};
+
+use log::{error, info};
+
+use super::config::{OnFail, ReconnectConfig};
+
+/// the error for when something did not come back within `max_attempts`, and autocrap should stop.
+#[derive(Debug)]
+pub struct GaveUp(String);
+
+impl fmt::Display for GaveUp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for GaveUp {}
+
+/// exponential backoff between attempts to get back something that went away.
+#[derive(Debug)]
+pub struct Reconnect {
+    what: String,
+    config: ReconnectConfig,
+    attempts: u32,
+    next_attempt: Option<Instant>,
+}
+
+impl Reconnect {
+    /// `what` names the thing being reconnected to in log messages.
+    pub fn new(what: &str, config: &ReconnectConfig) -> Reconnect {
+        Reconnect {
+            what: what.to_string(),
+            config: config.clone(),
+            attempts: 0,
+            next_attempt: None,
+        }
+    }
+
+    /// how long until the next attempt is due.
+    pub fn wait(&self, now: Instant) -> Duration {
+        self.next_attempt.map_or(Duration::ZERO, |t| t.saturating_duration_since(now))
+    }
+
+    /// whether the next attempt is due.
+    pub fn ready(&self, now: Instant) -> bool {
+        self.wait(now).is_zero()
+    }
+
+    /// notes a failed attempt and schedules the next one.
+    pub fn failed(&mut self, now: Instant) -> Result<(), GaveUp> {
+        self.attempts = self.attempts.saturating_add(1);
+        if self.config.max_attempts == Some(self.attempts) {
+            let message = format!("{} did not come back after {} attempts", self.what, self.attempts);
+            match self.config.on_fail {
+                OnFail::Exit => return Err(GaveUp(message)),
+                OnFail::KeepTrying => error!("{}, still trying", message),
+            }
+        }
+
+        self.next_attempt = Some(now + self.delay());
+        Ok(())
+    }
+
+    /// notes a successful attempt, so that the next outage starts over with a short delay.
+    pub fn succeeded(&mut self) {
+        if self.attempts > 0 {
+            info!("{} is back after {} attempts", self.what, self.attempts);
+        }
+        self.attempts = 0;
+        self.next_attempt = None;
+    }
+
+    fn delay(&self) -> Duration {
+        let doublings = self.attempts.saturating_sub(1).min(32);
+        let base = (self.config.delay_ms as f64 * 2f64.powi(doublings as i32)).min(self.config.max_delay_ms as f64);
+        let jitter = (random() * 2.0 - 1.0) * self.config.jitter.clamp(0.0, 1.0) as f64;
+        Duration::from_millis((base * (1.0 + jitter)) as u64)
+    }
+}
+
+/// a number in [0, 1), without pulling in a dependency. every `RandomState` has fresh keys.
+pub fn random() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / (u64::MAX as f64 + 1.0)
+}
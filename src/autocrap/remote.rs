@@ -0,0 +1,235 @@
+use std::{
+    io::{self, BufReader, ErrorKind, Read, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex
+    },
+    thread,
+    time::{Duration, Instant}
+};
+
+use log::{debug, info, warn};
+
+use super::{
+    config::Config,
+    device::{DeviceBackend, Result},
+    reconnect::Reconnect
+};
+
+/// how long the server waits for device data before checking whether the client is still there.
+const SERVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// a device attached to another machine running `autocrap serve`.
+///
+/// control events arrive as a stream of (control number, value) pairs. feedback is sent as frames,
+/// each prefixed with its length in a single byte.
+#[derive(Debug)]
+pub struct RemoteDevice {
+    addr: String,
+    reader: Mutex<RemoteReader>,
+    writer: Mutex<Option<TcpStream>>,
+    reconnected: AtomicBool,
+}
+
+#[derive(Debug)]
+struct RemoteReader {
+    stream: Option<TcpStream>,
+    pending: Vec<u8>,
+    reconnect: Reconnect,
+}
+
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+impl RemoteDevice {
+    fn disconnect(&self, reader: &mut RemoteReader) {
+        reader.stream = None;
+        reader.pending.clear();
+        *self.writer.lock().unwrap() = None;
+    }
+}
+
+impl DeviceBackend for RemoteDevice {
+    fn open(config: &Config) -> Result<Option<RemoteDevice>> {
+        let Some(ref addr) = config.remote else {
+            return Ok(None);
+        };
+
+        let stream = connect(addr).map_err(|e| format!("could not connect to remote device {}: {}", addr, e))?;
+        info!("connected to remote device {}", addr);
+        Ok(Some(RemoteDevice {
+            addr: addr.clone(),
+            writer: Mutex::new(Some(stream.try_clone()?)),
+            reader: Mutex::new(RemoteReader {
+                stream: Some(stream),
+                pending: vec![],
+                reconnect: Reconnect::new(&format!("remote device {}", addr), &config.reconnect),
+            }),
+            reconnected: AtomicBool::new(false),
+        }))
+    }
+
+    fn read_events(&self, timeout: Duration) -> Result<Vec<[u8; 2]>> {
+        let mut reader = self.reader.lock().unwrap();
+
+        if reader.stream.is_none() {
+            let wait = reader.reconnect.wait(Instant::now());
+            if !wait.is_zero() {
+                thread::sleep(wait.min(timeout));
+                return Ok(vec![]);
+            }
+
+            let stream = match connect(&self.addr) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("could not reconnect to remote device {}: {}", self.addr, e);
+                    reader.reconnect.failed(Instant::now())?;
+                    return Ok(vec![]);
+                }
+            };
+            reader.reconnect.succeeded();
+            info!("reconnected to remote device {}", self.addr);
+            *self.writer.lock().unwrap() = Some(stream.try_clone()?);
+            reader.stream = Some(stream);
+            self.reset()?;
+            self.reconnected.store(true, Ordering::Relaxed);
+        }
+
+        let Some(ref mut stream) = reader.stream else {
+            return Ok(vec![]);
+        };
+        // a zero timeout would mean blocking forever
+        stream.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+
+        let mut buf = [0u8; 256];
+        let num_bytes = match stream.read(&mut buf) {
+            Ok(0) => {
+                warn!("lost connection to remote device {}", self.addr);
+                self.disconnect(&mut reader);
+                return Ok(vec![]);
+            },
+            Ok(num_bytes) => num_bytes,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(vec![]),
+            Err(e) => {
+                warn!("lost connection to remote device {}: {}", self.addr, e);
+                self.disconnect(&mut reader);
+                return Ok(vec![]);
+            }
+        };
+
+        reader.pending.extend_from_slice(&buf[..num_bytes]);
+        let complete = reader.pending.len() / 2 * 2;
+        Ok(reader.pending.drain(..complete)
+            .collect::<Vec<_>>()
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect())
+    }
+
+    fn write_feedback(&self, data: &[u8]) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        let Some(ref mut stream) = *writer else {
+            debug!("remote device {} not connected, dropping feedback {:02x?}", self.addr, data);
+            return Ok(());
+        };
+
+        let len = u8::try_from(data.len()).map_err(|_| "feedback too long for remote device")?;
+        if let Err(e) = stream.write_all(&[&[len][..], data].concat()) {
+            warn!("could not send feedback to remote device {}: {}", self.addr, e);
+            *writer = None;
+        }
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        // the server passes this on to the real device
+        self.write_feedback(&[0xb0, 0x00, 0x00])
+    }
+
+    fn reconnected(&self) -> bool {
+        self.reconnected.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// makes `device` available to one `RemoteDevice` client at a time. clients from other machines than this one
+/// need to be in `allow`, which must be given to listen beyond localhost.
+pub fn serve(device: &dyn DeviceBackend, addr: SocketAddr, allow: &[IpAddr]) -> Result<()> {
+    if !addr.ip().is_loopback() && allow.is_empty() {
+        return Err(format!("listening on {} needs --allow with the addresses of the machines to let in", addr).into());
+    }
+
+    let listener = TcpListener::bind(addr)?;
+    info!("serving device on {}", addr);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("could not accept connection: {}", e);
+                continue;
+            }
+        };
+        let peer = stream.peer_addr()?;
+        let ip = peer.ip().to_canonical();
+        if !ip.is_loopback() && !allow.contains(&ip) {
+            warn!("refusing client {}, which is not allowed", peer);
+            let _ = stream.shutdown(Shutdown::Both);
+            continue;
+        }
+        info!("client connected: {}", peer);
+
+        if let Err(e) = serve_client(device, &stream) {
+            warn!("client {}: {}", peer, e);
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+
+        info!("client disconnected: {}", peer);
+        device.reset()?;
+    }
+
+    Ok(())
+}
+
+fn serve_client(device: &dyn DeviceBackend, stream: &TcpStream) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let connected = AtomicBool::new(true);
+
+    thread::scope(|s| {
+        thread::Builder::new().name("remote-feedback".to_string()).spawn_scoped(s, || {
+            let mut reader = BufReader::new(stream);
+            while let Ok(data) = read_frame(&mut reader) {
+                if let Err(e) = device.write_feedback(&data) {
+                    warn!("could not write feedback: {}", e);
+                }
+            }
+            connected.store(false, Ordering::Relaxed);
+        })?;
+
+        let result = forward_events(device, stream, &connected);
+        // unblock the feedback thread if we are the ones giving up
+        let _ = stream.shutdown(Shutdown::Both);
+        result
+    })
+}
+
+fn forward_events(device: &dyn DeviceBackend, mut stream: &TcpStream, connected: &AtomicBool) -> Result<()> {
+    while connected.load(Ordering::Relaxed) {
+        let events = device.read_events(SERVE_POLL_INTERVAL)?;
+        if !events.is_empty() {
+            stream.write_all(&events.concat())?;
+        }
+    }
+    Ok(())
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8];
+    reader.read_exact(&mut len)?;
+    let mut data = vec![0u8; len[0] as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
@@ -0,0 +1,201 @@
+use std::{error::Error, time::Duration};
+
+use rosc::{OscMessage, OscType};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{
+    config::Config,
+    determinism,
+    interpreter::{Interpreter, Response}
+};
+
+/// the same as the ticker when it is busy.
+const TICK_INTERVAL: Duration = Duration::from_millis(1);
+/// how long to keep ticking after the last event, for pulses, blinks and the like to finish.
+const TAIL: Duration = Duration::from_millis(1000);
+const SEED: u64 = 0x2545f4914f6cdd1d;
+/// how many differing lines are listed before giving up.
+const MAX_DIFFERENCES: usize = 10;
+
+/// something coming into autocrap, at some point in a session.
+#[derive(Debug, Deserialize)]
+pub struct Event {
+    /// milliseconds since the start of the session.
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub input: Input,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Input {
+    /// `[num, value]` from the device.
+    Ctrl([u8; 2]),
+    Osc {
+        addr: String,
+        #[serde(default)]
+        args: Vec<Value>,
+    },
+    Midi(Vec<u8>),
+}
+
+/// reads a session with one JSON event per line. blank lines are skipped.
+pub fn parse(text: &str) -> Result<Vec<Event>, Box<dyn Error>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e).into()))
+        .collect()
+}
+
+/// runs a session through a fresh interpreter, on a fixed clock and with seeded random numbers, ticking as
+/// often as the ticker would. returns a line for everything sent, which is the same on every run.
+pub fn run(config: &Config, events: &[Event]) -> Result<Vec<String>, Box<dyn Error>> {
+    determinism::enable(SEED);
+    let mut interpreter = Interpreter::new(config);
+    let mut lines = vec![];
+    let mut at = Duration::ZERO;
+
+    for response in interpreter.init() {
+        describe(&mut lines, at, &response);
+    }
+    for event in events {
+        let event_at = Duration::from_millis(event.at_ms).max(at);
+        tick_until(&mut interpreter, &mut lines, &mut at, event_at);
+
+        let response = match event.input {
+            Input::Ctrl([num, val]) => interpreter.handle_ctrl(num, val),
+            Input::Osc { ref addr, ref args } => {
+                let args = args.iter().map(osc_arg).collect::<Result<_, _>>()?;
+                interpreter.handle_osc(&OscMessage { addr: addr.clone(), args })
+            },
+            Input::Midi(ref msg) => interpreter.handle_midi(msg),
+        };
+        if let Some(response) = response {
+            describe(&mut lines, at, &response);
+        }
+    }
+    let end = at + TAIL;
+    tick_until(&mut interpreter, &mut lines, &mut at, end);
+
+    Ok(lines)
+}
+
+/// the lines of a run which differ from the expected output, at most `MAX_DIFFERENCES` of them.
+pub fn differences(lines: &[String], expected: &str) -> Vec<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let mut differences: Vec<String> = (0..lines.len().max(expected.len()))
+        .filter_map(|i| {
+            let (got, want) = (lines.get(i).map(String::as_str), expected.get(i).copied());
+            (got != want).then(|| format!("line {}: expected {}, got {}", i + 1, want.unwrap_or("nothing"), got.unwrap_or("nothing")))
+        })
+        .collect();
+    if differences.len() > MAX_DIFFERENCES {
+        let more = differences.len() - MAX_DIFFERENCES;
+        differences.truncate(MAX_DIFFERENCES);
+        differences.push(format!("and {} more", more));
+    }
+    differences
+}
+
+fn tick_until(interpreter: &mut Interpreter, lines: &mut Vec<String>, at: &mut Duration, until: Duration) {
+    while *at + TICK_INTERVAL <= until {
+        *at += TICK_INTERVAL;
+        determinism::advance(TICK_INTERVAL);
+        for response in interpreter.tick(determinism::now()) {
+            describe(lines, *at, &response);
+        }
+    }
+    determinism::advance(until - *at);
+    *at = until;
+}
+
+fn osc_arg(value: &Value) -> Result<OscType, Box<dyn Error>> {
+    Ok(match value {
+        Value::Number(n) if n.is_i64() => OscType::Int(n.as_i64().unwrap_or_default() as i32),
+        Value::Number(n) => OscType::Float(n.as_f64().unwrap_or_default() as f32),
+        Value::String(s) => OscType::String(s.clone()),
+        Value::Bool(b) => OscType::Bool(*b),
+        _ => return Err(format!("unsupported OSC argument: {}", value).into())
+    })
+}
+
+fn describe(lines: &mut Vec<String>, at: Duration, response: &Response) {
+    let at = at.as_millis();
+    for ctrl in &response.ctrl {
+        match ctrl.color {
+            _ if ctrl.raw => lines.push(format!("{} raw {:02x?}", at, ctrl.data)),
+            Some([r, g, b]) => lines.push(format!("{} ctrl {:?} #{:02x}{:02x}{:02x}", at, ctrl.data, r, g, b)),
+            None => lines.push(format!("{} ctrl {:?}", at, ctrl.data)),
+        }
+    }
+    for motor in &response.motor {
+        lines.push(format!("{} motor {} {}", at, motor.num, motor.position));
+    }
+    for osc in &response.osc {
+        lines.push(format!("{} osc {} {:?}", at, osc.addr, osc.args));
+    }
+    for midi in &response.midi {
+        lines.push(format!("{} midi {:02x?}", at, midi.data));
+    }
+    for plugin in &response.plugin {
+        lines.push(format!("{} plugin {} {:?}", at, plugin.addr, plugin.args));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{differences, parse, run};
+
+    #[test]
+    fn sessions_match_expected() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut checked = 0;
+        for entry in fs::read_dir(root.join("sessions")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "jsonl") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            let config = serde_json::from_str(&fs::read_to_string(root.join("config").join(format!("{}.json", name))).unwrap()).unwrap();
+            let events = parse(&fs::read_to_string(&path).unwrap()).unwrap();
+            let expected = fs::read_to_string(path.with_extension("expected")).unwrap();
+            let lines = run(&config, &events).unwrap();
+            assert_eq!(differences(&lines, &expected), Vec::<String>::new(), "{}", name);
+            checked += 1;
+        }
+        assert!(checked > 0, "no sessions found");
+    }
+
+    #[test]
+    fn runs_are_identical() {
+        // with an lfo, a pulse and a randomize button, so that both the clock and chance show up
+        let config = serde_json::from_value(serde_json::json!({
+            "vendor_id": 4661,
+            "product_id": 10,
+            "interface": {"Osc": {"out_addr": "127.0.0.1:9901", "in_addr": "127.0.0.1:9902"}},
+            "lfos": [{"name": "wobble", "shape": "Sine", "rate": 3.0, "depth": 0.5}],
+            "group_actions": [{"name": "dice", "controls": ["knob"], "ctrl_in_num": 113, "action": "Randomize"}],
+            "mappings": [
+                {"Single": {"name": "knob", "ctrl_in_num": 64, "ctrl_out_num": 64, "lfo": "wobble",
+                    "ctrl_kind": {"Relative": {"mode": "Accumulate"}}}},
+                {"Single": {"name": "button", "ctrl_in_num": 112, "ctrl_out_num": 112, "pulse_ms": 50,
+                    "ctrl_kind": {"OnOff": {"mode": "Momentary"}}}}
+            ]
+        })).unwrap();
+        let events = parse(concat!(
+            r#"{"at_ms": 10, "ctrl": [64, 5]}"#, "\n",
+            r#"{"at_ms": 20, "ctrl": [112, 127]}"#, "\n",
+            r#"{"at_ms": 30, "osc": {"addr": "/knob", "args": [0.25]}}"#, "\n",
+            r#"{"at_ms": 40, "ctrl": [113, 127]}"#, "\n",
+        )).unwrap();
+
+        let first = run(&config, &events).unwrap();
+        let second = run(&config, &events).unwrap();
+        assert!(!first.is_empty());
+        assert_eq!(first.join("\n").into_bytes(), second.join("\n").into_bytes());
+    }
+}
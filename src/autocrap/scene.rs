@@ -0,0 +1,67 @@
+use log::info;
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::SceneConfig,
+    interpreter::{CtrlResponse, Response}
+};
+
+const OSC_SCENE_ADDR: &str = "/autocrap/scene";
+
+/// named sets of mapping values from the config, applied with a button or over OSC.
+#[derive(Debug)]
+pub struct Scenes {
+    scenes: Vec<SceneConfig>,
+    current: Option<usize>,
+}
+
+impl Scenes {
+    pub fn new(scenes: &[SceneConfig]) -> Scenes {
+        Scenes {
+            scenes: scenes.to_vec(),
+            current: None,
+        }
+    }
+
+    /// the scene triggered by a control, if any.
+    pub fn ctrl_index(&self, num: u8) -> Option<usize> {
+        self.scenes.iter().position(|s| s.ctrl_in_num == Some(num))
+    }
+
+    /// the scene named in an OSC message, if it is for us.
+    pub fn osc_index(&self, msg: &OscMessage) -> Option<Option<usize>> {
+        if msg.addr != OSC_SCENE_ADDR {
+            return None;
+        }
+
+        let Some(OscType::String(name)) = msg.args.first() else {
+            return Some(None);
+        };
+
+        Some(self.scenes.iter().position(|s| &s.name == name))
+    }
+
+    /// makes a scene current, returning the scene LEDs and the mapping values to apply.
+    pub fn select(&mut self, i: usize) -> (Response, Vec<(String, u8)>) {
+        info!("scene {}", self.scenes[i].name);
+        self.current = Some(i);
+
+        let mut response = Response::new();
+        for (j, scene) in self.scenes.iter().enumerate() {
+            let Some(num) = scene.ctrl_out_num else {
+                continue;
+            };
+
+            response.ctrl.push(CtrlResponse {
+                data: vec![num, if j == i { 0x7f } else { 0x00 }],
+                color: None,
+                raw: false
+            });
+        }
+
+        let values = self.scenes[i].values.iter()
+            .map(|(name, &val)| (name.clone(), val))
+            .collect();
+        (response, values)
+    }
+}
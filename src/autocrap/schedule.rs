@@ -0,0 +1,94 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime}
+};
+
+use log::warn;
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime};
+
+/// the timetag which means "now".
+const IMMEDIATELY: OscTime = OscTime { seconds: 0, fractional: 1 };
+
+/// seconds from the start of 1900, where OSC time begins, to the unix epoch.
+const UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// messages waiting at most, so that bundles far in the future can't pile up without end.
+const MAX_QUEUED: usize = 1024;
+
+/// messages from OSC bundles whose timetag is still in the future, kept until it arrives.
+#[derive(Debug)]
+pub struct Schedule {
+    tolerance: Duration,
+    /// by time, and then in the order they came in.
+    queue: BTreeMap<(SystemTime, u64), OscMessage>,
+    count: u64,
+}
+
+impl Schedule {
+    /// messages due within `tolerance` from now are applied right away.
+    pub fn new(tolerance: Duration) -> Schedule {
+        Schedule {
+            tolerance,
+            queue: BTreeMap::new(),
+            count: 0,
+        }
+    }
+
+    /// the messages of a bundle which are due now. the rest are kept for later.
+    pub fn bundle(&mut self, now: SystemTime, bundle: OscBundle) -> Vec<OscMessage> {
+        self.add(now, bundle);
+        self.due(now)
+    }
+
+    fn add(&mut self, now: SystemTime, bundle: OscBundle) {
+        let at = time(now, bundle.timetag);
+        for packet in bundle.content {
+            match packet {
+                OscPacket::Message(msg) => {
+                    if self.queue.len() >= MAX_QUEUED {
+                        warn!("too many scheduled OSC messages waiting, dropping {}", msg.addr);
+                        continue;
+                    }
+                    self.queue.insert((at, self.count), msg);
+                    self.count += 1;
+                },
+                // a nested bundle can't happen before the one it is in
+                OscPacket::Bundle(mut inner) => {
+                    if time(now, inner.timetag) < at {
+                        inner.timetag = bundle.timetag;
+                    }
+                    self.add(now, inner);
+                }
+            }
+        }
+    }
+
+    /// the messages whose time has come, in order.
+    pub fn due(&mut self, now: SystemTime) -> Vec<OscMessage> {
+        let mut due = vec![];
+        while let Some(entry) = self.queue.first_entry() {
+            if entry.key().0 > now + self.tolerance {
+                break;
+            }
+            due.push(entry.remove());
+        }
+        due
+    }
+
+    /// how long until the next message is due, if any are waiting.
+    pub fn next(&self, now: SystemTime) -> Option<Duration> {
+        self.queue.keys().next().map(|(at, _)| at.duration_since(now).unwrap_or_default())
+    }
+}
+
+/// when a timetag is due. "now", and anything before the unix epoch, is due right away.
+fn time(now: SystemTime, timetag: OscTime) -> SystemTime {
+    if timetag == IMMEDIATELY {
+        return now;
+    }
+    let Some(seconds) = (timetag.seconds as u64).checked_sub(UNIX_OFFSET) else {
+        return now;
+    };
+    let nanos = (timetag.fractional as u64 * 1_000_000_000) >> 32;
+    SystemTime::UNIX_EPOCH + Duration::new(seconds, nanos as u32)
+}
@@ -0,0 +1,64 @@
+use std::{io, net::UdpSocket};
+
+use super::config::SocketOptions;
+
+/// applies the configured options to an OSC socket.
+#[cfg(unix)]
+pub fn apply(sock: &UdpSocket, options: &SocketOptions) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let fd = sock.as_raw_fd();
+    if let Some(dscp) = options.dscp {
+        // the DSCP is the upper six bits of the TOS byte
+        let tos = (dscp.min(63) as libc::c_int) << 2;
+        setsockopt(fd, libc::IPPROTO_IP, libc::IP_TOS, &tos)?;
+        log::info!("osc socket dscp: {}", dscp);
+    }
+    if let Some(size) = options.send_buffer_size {
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_SNDBUF, &(size as libc::c_int))?;
+    }
+    if let Some(size) = options.recv_buffer_size {
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, &(size as libc::c_int))?;
+    }
+    if let Some(ref device) = options.device {
+        bind_to_device(fd, device)?;
+        log::info!("osc socket bound to {}", device);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(_sock: &UdpSocket, options: &SocketOptions) -> io::Result<()> {
+    if options.dscp.is_some() || options.send_buffer_size.is_some() || options.recv_buffer_size.is_some() || options.device.is_some() {
+        log::warn!("osc socket options are currently unsupported on non-unix systems");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn bind_to_device(fd: libc::c_int, device: &str) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, device.as_ptr() as *const libc::c_void, device.len() as libc::socklen_t)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn bind_to_device(_fd: libc::c_int, device: &str) -> io::Result<()> {
+    log::warn!("binding to network interface {} is only supported on Linux", device);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn setsockopt(fd: libc::c_int, level: libc::c_int, name: libc::c_int, value: &libc::c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(fd, level, name, value as *const libc::c_int as *const libc::c_void, size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path
+};
+
+use serde::{Deserialize, Serialize};
+
+/// what autocrap remembers across restarts, in the config's `state_file`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// the active page.
+    pub page: Option<u8>,
+}
+
+/// the state saved in `path`, or the default state if nothing has been saved yet.
+pub fn load(path: &Path) -> io::Result<State> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+        Err(e) => return Err(e)
+    };
+    serde_json::from_str(&text).map_err(io::Error::other)
+}
+
+/// saves the state to `path`. it is written next to it and moved into place, so that losing power
+/// halfway leaves the old state rather than a broken file.
+pub fn save(path: &Path, state: &State) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(serde_json::to_string(state).map_err(io::Error::other)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
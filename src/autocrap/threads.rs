@@ -0,0 +1,91 @@
+use std::io;
+
+use log::{info, warn};
+
+use super::config::ThreadConfig;
+
+/// the real-time priority asked for, low enough to stay behind the audio threads of a DAW.
+const REALTIME_PRIORITY: i32 = 10;
+
+/// applies the settings for the current thread. what the system refuses is only logged, as the thread can
+/// do its job without.
+pub fn configure(name: &str, config: &ThreadConfig) {
+    if !config.cpus.is_empty() {
+        match set_affinity(&config.cpus) {
+            Ok(()) => info!("{} thread pinned to cpus {:?}", name, config.cpus),
+            Err(e) => warn!("could not pin {} thread to cpus {:?}: {}", name, config.cpus, e),
+        }
+    }
+    if let Some(nice) = config.nice {
+        match set_nice(nice) {
+            Ok(()) => info!("{} thread niceness: {}", name, nice),
+            Err(e) => warn!("could not set niceness of {} thread to {}: {}", name, nice, e),
+        }
+    }
+    if config.realtime {
+        match set_realtime() {
+            Ok(()) => info!("{} thread has real-time priority {}", name, REALTIME_PRIORITY),
+            Err(e) => warn!("could not get real-time priority for {} thread, running it normally: {}", name, e),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_realtime() -> io::Result<()> {
+    let param = libc::sched_param { sched_priority: REALTIME_PRIORITY };
+    // unlike most calls, this returns the error rather than setting errno
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        e => Err(io::Error::from_raw_os_error(e))
+    }
+}
+
+#[cfg(not(unix))]
+fn set_realtime() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on unix systems"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    // CPU_SET panics for cpus which don't fit in the set
+    if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= libc::CPU_SETSIZE as usize) {
+        let message = format!("cpu {} is out of range, the highest is {}", cpu, libc::CPU_SETSIZE - 1);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        // 0 is the calling thread
+        libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// on Linux, niceness is per thread.
+#[cfg(target_os = "linux")]
+fn set_nice(nice: i32) -> io::Result<()> {
+    let result = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, tid, nice)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on Linux"))
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant}
+};
+
+use super::interpreter::{CtrlResponse, Response};
+
+/// limits how often LEDs are updated from host feedback, such as dense automation, so that it
+/// doesn't flood the device. updates arriving too soon are held back, and only the latest is sent.
+#[derive(Debug)]
+pub struct Throttle {
+    interval: Duration,
+    /// when each LED, by control number, was last written.
+    sent: BTreeMap<u8, Instant>,
+    pending: BTreeMap<u8, CtrlResponse>,
+}
+
+impl Throttle {
+    /// `max_rate` is the most updates per second for each LED.
+    pub fn new(max_rate: f32) -> Throttle {
+        Throttle {
+            interval: Duration::from_secs_f32(1.0 / max_rate.max(0.1)),
+            sent: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// holds back the LED output of feedback for LEDs which were written too recently.
+    pub fn feedback(&mut self, now: Instant, response: &mut Response) {
+        let interval = self.interval;
+        let (sent, pending) = (&mut self.sent, &mut self.pending);
+        response.ctrl.retain(|ctrl| {
+            let (&[num, _], false) = (&ctrl.data[..], ctrl.raw) else {
+                return true;
+            };
+
+            match sent.get(&num) {
+                Some(&at) if now < at + interval => {
+                    pending.insert(num, ctrl.clone());
+                    false
+                },
+                _ => {
+                    sent.insert(num, now);
+                    pending.remove(&num);
+                    true
+                }
+            }
+        });
+    }
+
+    /// notes LED output which was sent right away, such as from turning a knob, replacing held back feedback.
+    pub fn direct(&mut self, now: Instant, response: &Response) {
+        for ctrl in response.ctrl.iter().filter(|c| !c.raw) {
+            if let [num, _] = ctrl.data[..] {
+                self.sent.insert(num, now);
+                self.pending.remove(&num);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// the held back updates which are now due.
+    pub fn tick(&mut self, now: Instant) -> Option<Response> {
+        let due: Vec<u8> = self.pending.keys()
+            .copied()
+            .filter(|num| self.sent.get(num).is_none_or(|&at| now >= at + self.interval))
+            .collect();
+        if due.is_empty() {
+            return None;
+        }
+
+        let mut response = Response::new();
+        for num in due {
+            if let Some(ctrl) = self.pending.remove(&num) {
+                self.sent.insert(num, now);
+                response.ctrl.push(ctrl);
+            }
+        }
+        Some(response)
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use rosc::OscType;
+
+use super::{
+    config::{CtrlKind, Mapping, TouchConfig},
+    interpreter::{OscResponse, Response}
+};
+
+/// which controls are being actuated, announced as `/autocrap/touch/<name>` with 1 or 0.
+///
+/// buttons are touched while held. other controls, such as encoders, count as touched from their
+/// first event until they have been left alone for a while.
+#[derive(Debug)]
+pub struct Touch {
+    release_after: Duration,
+    ctrls: Vec<TouchCtrl>,
+}
+
+#[derive(Debug)]
+struct TouchCtrl {
+    name: String,
+    page: Option<u8>,
+    nums: Vec<u8>,
+    button: bool,
+    /// when the control was last actuated, while it is touched.
+    touched: Option<Instant>,
+}
+
+impl Touch {
+    pub fn new(config: &TouchConfig, mappings: &[Mapping]) -> Touch {
+        Touch {
+            release_after: Duration::from_millis(config.release_ms),
+            ctrls: mappings.iter()
+                .filter(|m| !m.ctrl_in_nums().is_empty())
+                .map(|m| TouchCtrl {
+                    name: m.name.clone(),
+                    page: m.page,
+                    nums: m.ctrl_in_nums(),
+                    button: matches!(m.ctrl_kind, CtrlKind::OnOff { .. }),
+                    touched: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8, now: Instant, is_active: impl Fn(Option<u8>) -> bool) -> Option<Response> {
+        let mut response = Response::new();
+        for ctrl in self.ctrls.iter_mut().filter(|c| c.nums.contains(&num) && is_active(c.page)) {
+            let touched = !ctrl.button || val != 0x00;
+            if touched != ctrl.touched.is_some() {
+                response.osc.push(ctrl.announce(touched));
+            }
+            ctrl.touched = touched.then_some(now);
+        }
+
+        (!response.is_empty()).then_some(response)
+    }
+
+    /// releases controls which have not been actuated for a while.
+    pub fn tick(&mut self, now: Instant) -> Option<Response> {
+        let mut response = Response::new();
+        for ctrl in self.ctrls.iter_mut().filter(|c| !c.button) {
+            let Some(touched) = ctrl.touched else {
+                continue;
+            };
+
+            if now.duration_since(touched) >= self.release_after {
+                ctrl.touched = None;
+                response.osc.push(ctrl.announce(false));
+            }
+        }
+
+        (!response.is_empty()).then_some(response)
+    }
+}
+
+impl TouchCtrl {
+    fn announce(&self, touched: bool) -> OscResponse {
+        OscResponse {
+            addr: format!("/autocrap/touch/{}", self.name),
+            args: vec![OscType::Int(touched as i32)]
+        }
+    }
+}
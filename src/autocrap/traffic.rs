@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use super::{
+    config::{TrafficDirection, TrafficLedConfig},
+    interpreter::{CtrlResponse, Response}
+};
+
+/// how long an LED stays lit after a message.
+const FLASH: Duration = Duration::from_millis(60);
+
+#[derive(Debug)]
+struct TrafficLed {
+    ctrl_out_num: u8,
+    controls: Vec<String>,
+    direction: TrafficDirection,
+    /// when to switch off, while lit.
+    lit_until: Option<Instant>,
+}
+
+/// LEDs which flash when MIDI/OSC for their mappings goes in or out, to see at a glance
+/// whether anything is getting through.
+#[derive(Debug)]
+pub struct TrafficLeds {
+    leds: Vec<TrafficLed>,
+}
+
+impl TrafficLeds {
+    pub fn new(configs: &[TrafficLedConfig]) -> TrafficLeds {
+        TrafficLeds {
+            leds: configs.iter()
+                .map(|config| TrafficLed {
+                    ctrl_out_num: config.ctrl_out_num,
+                    controls: config.controls.clone(),
+                    direction: config.direction,
+                    lit_until: None,
+                })
+                .collect()
+        }
+    }
+
+    /// notes a message for the mapping called `name`, lighting the LEDs which were off.
+    pub fn traffic(&mut self, name: Option<&str>, incoming: bool, now: Instant) -> Vec<CtrlResponse> {
+        let mut lit = vec![];
+        for led in &mut self.leds {
+            let direction = match led.direction {
+                TrafficDirection::In => incoming,
+                TrafficDirection::Out => !incoming,
+                TrafficDirection::Both => true,
+            };
+            let watched = led.controls.is_empty() || name.is_some_and(|name| led.controls.iter().any(|c| c == name));
+            if !direction || !watched {
+                continue;
+            }
+
+            if led.lit_until.is_none() {
+                lit.push(CtrlResponse { data: vec![led.ctrl_out_num, 0x7f], color: None, raw: false });
+            }
+            led.lit_until = Some(now + FLASH);
+        }
+        lit
+    }
+
+    /// whether there are no LEDs to flash, so nothing needs ticking.
+    pub fn is_empty(&self) -> bool {
+        self.leds.is_empty()
+    }
+
+    /// switches off the LEDs whose flash is over.
+    pub fn tick(&mut self, now: Instant) -> Option<Response> {
+        let mut response = Response::new();
+        for led in &mut self.leds {
+            if led.lit_until.is_some_and(|until| now >= until) {
+                led.lit_until = None;
+                response.ctrl.push(CtrlResponse { data: vec![led.ctrl_out_num, 0x00], color: None, raw: false });
+            }
+        }
+        (!response.ctrl.is_empty()).then_some(response)
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    slice,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex
+    },
+    time::{Duration, Instant}
+};
+
+use log::warn;
+use rusb::{ffi, Context, DeviceHandle, UsbContext};
+
+/// how long to wait for cancelled transfers to come back when shutting down.
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// interrupt IN transfers which are kept in flight all the time, so that the host keeps polling
+/// the device while earlier packets are still being processed. completed packets are queued until read.
+#[derive(Debug)]
+pub struct TransferQueue {
+    context: Context,
+    transfers: Vec<*mut ffi::libusb_transfer>,
+    buffers: Vec<Box<[u8]>>,
+    shared: *const Shared,
+}
+
+/// state shared with the completion callback.
+#[derive(Debug, Default)]
+struct Shared {
+    completed: Mutex<VecDeque<rusb::Result<Vec<u8>>>>,
+    stopping: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+// the transfers and the shared state are only touched through libusb, the mutex and atomics, and on drop
+unsafe impl Send for TransferQueue {}
+unsafe impl Sync for TransferQueue {}
+
+impl TransferQueue {
+    /// submits `count` transfers of `packet_size` bytes each on the endpoint at `address`.
+    pub fn new(handle: &DeviceHandle<Context>, address: u8, packet_size: usize, count: usize) -> rusb::Result<TransferQueue> {
+        let mut queue = TransferQueue {
+            context: handle.context().clone(),
+            transfers: vec![],
+            buffers: vec![],
+            shared: Box::into_raw(Box::default()),
+        };
+
+        for _ in 0..count.max(1) {
+            let transfer = unsafe { ffi::libusb_alloc_transfer(0) };
+            if transfer.is_null() {
+                return Err(rusb::Error::NoMem);
+            }
+            queue.transfers.push(transfer);
+
+            let mut buffer = vec![0u8; packet_size].into_boxed_slice();
+            unsafe {
+                ffi::libusb_fill_interrupt_transfer(
+                    transfer,
+                    handle.as_raw(),
+                    address,
+                    buffer.as_mut_ptr(),
+                    buffer.len() as i32,
+                    transfer_completed,
+                    queue.shared as *mut c_void,
+                    0
+                );
+            }
+            queue.buffers.push(buffer);
+
+            let shared = unsafe { &*queue.shared };
+            shared.in_flight.fetch_add(1, Ordering::SeqCst);
+            if unsafe { ffi::libusb_submit_transfer(transfer) } != 0 {
+                shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+                return Err(rusb::Error::Io);
+            }
+        }
+
+        Ok(queue)
+    }
+
+    /// waits up to `timeout` for packets on any of the queues, returning every one that has arrived so far
+    /// along with the index of its queue. returns nothing if the timeout passes.
+    ///
+    /// the queues must belong to the same device, whose events are all handled together.
+    pub fn read(queues: &[TransferQueue], timeout: Duration) -> rusb::Result<Vec<(usize, Vec<u8>)>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut packets = vec![];
+            for (i, queue) in queues.iter().enumerate() {
+                packets.extend(queue.take()?.into_iter().map(|packet| (i, packet)));
+            }
+            if !packets.is_empty() {
+                return Ok(packets);
+            }
+
+            let now = Instant::now();
+            let Some(first) = queues.first() else {
+                return Ok(vec![]);
+            };
+            if now >= deadline {
+                return Ok(vec![]);
+            }
+            first.handle_events(deadline - now)?;
+        }
+    }
+
+    /// the packets which have arrived, without waiting.
+    fn take(&self) -> rusb::Result<Vec<Vec<u8>>> {
+        let shared = unsafe { &*self.shared };
+        let mut completed = shared.completed.lock().unwrap();
+        if let Some(Err(e)) = completed.front() {
+            let e = *e;
+            completed.pop_front();
+            return Err(e);
+        }
+        if completed.is_empty() && shared.in_flight.load(Ordering::SeqCst) == 0 {
+            return Err(rusb::Error::NoDevice);
+        }
+
+        let mut packets = vec![];
+        while let Some(Ok(_)) = completed.front() {
+            if let Some(Ok(packet)) = completed.pop_front() {
+                packets.push(packet);
+            }
+        }
+        Ok(packets)
+    }
+
+    fn handle_events(&self, timeout: Duration) -> rusb::Result<()> {
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        match unsafe { ffi::libusb_handle_events_timeout_completed(self.context.as_raw(), &tv, std::ptr::null_mut()) } {
+            0 => Ok(()),
+            ffi::constants::LIBUSB_ERROR_INTERRUPTED => Ok(()),
+            _ => Err(rusb::Error::Io)
+        }
+    }
+}
+
+impl Drop for TransferQueue {
+    fn drop(&mut self) {
+        let shared = unsafe { &*self.shared };
+        shared.stopping.store(true, Ordering::SeqCst);
+        for &transfer in &self.transfers {
+            unsafe { ffi::libusb_cancel_transfer(transfer) };
+        }
+
+        let deadline = Instant::now() + CANCEL_TIMEOUT;
+        while shared.in_flight.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            let _ = self.handle_events(Duration::from_millis(10));
+        }
+
+        if shared.in_flight.load(Ordering::SeqCst) > 0 {
+            // libusb may still write to the buffers and call back, so they must outlive us
+            warn!("usb transfers did not finish, leaking them");
+            std::mem::forget(std::mem::take(&mut self.buffers));
+            return;
+        }
+
+        for &transfer in &self.transfers {
+            unsafe { ffi::libusb_free_transfer(transfer) };
+        }
+        drop(unsafe { Box::from_raw(self.shared as *mut Shared) });
+    }
+}
+
+extern "system" fn transfer_completed(transfer: *mut ffi::libusb_transfer) {
+    let transfer = unsafe { &mut *transfer };
+    let shared = unsafe { &*(transfer.user_data as *const Shared) };
+
+    let result = match transfer.status {
+        ffi::constants::LIBUSB_TRANSFER_COMPLETED => Ok(unsafe {
+            slice::from_raw_parts(transfer.buffer, transfer.actual_length.max(0) as usize)
+        }.to_vec()),
+        ffi::constants::LIBUSB_TRANSFER_CANCELLED => {
+            shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return;
+        },
+        ffi::constants::LIBUSB_TRANSFER_TIMED_OUT => Err(rusb::Error::Timeout),
+        ffi::constants::LIBUSB_TRANSFER_OVERFLOW => Err(rusb::Error::Overflow),
+        ffi::constants::LIBUSB_TRANSFER_STALL => Err(rusb::Error::Pipe),
+        ffi::constants::LIBUSB_TRANSFER_NO_DEVICE => Err(rusb::Error::NoDevice),
+        _ => Err(rusb::Error::Io)
+    };
+    // after a transfer fails for good, there is no point in trying again
+    let resubmit = matches!(result, Ok(_) | Err(rusb::Error::Timeout) | Err(rusb::Error::Overflow))
+        && !shared.stopping.load(Ordering::SeqCst);
+
+    // a poisoned lock only means the reader panicked, and panicking here would abort
+    let mut completed = shared.completed.lock().unwrap_or_else(|e| e.into_inner());
+    if !matches!(result, Err(rusb::Error::Timeout)) {
+        completed.push_back(result);
+    }
+    if resubmit && unsafe { ffi::libusb_submit_transfer(transfer) } == 0 {
+        return;
+    }
+    if resubmit {
+        completed.push_back(Err(rusb::Error::Io));
+    }
+    shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+}
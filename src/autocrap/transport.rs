@@ -0,0 +1,225 @@
+use log::warn;
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::{CtrlKind, Mapping, MidiSpec, TransportAction},
+    interpreter::{osc_on, CtrlLogic, CtrlResponse, MidiResponse, OscResponse, Response}
+};
+
+const MIDI_START: u8 = 0xfa;
+const MIDI_CONTINUE: u8 = 0xfb;
+const MIDI_STOP: u8 = 0xfc;
+
+const MMC_STOP: u8 = 0x01;
+const MMC_PLAY: u8 = 0x02;
+const MMC_DEFERRED_PLAY: u8 = 0x03;
+const MMC_RECORD_STROBE: u8 = 0x06;
+const MMC_RECORD_EXIT: u8 = 0x07;
+const MMC_PAUSE: u8 = 0x09;
+
+/// MMC device id addressing all devices.
+const MMC_ALL_CALL: u8 = 0x7f;
+
+#[derive(Debug)]
+struct TransportButton {
+    action: TransportAction,
+    ctrl_in_num: Option<u8>,
+    ctrl_out_num: Option<u8>,
+    midi: Option<MidiSpec>,
+    osc_addr: String,
+    lit: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TransportState {
+    playing: bool,
+    recording: bool,
+    looping: bool,
+}
+
+impl TransportState {
+    fn is_lit(&self, action: TransportAction) -> bool {
+        match action {
+            TransportAction::Play => self.playing,
+            TransportAction::Stop => !self.playing && !self.recording,
+            TransportAction::Record => self.recording,
+            TransportAction::Loop => self.looping,
+        }
+    }
+
+    fn set(&mut self, action: TransportAction, on: bool) {
+        match action {
+            TransportAction::Play => self.playing = on,
+            TransportAction::Stop => if on {
+                self.playing = false;
+                self.recording = false;
+            },
+            TransportAction::Record => self.recording = on,
+            TransportAction::Loop => self.looping = on,
+        }
+    }
+}
+
+/// play/stop/record/loop buttons sharing one transport state, so that e.g. stop also turns off the play LED.
+/// the state is updated optimistically on button presses, and follows MIDI start/stop, MMC and
+/// per-button CC/note/OSC feedback from the host.
+#[derive(Debug)]
+pub struct TransportLogic {
+    buttons: Vec<TransportButton>,
+    state: TransportState,
+}
+
+impl TransportLogic {
+    pub fn from_mappings(mappings: &[Mapping]) -> TransportLogic {
+        let buttons = mappings.iter()
+            .filter_map(|mapping| {
+                let CtrlKind::Transport { action } = mapping.ctrl_kind else {
+                    return None;
+                };
+
+                if action == TransportAction::Loop && mapping.midi.is_none() {
+                    warn!("{}: MMC has no loop command, set midi to send a CC or note instead", mapping.name);
+                }
+
+                Some(TransportButton {
+                    action,
+                    ctrl_in_num: mapping.ctrl_in_num,
+                    ctrl_out_num: mapping.ctrl_out_num,
+                    midi: mapping.midi,
+                    osc_addr: mapping.osc_addr(),
+                    lit: false,
+                })
+            })
+            .collect();
+
+        TransportLogic {
+            buttons,
+            state: TransportState::default(),
+        }
+    }
+
+    /// LED feedback for every button whose lit state differs from the transport state.
+    fn refresh(&mut self, force: bool) -> Response {
+        let mut response = Response::new();
+        for button in &mut self.buttons {
+            let lit = self.state.is_lit(button.action);
+            if lit == button.lit && !force {
+                continue;
+            }
+
+            button.lit = lit;
+            if let Some(num) = button.ctrl_out_num {
+                response.ctrl.push(CtrlResponse {
+                    data: vec![num, if lit { 0x7f } else { 0x00 }],
+                    color: None,
+                    raw: false
+                });
+            }
+        }
+        response
+    }
+
+    fn mmc_command(&self, action: TransportAction) -> Option<u8> {
+        match action {
+            TransportAction::Play => Some(MMC_PLAY),
+            TransportAction::Stop => Some(MMC_STOP),
+            TransportAction::Record if self.state.recording => Some(MMC_RECORD_EXIT),
+            TransportAction::Record => Some(MMC_RECORD_STROBE),
+            TransportAction::Loop => None,
+        }
+    }
+
+    fn handle_mmc(&mut self, msg: &[u8]) -> Option<()> {
+        let [0xf0, 0x7f, _device, 0x06, command, 0xf7] = *msg else {
+            return None;
+        };
+
+        match command {
+            MMC_PLAY | MMC_DEFERRED_PLAY => self.state.playing = true,
+            MMC_STOP | MMC_PAUSE => self.state.set(TransportAction::Stop, true),
+            MMC_RECORD_STROBE => self.state.recording = true,
+            MMC_RECORD_EXIT => self.state.recording = false,
+            _ => return None
+        }
+
+        Some(())
+    }
+}
+
+impl CtrlLogic for TransportLogic {
+    fn from_mapping(mapping: &Mapping) -> Option<Box<dyn CtrlLogic>> {
+        let CtrlKind::Transport { .. } = mapping.ctrl_kind else {
+            return None;
+        };
+
+        Some(Box::new(TransportLogic::from_mappings(std::slice::from_ref(mapping))))
+    }
+
+    fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let i = self.buttons.iter().position(|b| b.ctrl_in_num == Some(num))?;
+        let pressed = val != 0x00;
+        let action = self.buttons[i].action;
+
+        let mut response = Response::new();
+        response.osc.push(OscResponse {
+            addr: self.buttons[i].osc_addr.clone(),
+            args: vec![OscType::Float(if pressed { 1.0 } else { 0.0 })]
+        });
+
+        match self.buttons[i].midi {
+            Some(midi) => response.midi.push(MidiResponse {
+                data: midi.message(if pressed { 0x7f } else { 0x00 }),
+                page: None
+            }),
+            None if pressed => if let Some(command) = self.mmc_command(action) {
+                response.midi.push(MidiResponse {
+                    data: vec![0xf0, 0x7f, MMC_ALL_CALL, 0x06, command, 0xf7],
+                    page: None
+                });
+            },
+            None => {}
+        }
+
+        if pressed {
+            let on = match action {
+                TransportAction::Record => !self.state.recording,
+                TransportAction::Loop => !self.state.looping,
+                _ => true
+            };
+            self.state.set(action, on);
+        }
+
+        response.extend(self.refresh(false));
+        Some(response)
+    }
+
+    fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        let i = self.buttons.iter().position(|b| b.osc_addr == msg.addr)?;
+        let on = osc_on(msg.args.first()?)?;
+
+        self.state.set(self.buttons[i].action, on);
+        Some(self.refresh(false))
+    }
+
+    fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        match *msg {
+            [MIDI_START] | [MIDI_CONTINUE] => self.state.playing = true,
+            [MIDI_STOP] => self.state.set(TransportAction::Stop, true),
+            _ => if self.handle_mmc(msg).is_none() {
+                let (action, val) = self.buttons.iter()
+                    .find_map(|b| Some((b.action, b.midi?.value(msg)?)))?;
+                self.state.set(action, val != 0);
+            }
+        }
+
+        Some(self.refresh(false))
+    }
+
+    fn init(&mut self) -> Vec<Response> {
+        vec![self.refresh(true)]
+    }
+
+    fn leds(&mut self) -> Vec<CtrlResponse> {
+        self.refresh(true).ctrl
+    }
+}
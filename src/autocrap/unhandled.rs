@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+        OnceLock
+    },
+    time::{Duration, Instant}
+};
+
+use log::warn;
+
+#[cfg(feature = "midi")]
+use super::config::{Mapping, MidiKind, MidiSpec};
+
+/// how often repeated unhandled input is summed up in the log.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// kinds of unhandled input kept apart. beyond that, new kinds are counted together as `OTHER`.
+const MAX_KINDS: usize = 256;
+
+const OTHER: &str = "other";
+
+/// input which nothing is mapped to, by what it is, such as `osc /foo` or `data 40`.
+struct Unhandled {
+    seen: BTreeMap<String, Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    /// times seen since the last summary.
+    recent: u64,
+    total: u64,
+    /// what might have been meant instead.
+    hint: Option<String>,
+}
+
+static UNHANDLED: Mutex<Unhandled> = Mutex::new(Unhandled {
+    seen: BTreeMap::new(),
+});
+
+/// what the times of the summaries count from.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// when the next summary is due, in ms since `EPOCH`, so that the ticker doesn't need the lock to find out.
+static NEXT_SUMMARY_MS: AtomicU64 = AtomicU64::new(0);
+
+fn with_unhandled<T>(f: impl FnOnce(&mut Unhandled) -> T) -> T {
+    let mut unhandled = UNHANDLED.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut unhandled)
+}
+
+/// warns about input which nothing is mapped to. the first time `what` is seen, `detail` is logged.
+/// after that, it is only counted, and the counts are logged every now and then by `summarize`.
+pub fn warn(what: String, detail: impl Display) {
+    warn_hinted(what, detail, || None);
+}
+
+/// like `warn`, with a hint at what might have been meant, which is worked out the first time `what` is seen
+/// and repeated in the summaries.
+pub fn warn_hinted(what: String, detail: impl Display, hint: impl FnOnce() -> Option<String>) {
+    with_unhandled(|u| {
+        let kinds = u.seen.len();
+        let what = if kinds >= MAX_KINDS && !u.seen.contains_key(&what) { OTHER.to_string() } else { what };
+        let seen = u.seen.entry(what).or_default();
+        if seen.total == 0 {
+            seen.hint = hint();
+            match seen.hint {
+                Some(ref hint) => warn!("unhandled {}, {}", detail, hint),
+                None => warn!("unhandled {}", detail)
+            }
+            // the kind is new, so there is one more of them now
+            if kinds + 1 == MAX_KINDS {
+                warn!("{} kinds of unhandled input seen, any further ones are counted as {}", MAX_KINDS, OTHER);
+            }
+        } else {
+            seen.recent += 1;
+        }
+        seen.total += 1;
+    });
+}
+
+/// logs how often each unhandled input was repeated since the last summary, if it's time.
+pub fn summarize(now: Instant) {
+    let now_ms = now.saturating_duration_since(*EPOCH.get_or_init(|| now)).as_millis() as u64;
+    if now_ms < NEXT_SUMMARY_MS.load(Ordering::Relaxed) {
+        return;
+    }
+    NEXT_SUMMARY_MS.store(now_ms + SUMMARY_INTERVAL.as_millis() as u64, Ordering::Relaxed);
+
+    with_unhandled(|u| {
+        let repeated: Vec<String> = u.seen.iter_mut()
+            .filter(|(_, seen)| seen.recent > 0)
+            .map(|(what, seen)| match seen.hint {
+                Some(ref hint) => format!("{} ({}x, {})", what, std::mem::take(&mut seen.recent), hint),
+                None => format!("{} ({}x)", what, std::mem::take(&mut seen.recent))
+            })
+            .collect();
+        if !repeated.is_empty() {
+            warn!("unhandled again in the last {} s: {}", SUMMARY_INTERVAL.as_secs(), repeated.join(", "));
+        }
+    });
+}
+
+/// how often each unhandled input was seen since startup.
+pub fn totals() -> Vec<(String, u64)> {
+    with_unhandled(|u| u.seen.iter().map(|(what, seen)| (what.clone(), seen.total)).collect())
+}
+
+/// a MIDI message as it would be written in a mapping, such as `CC 21 ch 0`, or its first bytes for other messages.
+#[cfg(feature = "midi")]
+pub fn describe_midi(msg: &[u8]) -> String {
+    match MidiSpec::of_message(msg) {
+        Some(spec) => describe(&spec),
+        None => format!("midi {:02x?}", &msg[..msg.len().min(2)])
+    }
+}
+
+#[cfg(feature = "midi")]
+fn describe(spec: &MidiSpec) -> String {
+    let kind = match spec.kind {
+        MidiKind::Cc => "CC",
+        MidiKind::Note => "note",
+    };
+    format!("{} {} ch {}", kind, spec.num, spec.channel)
+}
+
+/// the mapping whose MIDI message is closest to `msg`, for catching channels or numbers which are off by a bit.
+/// only mappings of the same kind, and on the same channel or with the same number, count as close.
+#[cfg(feature = "midi")]
+pub fn nearest_midi(mappings: &[Mapping], msg: &[u8]) -> Option<String> {
+    let got = MidiSpec::of_message(msg)?;
+    let (mapping, spec) = mappings.iter()
+        .filter_map(|m| Some((m, m.midi?)))
+        .filter(|(_, spec)| spec.kind == got.kind && (spec.channel == got.channel || spec.num == got.num))
+        .min_by_key(|(_, spec)| spec.channel.abs_diff(got.channel) as u16 + spec.num.abs_diff(got.num) as u16)?;
+    Some(format!("nearest mapping {} uses {}", mapping.name, describe(&spec)))
+}
@@ -0,0 +1,641 @@
+use std::{
+    sync::{atomic::{AtomicBool, Ordering}, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant}
+};
+
+use log::{debug, info, trace, warn};
+use rusb::{
+    Context, Device, Direction, DeviceDescriptor, DeviceHandle,
+    TransferType, UsbContext,
+};
+
+use super::{
+    config::{Config, FeedbackCodec, FeedbackConfig, FeedbackTransfer, Framing},
+    device::{parse_ctrls, DeviceBackend, Result},
+    diagnostics,
+    profile,
+    reconnect::Reconnect,
+    transfer::TransferQueue
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+const CLAIM_RETRY_DELAY: Duration = Duration::from_millis(500);
+const CLAIM_RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
+const HANDSHAKE_RETRY_DELAY: Duration = Duration::from_millis(250);
+// b0 looks to be a "start" byte, 00 00 is reset (all leds off)
+
+#[derive(Clone, Copy, Debug)]
+struct Endpoint {
+    config: u8,
+    iface: u8,
+    address: u8,
+    transfer_type: TransferType,
+    direction: Direction,
+    max_packet_size: u16,
+}
+
+/// a device accessed directly over USB. input comes from one or more interrupt endpoints, feedback goes out
+/// through an interrupt endpoint or control transfers.
+///
+/// when the device goes away, e.g. because the computer went to sleep, it is looked for again
+/// following the reconnect policy, until it comes back. feedback sent in the meantime is dropped.
+#[derive(Debug)]
+pub struct UsbDevice {
+    config: Config,
+    connection: RwLock<Option<Connection>>,
+    reconnect: Mutex<Reconnect>,
+    reconnected: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Connection {
+    // dropped before the handle, which the transfers belong to
+    transfers: Vec<TransferQueue>,
+    /// how to read the packets of each of `transfers`.
+    framings: Vec<Framing>,
+    /// the addresses of the endpoints `transfers` read from.
+    in_addresses: Vec<u8>,
+    handle: DeviceHandle<Context>,
+    out_endpoint: Option<Endpoint>,
+}
+
+impl DeviceBackend for UsbDevice {
+    fn open(config: &Config) -> Result<Option<UsbDevice>> {
+        let Some(connection) = Connection::open_verified(config, config.handshake_retries)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(UsbDevice {
+            config: config.clone(),
+            connection: RwLock::new(Some(connection)),
+            reconnect: Mutex::new(Reconnect::new("device", &config.reconnect)),
+            reconnected: AtomicBool::new(false),
+        }))
+    }
+
+    fn read_events(&self, timeout: Duration) -> Result<Vec<[u8; 2]>> {
+        let guard = self.connection.read().unwrap();
+        let Some(connection) = guard.as_ref() else {
+            drop(guard);
+            self.resume(timeout)?;
+            return Ok(vec![]);
+        };
+
+        let packets = match TransferQueue::read(&connection.transfers, timeout) {
+            Ok(packets) => packets,
+            Err(e) if is_gone(e) => {
+                drop(guard);
+                self.lost(e);
+                return Ok(vec![]);
+            },
+            // the stalled transfers are not resubmitted, so the device is opened again once the halt is cleared
+            Err(rusb::Error::Pipe) => {
+                warn!("device stalled, clearing the halt and opening it again");
+                connection.clear_halts();
+                drop(guard);
+                self.connection.write().unwrap().take();
+                return Ok(vec![]);
+            },
+            Err(e) => return Err(e.into())
+        };
+
+        let mut events = vec![];
+        for (i, packet) in packets {
+            trace!("read({:?}) from {}: {:02x?}", packet.len(), i, packet);
+            diagnostics::record_usb(diagnostics::Direction::In, &packet);
+            events.extend(parse_packet(&connection.framings[i], &packet));
+        }
+        Ok(events)
+    }
+
+    fn write_feedback(&self, data: &[u8]) -> Result<()> {
+        self.write_feedback_batch(&[data.to_vec()])
+    }
+
+    fn write_feedback_batch(&self, messages: &[Vec<u8>]) -> Result<()> {
+        let codec = self.config.feedback.codec;
+        self.write_encoded(&messages.iter().map(|data| encode(codec, data, None)).collect::<Vec<_>>())
+    }
+
+    fn write_color(&self, data: &[u8], color: [u8; 3]) -> Result<()> {
+        self.write_encoded(&[encode(self.config.feedback.codec, data, Some(color))])
+    }
+
+    fn write_motor(&self, num: u8, position: u16) -> Result<()> {
+        let Some(ref motor) = self.config.feedback.motor else {
+            debug!("no motor config, ignoring position {} for motor {}", position, num);
+            return Ok(());
+        };
+
+        let guard = self.connection.read().unwrap();
+        let Some(connection) = guard.as_ref() else {
+            debug!("device is gone, dropping motor position");
+            return Ok(());
+        };
+
+        let mut packet = [&motor.prefix[..], &[num]].concat();
+        if motor.fine {
+            packet.extend([(position >> 7) as u8 & 0x7f, position as u8 & 0x7f]);
+        } else {
+            packet.push((position >> 7) as u8 & 0x7f);
+        }
+        match connection.write_raw(&self.config.feedback, &packet) {
+            Ok(()) => Ok(()),
+            Err(e) if is_gone(e) => {
+                drop(guard);
+                self.lost(e);
+                Ok(())
+            },
+            // a one-off, the next write may well work
+            Err(rusb::Error::Io) => {
+                warn!("could not write to the device, dropping the packet");
+                Ok(())
+            },
+            Err(e) => Err(e.into())
+        }
+    }
+
+    fn write_raw(&self, data: &[u8]) -> Result<()> {
+        let guard = self.connection.read().unwrap();
+        let Some(connection) = guard.as_ref() else {
+            debug!("device is gone, dropping raw feedback");
+            return Ok(());
+        };
+
+        match connection.write_raw(&self.config.feedback, data) {
+            Ok(()) => Ok(()),
+            Err(e) if is_gone(e) => {
+                drop(guard);
+                self.lost(e);
+                Ok(())
+            },
+            // a one-off, the next write may well work
+            Err(rusb::Error::Io) => {
+                warn!("could not write to the device, dropping the packet");
+                Ok(())
+            },
+            Err(e) => Err(e.into())
+        }
+    }
+
+    fn reset(&self) -> Result<()> {
+        // not an LED, so not for the codec. devices without a profile are left as they are
+        match profile::find(&self.config) {
+            Some(profile) => self.write_encoded(&[profile.reset.to_vec()]),
+            None => Ok(())
+        }
+    }
+
+    fn reconnected(&self) -> bool {
+        self.reconnected.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl UsbDevice {
+    /// sends feedback which is already in the device's format.
+    fn write_encoded(&self, messages: &[Vec<u8>]) -> Result<()> {
+        let guard = self.connection.read().unwrap();
+        let Some(connection) = guard.as_ref() else {
+            debug!("device is gone, dropping feedback");
+            return Ok(());
+        };
+
+        for packet in packets(&self.config.feedback, connection.out_endpoint, messages) {
+            match connection.write_packet(&self.config.feedback, &packet) {
+                Ok(()) => {},
+                Err(e) if is_gone(e) => {
+                    drop(guard);
+                    self.lost(e);
+                    return Ok(());
+                },
+                // a one-off, the next write may well work
+                Err(rusb::Error::Io) => warn!("could not write to the device, dropping the packet"),
+                Err(e) => return Err(e.into())
+            }
+        }
+        Ok(())
+    }
+
+    /// forgets the connection after the device went away.
+    fn lost(&self, e: rusb::Error) {
+        let mut connection = self.connection.write().unwrap();
+        if connection.take().is_some() {
+            warn!("device went away ({}), waiting for it to come back", e);
+        }
+    }
+
+    /// looks for the device again if an attempt is due, otherwise waits up to `timeout` for one to be.
+    fn resume(&self, timeout: Duration) -> Result<()> {
+        let mut reconnect = self.reconnect.lock().unwrap();
+        let wait = reconnect.wait(Instant::now());
+        if !wait.is_zero() {
+            drop(reconnect);
+            thread::sleep(wait.min(timeout));
+            return Ok(());
+        }
+
+        // the reconnect policy does the retrying here
+        match Connection::open_verified(&self.config, 0) {
+            Ok(Some(connection)) => {
+                *self.connection.write().unwrap() = Some(connection);
+                reconnect.succeeded();
+                info!("device resumed");
+                self.reconnected.store(true, Ordering::Relaxed);
+            },
+            Ok(None) => reconnect.failed(Instant::now())?,
+            Err(e) => {
+                debug!("device not ready yet: {}", e);
+                reconnect.failed(Instant::now())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// splits a packet into (control number, value) pairs following the endpoint's framing.
+fn parse_packet(framing: &Framing, packet: &[u8]) -> Vec<[u8; 2]> {
+    parse_ctrls(packet.get(framing.skip..).unwrap_or_default()).into_iter()
+        .map(|[num, val]| [num.wrapping_add(framing.num_offset), val])
+        .collect()
+}
+
+/// whether an error means the device is no longer there, as opposed to a one-off failure.
+fn is_gone(e: rusb::Error) -> bool {
+    matches!(e, rusb::Error::NoDevice | rusb::Error::NotFound)
+}
+
+impl Connection {
+    /// opens the device and checks that it takes the handshake, starting over up to `retries` times
+    /// if anything along the way fails. some hubs drop the first transfers after the device appears.
+    fn open_verified(config: &Config, retries: u32) -> Result<Option<Connection>> {
+        // the config's, or else the profile's LED reset. without either, the device isn't checked
+        let handshake = config.handshake.clone()
+            .or_else(|| profile::find(config).map(|p| p.reset.to_vec()))
+            .filter(|handshake| !handshake.is_empty());
+        let mut delay = HANDSHAKE_RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            let error = match Connection::open(config) {
+                Ok(None) => return Ok(None),
+                Ok(Some(connection)) => match handshake.as_ref().map(|h| connection.write_packet(&config.feedback, h)) {
+                    None | Some(Ok(())) => return Ok(Some(connection)),
+                    Some(Err(e)) => format!("device did not take the handshake: {}", e).into(),
+                },
+                Err(e) => e,
+            };
+
+            if attempt >= retries {
+                return Err(error);
+            }
+            attempt += 1;
+            warn!("handshake failed ({}), retrying in {:?} ({}/{})", error, delay, attempt, retries);
+            thread::sleep(delay);
+            delay = (delay * 2).min(CLAIM_RETRY_MAX_DELAY);
+        }
+    }
+
+    fn open(config: &Config) -> Result<Option<Connection>> {
+        let mut context = Context::new()?;
+
+        let (vendor_id, product_id) = profile::ids(config)?;
+        let Some((mut device, device_desc, mut handle)) = open_device(&mut context, vendor_id, product_id)? else {
+            return Ok(None);
+        };
+
+        handle.reset()?;
+
+        let languages = handle.read_languages(DEFAULT_TIMEOUT)?;
+
+        info!("active configuration: {}", handle.active_configuration()?);
+        info!("languages: {:?}", languages);
+
+        if !languages.is_empty() {
+            let language = languages[0];
+
+            info!(
+                "manufacturer: {:?}",
+                handle
+                    .read_manufacturer_string(language, &device_desc, DEFAULT_TIMEOUT)
+                    .ok()
+            );
+            info!(
+                "product: {:?}",
+                handle
+                    .read_product_string(language, &device_desc, DEFAULT_TIMEOUT)
+                    .ok()
+            );
+            info!(
+                "serial number: {:?}",
+                handle
+                    .read_serial_number_string(language, &device_desc, DEFAULT_TIMEOUT)
+                    .ok()
+            );
+        }
+
+        let profile = profile::find(config);
+        let in_num = config.in_endpoint.or(profile.map(|p| p.in_endpoint));
+        let out_num = config.out_endpoint.or(profile.map(|p| p.out_endpoint));
+        let in_endpoint = find_endpoint(&mut device, &device_desc, |e| in_num.is_none_or(|n| e.config == n) && e.transfer_type == TransferType::Interrupt && e.direction == Direction::In)
+            .ok_or("control in endpoint not found")?;
+        if in_num.is_none() {
+            info!("no in_endpoint given, using the first interrupt in endpoint");
+        }
+        let out_endpoint = match config.feedback.transfer {
+            FeedbackTransfer::Interrupt => Some(
+                find_endpoint(&mut device, &device_desc, |e| match config.feedback.endpoint_address {
+                    Some(address) => e.address == address,
+                    None => out_num.is_none_or(|n| e.config == n)
+                } && e.transfer_type == TransferType::Interrupt && e.direction == Direction::Out)
+                    .ok_or("control out endpoint not found")?
+            ),
+            FeedbackTransfer::Control { .. } => None
+        };
+
+        let mut extra_in_endpoints = vec![];
+        for extra in &config.in_endpoints {
+            let endpoint = find_endpoint(&mut device, &device_desc, |e| e.address == extra.address && e.transfer_type == TransferType::Interrupt && e.direction == Direction::In)
+                .ok_or_else(|| format!("control in endpoint {:#04x} not found", extra.address))?;
+            extra_in_endpoints.push((endpoint, extra.framing));
+        }
+
+        info!("control in endpoint: {:?}", in_endpoint);
+        for (endpoint, _) in &extra_in_endpoints {
+            info!("control in endpoint: {:?}", endpoint);
+        }
+        if out_endpoint.is_some() && out_num.is_none() && config.feedback.endpoint_address.is_none() {
+            info!("no out_endpoint given, using the first interrupt out endpoint");
+        }
+        match out_endpoint {
+            Some(out_endpoint) => info!("control out endpoint: {:?}", out_endpoint),
+            None => info!("feedback via control transfers: {:?}", config.feedback.transfer)
+        }
+        if let Some(out_endpoint) = out_endpoint.filter(|e| e.max_packet_size > 0) {
+            // feedback is only split between LEDs, so one has to fit
+            let needed = config.feedback.prefix.len() + config.feedback.codec.entry_size();
+            if needed > out_endpoint.max_packet_size as usize {
+                return Err(format!(
+                    "the feedback prefix and one LED take {} bytes, but the out endpoint only takes {} at once",
+                    needed, out_endpoint.max_packet_size
+                ).into());
+            }
+        }
+
+        match handle.set_auto_detach_kernel_driver(true) {
+            Ok(()) => Ok(()),
+            Err(rusb::Error::NotSupported) => Ok(()),
+            err => err
+        }?;
+
+        let in_endpoints: Vec<(Endpoint, Framing)> = std::iter::once((in_endpoint, profile.map(|p| p.framing).unwrap_or_default()))
+            .chain(extra_in_endpoints)
+            .collect();
+        let mut claimed = vec![];
+        for endpoint in in_endpoints.iter().map(|(e, _)| e).chain(out_endpoint.as_ref()) {
+            // endpoints often share an interface, which only needs claiming once
+            if !claimed.contains(&endpoint.iface) {
+                configure_endpoint(&mut handle, endpoint, config)?;
+                claimed.push(endpoint.iface);
+            }
+        }
+
+        let mut transfers = vec![];
+        let mut framings = vec![];
+        let mut in_addresses = vec![];
+        for (endpoint, framing) in in_endpoints {
+            let packet_size = endpoint.max_packet_size.max(8) as usize;
+            transfers.push(TransferQueue::new(&handle, endpoint.address, packet_size, config.in_transfers)?);
+            framings.push(framing);
+            in_addresses.push(endpoint.address);
+        }
+
+        Ok(Some(Connection {
+            transfers,
+            framings,
+            in_addresses,
+            handle,
+            out_endpoint,
+        }))
+    }
+
+    fn write_packet(&self, feedback: &FeedbackConfig, data: &[u8]) -> rusb::Result<()> {
+        self.write_raw(feedback, &[&feedback.prefix[..], data].concat())
+    }
+
+    /// clears the halt of the in endpoints after one stalled.
+    fn clear_halts(&self) {
+        for &address in &self.in_addresses {
+            if let Err(e) = self.handle.clear_halt(address) {
+                warn!("could not clear the halt of in endpoint {:#04x}: {}", address, e);
+            }
+        }
+    }
+
+    /// sends a packet as it is, the way feedback is configured to go.
+    fn write_raw(&self, feedback: &FeedbackConfig, packet: &[u8]) -> rusb::Result<()> {
+        debug!("send ctrl: {:02x?}", packet);
+        diagnostics::record_usb(diagnostics::Direction::Out, packet);
+        match (feedback.transfer, self.out_endpoint) {
+            (FeedbackTransfer::Control { request_type, request, value, index }, _) => {
+                self.handle.write_control(request_type, request, value, index, packet, DEFAULT_TIMEOUT)?;
+            },
+            (FeedbackTransfer::Interrupt, Some(out_endpoint)) => {
+                match self.handle.write_interrupt(out_endpoint.address, packet, DEFAULT_TIMEOUT) {
+                    Err(rusb::Error::Pipe) => {
+                        warn!("out endpoint {:#04x} stalled, clearing the halt", out_endpoint.address);
+                        self.handle.clear_halt(out_endpoint.address)?;
+                        self.handle.write_interrupt(out_endpoint.address, packet, DEFAULT_TIMEOUT)?;
+                    },
+                    result => {
+                        result?;
+                    }
+                }
+            },
+            // open makes sure there is one
+            (FeedbackTransfer::Interrupt, None) => return Err(rusb::Error::NotSupported)
+        }
+        Ok(())
+    }
+}
+
+/// puts `[num, value]` feedback into the format the device wants.
+fn encode(codec: FeedbackCodec, data: &[u8], color: Option<[u8; 3]>) -> Vec<u8> {
+    let FeedbackCodec::Rgb { seven_bit } = codec else {
+        return data.to_vec();
+    };
+
+    parse_ctrls(data).into_iter()
+        .flat_map(|[num, val]| {
+            let rgb = color.unwrap_or([val.min(0x7f) << 1; 3]);
+            [num].into_iter().chain(rgb.map(|c| if seven_bit { c >> 1 } else { c }))
+        })
+        .collect()
+}
+
+/// splits feedback messages to fit the out endpoint, packing several into one packet if `coalesce` is on.
+/// messages are only split between LEDs, never in the middle of one.
+fn packets(feedback: &FeedbackConfig, out_endpoint: Option<Endpoint>, messages: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let max_size = out_endpoint
+        .map(|e| e.max_packet_size as usize)
+        .filter(|&size| size > 0)
+        .unwrap_or(usize::MAX);
+    let room = max_size.saturating_sub(feedback.prefix.len());
+    let entry_size = feedback.codec.entry_size();
+    let chunk_size = (room - room % entry_size).max(entry_size);
+
+    let mut packets: Vec<Vec<u8>> = vec![];
+    for message in messages {
+        // anything not made of LEDs, such as raw feedback or a reset, goes as it is
+        let chunks: Vec<&[u8]> = if message.len() <= room || message.len() % entry_size != 0 {
+            vec![message]
+        } else {
+            message.chunks(chunk_size).collect()
+        };
+        for chunk in chunks {
+            match packets.last_mut() {
+                Some(packet) if feedback.coalesce && packet.len() + chunk.len() <= room =>
+                    packet.extend_from_slice(chunk),
+                _ => packets.push(chunk.to_vec())
+            }
+        }
+    }
+    packets
+}
+
+type OpenDevice<T> = (Device<T>, DeviceDescriptor, DeviceHandle<T>);
+
+fn open_device<T: UsbContext>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+) -> Result<Option<OpenDevice<T>>> {
+    let devices = match context.devices() {
+        Ok(d) => d,
+        Err(_) => return Ok(None),
+    };
+
+    for device in devices.iter() {
+        let device_desc = match device.device_descriptor() {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+
+        if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
+            return match device.open() {
+                Ok(handle) => Ok(Some((device, device_desc, handle))),
+                Err(e) => Err(format!("device found but failed to open: {}", e).into()),
+            };
+        }
+    }
+
+    Ok(None)
+}
+
+fn find_endpoint<T: UsbContext>(
+    device: &mut Device<T>,
+    device_desc: &DeviceDescriptor,
+    predicate: impl Fn(Endpoint) -> bool
+) -> Option<Endpoint> {
+    for n in 0..device_desc.num_configurations() {
+        let config_desc = match device.config_descriptor(n) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        for interface in config_desc.interfaces() {
+            for interface_desc in interface.descriptors() {
+                for endpoint_desc in interface_desc.endpoint_descriptors() {
+                    let endpoint = Endpoint {
+                        config: config_desc.number(),
+                        iface: interface_desc.interface_number(),
+                        address: endpoint_desc.address(),
+                        transfer_type: endpoint_desc.transfer_type(),
+                        direction: endpoint_desc.direction(),
+                        max_packet_size: endpoint_desc.max_packet_size()
+                    };
+
+                    if predicate(endpoint) {
+                        return Some(endpoint);
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn configure_endpoint<T: UsbContext>(
+    handle: &mut DeviceHandle<T>,
+    endpoint: &Endpoint,
+    config: &Config,
+) -> Result<()> {
+    // handle.set_active_configuration(endpoint.config)?;
+    info!("configure_endpoint {:?}", endpoint);
+    claim_interface(handle, endpoint.iface, config.claim_retries, config.force_detach)?;
+    // handle.set_alternate_setting(endpoint.iface, endpoint.setting)?;
+    Ok(())
+}
+
+/// claims an interface, retrying with backoff while something else holds it.
+fn claim_interface<T: UsbContext>(
+    handle: &mut DeviceHandle<T>,
+    iface: u8,
+    retries: u32,
+    force_detach: bool,
+) -> Result<()> {
+    let mut delay = CLAIM_RETRY_DELAY;
+    for attempt in 0..=retries {
+        match handle.claim_interface(iface) {
+            Ok(()) => return Ok(()),
+            Err(rusb::Error::Busy) => {}
+            Err(e) => return Err(format!("could not claim interface {}: {}", iface, e).into()),
+        }
+
+        match handle.kernel_driver_active(iface) {
+            Ok(true) if force_detach => match handle.detach_kernel_driver(iface) {
+                Ok(()) => {
+                    info!("detached kernel driver from interface {}", iface);
+                    // claimed right away, without using up a retry
+                    match handle.claim_interface(iface) {
+                        Ok(()) => return Ok(()),
+                        Err(rusb::Error::Busy) => warn!("interface {} is still busy after detaching the kernel driver", iface),
+                        Err(e) => return Err(format!("could not claim interface {}: {}", iface, e).into()),
+                    }
+                },
+                Err(e) => warn!("could not detach kernel driver from interface {}: {}", iface, e),
+            },
+            Ok(true) => warn!("interface {} is busy, held by a kernel driver (try --force-detach)", iface),
+            _ => warn!("interface {} is busy, another program may be using the device", iface),
+        }
+
+        if attempt < retries {
+            info!("retrying in {:?} ({}/{})", delay, attempt + 1, retries);
+            thread::sleep(delay);
+            delay = (delay * 2).min(CLAIM_RETRY_MAX_DELAY);
+        }
+    }
+
+    Err(format!("interface {} is busy", iface).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusb::{Direction, TransferType};
+
+    use super::{packets, Endpoint};
+    use crate::autocrap::config::FeedbackConfig;
+
+    fn endpoint(max_packet_size: u16) -> Option<Endpoint> {
+        Some(Endpoint { config: 1, iface: 0, address: 0x02, transfer_type: TransferType::Interrupt, direction: Direction::Out, max_packet_size })
+    }
+
+    #[test]
+    fn packets_keep_leds_whole() {
+        let feedback = FeedbackConfig { prefix: vec![0xb0], coalesce: true, ..FeedbackConfig::default() };
+        let message: Vec<u8> = (0..10).collect();
+        // 7 bytes of room after the prefix, which fits 3 LEDs
+        assert_eq!(packets(&feedback, endpoint(8), &[message]), vec![vec![0, 1, 2, 3, 4, 5], vec![6, 7, 8, 9]]);
+        assert_eq!(packets(&feedback, endpoint(8), &[vec![1, 2], vec![3, 4], vec![5, 6], vec![7, 8]]), vec![vec![1, 2, 3, 4, 5, 6], vec![7, 8]]);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use log::{info, warn};
+use rosc::{OscMessage, OscType};
+
+use super::{
+    config::{substitute, Mapping, VarButtonConfig},
+    interpreter::{osc_number, CtrlResponse, OscResponse, Response}
+};
+
+const OSC_VAR_PREFIX: &str = "/autocrap/var/";
+
+/// named values which aren't tied to a control, such as the deck a DJ controller is working on.
+/// they are set over OSC as `/autocrap/var/<name>` or with buttons, and can be used in conditions
+/// and in OSC addresses, as in `/deck{deck}/volume`.
+#[derive(Debug)]
+pub struct Vars {
+    values: BTreeMap<String, u8>,
+    buttons: Vec<VarButtonConfig>,
+    /// OSC addresses of mappings which refer to variables.
+    templates: Vec<String>,
+}
+
+impl Vars {
+    pub fn new(values: &BTreeMap<String, u8>, buttons: &[VarButtonConfig], mappings: &[Mapping]) -> Vars {
+        for button in buttons.iter().filter(|b| !values.contains_key(&b.var)) {
+            warn!("button for unknown variable {}", button.var);
+        }
+
+        Vars {
+            values: values.clone(),
+            buttons: buttons.to_vec(),
+            templates: mappings.iter()
+                .map(|m| m.osc_addr())
+                .filter(|addr| values.keys().any(|var| addr.contains(&format!("{{{}", var))))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<u8> {
+        self.values.get(name).copied()
+    }
+
+    fn set(&mut self, name: &str, val: u8) -> Response {
+        let Some(current) = self.values.get_mut(name) else {
+            warn!("unknown variable: {}", name);
+            return Response::new();
+        };
+        *current = val;
+        info!("{} = {}", name, val);
+
+        let mut response = Response::from(OscResponse {
+            addr: format!("{}{}", OSC_VAR_PREFIX, name),
+            args: vec![OscType::Int(val as i32)]
+        });
+        response.ctrl = self.leds(Some(name));
+        response
+    }
+
+    /// LEDs of the buttons for `var`, or for all variables. a button is lit while its value is set.
+    pub fn leds(&self, var: Option<&str>) -> Vec<CtrlResponse> {
+        self.buttons.iter()
+            .filter(|b| var.is_none_or(|v| v == b.var))
+            .filter_map(|b| Some(CtrlResponse {
+                data: vec![b.ctrl_out_num?, if self.get(&b.var) == Some(b.value) { 0x7f } else { 0x00 }],
+                color: None,
+                raw: false
+            }))
+            .collect()
+    }
+
+    pub fn handle_ctrl(&mut self, num: u8, val: u8) -> Option<Response> {
+        let button = self.buttons.iter().find(|b| b.ctrl_in_num == Some(num))?;
+        if val == 0x00 {
+            return Some(Response::new());
+        }
+
+        let (var, value) = (button.var.clone(), button.value);
+        Some(self.set(&var, value))
+    }
+
+    pub fn handle_osc(&mut self, msg: &OscMessage) -> Option<Response> {
+        let name = msg.addr.strip_prefix(OSC_VAR_PREFIX)?;
+        let Some(val) = msg.args.first().and_then(osc_number) else {
+            let val = self.get(name)?;
+            return Some(OscResponse { addr: msg.addr.clone(), args: vec![OscType::Int(val as i32)] }.into());
+        };
+        Some(self.set(name, val.round().clamp(0.0, 127.0) as u8))
+    }
+
+    pub fn handle_midi(&mut self, msg: &[u8]) -> Option<Response> {
+        let (button, val) = self.buttons.iter().find_map(|b| Some((b, b.midi?.value(msg)?)))?;
+        if val == 0x00 {
+            return Some(Response::new());
+        }
+
+        let (var, value) = (button.var.clone(), button.value);
+        Some(self.set(&var, value))
+    }
+
+    fn vars(&self) -> Vec<(&str, u8)> {
+        self.values.iter().map(|(name, &val)| (name.as_str(), val)).collect()
+    }
+
+    /// fills in the variables in OSC addresses.
+    pub fn outgoing(&self, response: &mut Response) {
+        let vars = self.vars();
+        for osc in response.osc.iter_mut().filter(|osc| osc.addr.contains('{')) {
+            osc.addr = substitute(&osc.addr, &vars);
+        }
+    }
+
+    /// turns an incoming OSC address back into the address of the mapping it is for, going by
+    /// the current values of the variables. messages for other values are left as they are.
+    pub fn incoming(&self, msg: &mut OscMessage) {
+        let vars = self.vars();
+        if let Some(template) = self.templates.iter().find(|t| substitute(t, &vars) == msg.addr) {
+            msg.addr = template.clone();
+        }
+    }
+}
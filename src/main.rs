@@ -1,57 +1,96 @@
+
 use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
     error::Error,
     fs::File,
-    io::BufReader,
-    net::UdpSocket,
-    path::PathBuf,
+    io::{BufReader, Write},
+    net::{IpAddr, SocketAddr, TcpListener, UdpSocket},
+    path::{Path, PathBuf},
     sync::{
+        atomic::{AtomicBool, Ordering},
         Arc, RwLock,
         mpsc
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
     vec::Vec
 };
+#[cfg(feature = "midi")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "osc")]
+use std::time::SystemTime;
 
-use clap::Parser;
-use colog;
+use clap::{Parser, Subcommand, ValueEnum};
 use log::{error, warn, info, debug, trace};
+#[cfg(feature = "midi")]
 use midir::{
-    MidiInput, MidiOutput,
+    MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection,
 };
-#[cfg(unix)]
+#[cfg(all(feature = "midi", unix))]
 use midir::os::unix::{VirtualInput, VirtualOutput};
 
 use rosc::encoder;
-use rosc::{OscMessage, OscPacket};
-
-use rusb::{
-    Context, Device, Direction, DeviceDescriptor, DeviceHandle,
-    TransferType, UsbContext,
-};
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime};
+#[cfg(feature = "osc")]
+use rosc::OscType;
 
-use serde_json;
+#[cfg(feature = "web")]
+use tungstenite::{Message, stream::MaybeTlsStream};
 
 mod autocrap;
 
 use autocrap::{
-    config::{Config, Interface, MidiInterface, MidiPort, OscInterface},
-    interpreter::{Interpreter, CtrlResponse, MidiResponse, OscResponse}
+    bench,
+    capture::{self, Capture},
+    check,
+    control,
+    config::{Config, ConflictPolicy, Interface, MidiPort, OscInterface, ThreadConfig},
+    device::{DeviceBackend, NullDevice},
+    diagnostics::{self, Direction},
+    diff,
+    docs,
+    effective,
+    export,
+    fields,
+    filter,
+    import,
+    interpreter::{Interpreter, CtrlResponse, MidiResponse, MotorResponse, OscResponse, Response},
+    logfile::RotatingFile,
+    profile,
+    reconnect::{GaveUp, Reconnect},
+    remote::{self, RemoteDevice},
+    replay,
+    sockopt,
+    state::{self, State},
+    threads,
+    unhandled,
+    usb::UsbDevice
 };
+#[cfg(feature = "midi")]
+use autocrap::{
+    config::{Mapping, MidiInterface},
+    mididevice::MidiDevice,
+    midiloop
+};
+#[cfg(feature = "osc")]
+use autocrap::{config::ReplyTo, schedule::Schedule};
+#[cfg(feature = "web")]
+use autocrap::{config::ObsInterface, obs::ObsBridge};
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
-
-#[derive(Clone, Copy, Debug)]
-struct Endpoint {
-    config: u8,
-    iface: u8,
-    setting: u8,
-    address: u8,
-    transfer_type: TransferType,
-    direction: Direction,
-}
+const TICK_INTERVAL: Duration = Duration::from_millis(1);
+const IDLE_TICK_INTERVAL: Duration = Duration::from_millis(100);
+#[cfg(feature = "web")]
+const OBS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const PLUGIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const SELFTEST_BLINK_INTERVAL: Duration = Duration::from_millis(250);
+const RECV_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(5);
+/// the threads which `threads` in the config can refer to.
+const THREAD_NAMES: [&str; 10] = ["usb-reader", "usb-writer", "ticker", "state", "control", "output", "midi-rx", "osc-rx", "obs-rx", "plugin"];
+/// how often threads waiting for input check whether they should stop for a new config.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -63,336 +102,1184 @@ struct Options {
     /// Set logging level
     #[arg(short, long)]
     log: Option<String>,
+
+    /// Write the log to a file, rotated by size
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Record outgoing messages to a .mid file, or an OSC capture file (can be repeated)
+    #[arg(long, value_name = "FILE")]
+    capture: Vec<PathBuf>,
+
+    /// Run without a device, e.g. to translate between MIDI and OSC with `bridge`
+    #[arg(long)]
+    no_device: bool,
+
+    /// Detach kernel drivers holding the device's interfaces, where the platform allows it
+    #[arg(long)]
+    force_detach: bool,
+
+    /// Ask for real-time scheduling of the threads talking to the device
+    #[arg(long)]
+    realtime: bool,
+
+    /// Run a recorded session of inputs through the mappings on a fixed clock, and print what is sent
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Compare the output of --replay with FILE instead of printing it, failing if they differ
+    #[arg(long, value_name = "FILE", requires = "replay")]
+    expect: Option<PathBuf>,
+
+    /// Print the config with defaults filled in and mappings expanded, as json (default) or table
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "json", hide_possible_values = true)]
+    print_effective_config: Option<EffectiveFormat>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Measure interpreter throughput with synthetic events, without a device
+    Bench {
+        /// Number of events to run
+        #[arg(short, long, default_value_t = 100_000)]
+        events: usize,
+    },
+    /// Check that every configured control and LED works
+    Selftest {
+        /// Seconds to wait for each control
+        #[arg(short, long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Check the config for mistakes without running it
+    Check {
+        /// Check again every time the config is saved
+        #[arg(long)]
+        watch: bool,
+        /// Show a desktop notification when the config has errors
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Compare the config with another one, mapping by mapping
+    Diff {
+        /// The config to compare with
+        new: PathBuf,
+    },
+    /// Print the mappings for setting up a DAW
+    Export {
+        /// What to print
+        #[arg(value_enum)]
+        format: ExportFormat,
+    },
+    /// Print the config with mappings converted from an Automap-style XML file added
+    Import {
+        /// The file to convert
+        file: PathBuf,
+    },
+    /// Print a cheat sheet of the mappings in Markdown
+    Docs {
+        /// Print a standalone HTML page instead
+        #[arg(long)]
+        html: bool,
+    },
+    /// Send a command to the running instance with this config's `control_addr`
+    Ctl {
+        #[command(subcommand)]
+        command: CtlCommand,
+    },
+    /// Make the device available over TCP to an instance with `remote` in its config
+    Serve {
+        /// Address to listen on
+        #[arg(default_value = "127.0.0.1:9300")]
+        addr: SocketAddr,
+        /// Address of a machine allowed to connect, needed when listening beyond localhost
+        #[arg(long)]
+        allow: Vec<IpAddr>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CtlCommand {
+    /// Swap in another config, keeping the device open
+    Load {
+        /// The config to load
+        config: PathBuf,
+    },
+    /// Show how often each kind of unhandled input was seen since startup
+    Unhandled,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EffectiveFormat {
+    Json,
+    Table,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// An Ableton Live user remote script (UserConfiguration.txt)
+    Live,
+    /// A list of every mapping's MIDI message
+    Csv,
 }
 
 fn main() {
-    run().unwrap();
+    diagnostics::install_panic_hook();
+
+    if let Err(e) = run() {
+        fail(e);
+    }
+}
+
+/// logs the error that stopped autocrap, writes a crash report and exits.
+fn fail(e: Box<dyn Error>) -> ! {
+    error!("{}", e);
+    match diagnostics::write_report(&e.to_string()) {
+        Ok(path) => eprintln!("crash report written to {}", path.display()),
+        Err(e) => eprintln!("could not write crash report: {}", e),
+    }
+    std::process::exit(1);
 }
 
 fn run() -> Result<()> {
     let options = Options::parse();
 
+    // before loading the config, which may well be broken
+    if let Some(Command::Check { watch, notify }) = options.command {
+        let mut colog_builder = colog::default_builder();
+        if let Some(ref filters_str) = options.log {
+            colog_builder.parse_filters(filters_str);
+        }
+        colog_builder.init();
+        if !check::run(&options.config, watch, notify, check_config) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let file = File::open(&options.config)?;
+    let value: serde_json::Value = serde_json::from_reader(BufReader::new(file))?;
+    let mut config: Config = serde_json::from_value(value.clone())?;
+    config.force_detach |= options.force_detach;
+    diagnostics::set_config(&config);
+
     let mut colog_builder = colog::default_builder();
+    for (module, level) in &config.logging.levels {
+        colog_builder.filter_module(module, level.parse()?);
+    }
     if let Some(ref filters_str) = options.log {
         colog_builder.parse_filters(filters_str);
     }
+    if let Some(ref path) = options.log_file {
+        let file = RotatingFile::open(path, config.logging.max_size, config.logging.keep)?;
+        colog_builder
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .format(|buf, record| writeln!(
+                buf, "{} {:<5} {}: {}", buf.timestamp_millis(), record.level(), record.target(), record.args()
+            ));
+    }
     colog_builder.init();
 
-    let file = File::open(&options.config)?;
-    let reader = BufReader::new(file);
-    let config: Config = serde_json::from_reader(reader)?;
-    info!("config: {:?}", config);
-
-    let mut context = Context::new().unwrap();
-
-    match open_device(&mut context, config.vendor_id, config.product_id) {
-        Some((mut device, device_desc, mut handle)) => {
-            handle.reset().unwrap();
-
-            let languages = handle.read_languages(DEFAULT_TIMEOUT).unwrap();
-
-            info!("active configuration: {}", handle.active_configuration().unwrap());
-            info!("languages: {:?}", languages);
-
-            if !languages.is_empty() {
-                let language = languages[0];
-
-                info!(
-                    "manufacturer: {:?}",
-                    handle
-                        .read_manufacturer_string(language, &device_desc, DEFAULT_TIMEOUT)
-                        .ok()
-                );
-                info!(
-                    "product: {:?}",
-                    handle
-                        .read_product_string(language, &device_desc, DEFAULT_TIMEOUT)
-                        .ok()
-                );
-                info!(
-                    "serial number: {:?}",
-                    handle
-                        .read_serial_number_string(language, &device_desc, DEFAULT_TIMEOUT)
-                        .ok()
-                );
-            }
-
-            let ctrl_in_endpoint = find_endpoint(&mut device, &device_desc, |e| e.config == config.in_endpoint && e.transfer_type == TransferType::Interrupt && e.direction == Direction::In)
-                .ok_or("control out endpoint not found").unwrap();
-            let ctrl_out_endpoint = find_endpoint(&mut device, &device_desc, |e| e.config == config.out_endpoint && e.transfer_type == TransferType::Interrupt && e.direction == Direction::Out)
-                .ok_or("control out endpoint not found").unwrap();
-
-            info!("control in endpoint: {:?}", ctrl_in_endpoint);
-            info!("control out endpoint: {:?}", ctrl_out_endpoint);
-
-            match handle.set_auto_detach_kernel_driver(true) {
-                ok@Ok(()) => Ok(()),
-                Err(rusb::Error::NotSupported) => Ok(()),
-                err => err
-            }.unwrap();
-
-            configure_endpoint(&mut handle, &ctrl_in_endpoint).unwrap();
-            configure_endpoint(&mut handle, &ctrl_out_endpoint).unwrap();
-
-            let interpreter = Arc::new(RwLock::new(Interpreter::new(&config)));
-            let (receiver_ctrl_tx, ctrl_rx) = mpsc::channel();
-            let reader_ctrl_tx = receiver_ctrl_tx.clone();
-
-            write_init(&mut handle, ctrl_out_endpoint.address).unwrap();
-
-            thread::scope(|s| {
-                let writer_thread = s.spawn(|| {
-                    run_writer(&handle, &ctrl_out_endpoint, ctrl_rx).unwrap();
+    // not with Debug, which would show passwords
+    info!("config: {}", diagnostics::redacted(&config, false));
+    check_config(&config, &value)?;
+
+    if let Some(format) = options.print_effective_config {
+        print!("{}", match format {
+            EffectiveFormat::Json => effective::json(&config)?,
+            EffectiveFormat::Table => effective::table(&config),
+        });
+        return Ok(());
+    }
+    if let Some(ref path) = options.replay {
+        let events = replay::parse(&std::fs::read_to_string(path)?)?;
+        let lines = replay::run(&config, &events)?;
+        if let Some(ref expected) = options.expect {
+            let differences = replay::differences(&lines, &std::fs::read_to_string(expected)?);
+            if differences.is_empty() {
+                println!("{}: ok", path.display());
+                return Ok(());
+            }
+            println!("{}: differs from {}", path.display(), expected.display());
+            for difference in differences {
+                println!("  {}", difference);
+            }
+            std::process::exit(1);
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Bench { events }) = options.command {
+        bench::run(&config, events);
+        return Ok(());
+    }
+    if let Some(Command::Diff { ref new }) = options.command {
+        let new_config: Config = serde_json::from_reader(BufReader::new(File::open(new)?))?;
+        let lines = diff::diff(&config, &new_config);
+        if lines.is_empty() {
+            println!("no differences");
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Ctl { command: CtlCommand::Load { config: ref path } }) = options.command {
+        let addr = config.control_addr.ok_or("the config has no control_addr")?;
+        let value: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        check_config(&serde_json::from_value(value.clone())?, &value)?;
+        println!("{}", control::request(addr, &format!("load {}", value))?);
+        return Ok(());
+    }
+    if let Some(Command::Ctl { command: CtlCommand::Unhandled }) = options.command {
+        let addr = config.control_addr.ok_or("the config has no control_addr")?;
+        println!("{}", control::request(addr, "unhandled")?);
+        return Ok(());
+    }
+    if let Some(Command::Export { format }) = options.command {
+        print!("{}", match format {
+            ExportFormat::Live => export::live(&config),
+            ExportFormat::Csv => export::csv(&config),
+        });
+        return Ok(());
+    }
+    if let Some(Command::Import { ref file }) = options.command {
+        let import = import::xml(&std::fs::read_to_string(file)?);
+        // start from the file rather than `config`, so that defaults aren't spelled out
+        let mut value: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(&options.config)?))?;
+        let Some(mappings) = value["mappings"].as_array_mut() else {
+            return Err("config has no mappings".into());
+        };
+        info!("imported {} mappings", import.mappings.len());
+        mappings.extend(import.mappings);
+        if let Some(count) = import.pages {
+            if config.pages.as_ref().is_none_or(|p| p.count < count) {
+                value["pages"]["count"] = count.into();
+            }
+        }
+        if !import.skipped.is_empty() {
+            warn!("no room on the device for: {}", import.skipped.join(", "));
+        }
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+    if let Some(Command::Docs { html }) = options.command {
+        print!("{}", if html { docs::html(&config) } else { docs::markdown(&config) });
+        return Ok(());
+    }
+
+    let captures = options.capture.iter()
+        .map(|path| Capture::create(path))
+        .collect::<Result<Vec<_>>>()?;
+    let has_midi = std::iter::once(&config.interface).chain(config.bridge.as_ref()).any(|i| matches!(i, Interface::Midi(_)));
+    for (path, capture) in options.capture.iter().zip(&captures) {
+        if capture.is_midi() && !has_midi {
+            warn!("{} only records MIDI, and there is no MIDI interface, so it will stay empty", path.display());
+        } else if !capture.is_midi() && osc_interface(&config).is_none() {
+            warn!("{} only records OSC, and there is no OSC interface, so it will stay empty", path.display());
+        }
+    }
+
+    let device: Box<dyn DeviceBackend> = if options.no_device {
+        Box::new(NullDevice)
+    } else if config.remote.is_some() {
+        Box::new(RemoteDevice::open(&config)?.ok_or("no remote device configured")?)
+    } else if config.midi_device.is_some() {
+        let Some(device) = open_midi_device(&config)? else {
+            error!("could not find the midi device's ports");
+            return Ok(());
+        };
+        device
+    } else {
+        let Some(device) = UsbDevice::open(&config)? else {
+            let (vendor_id, product_id) = profile::ids(&config)?;
+            error!("could not find device {:04x}:{:04x}", vendor_id, product_id);
+            return Ok(());
+        };
+        Box::new(device)
+    };
+    let device = device.as_ref();
+
+    device.reset()?;
+
+    if let Some(Command::Serve { addr, ref allow }) = options.command {
+        return remote::serve(device, addr, allow);
+    }
+    if let Some(Command::Selftest { timeout }) = options.command {
+        return run_selftest(&config, device, Duration::from_secs(timeout));
+    }
+    run_device(config, device, captures, options.realtime)
+}
+
+/// runs the threads between the device, the interpreter and the interfaces, until autocrap stops.
+fn run_device(mut config: Config, device: &dyn DeviceBackend, mut captures: Vec<Capture>, realtime: bool) -> Result<()> {
+    let interpreter = Arc::new(RwLock::new(new_interpreter(&config)));
+    let (ctrl_tx, ctrl_rx) = mpsc::channel();
+    let (out_tx, mut out_rx) = mpsc::channel();
+    let reader_out_tx = out_tx.clone();
+    let ticker_out_tx = out_tx.clone();
+    let (state_tx, state_rx) = mpsc::channel();
+    let (load_tx, load_rx) = mpsc::channel();
+
+    let control = config.control_addr.map(TcpListener::bind).transpose()?;
+    let control_allow = config.control_allow.clone();
+    for response in interpreter.write().unwrap().init() {
+        out_tx.send(response).unwrap();
+    }
+
+    // the threads which outlive config reloads keep the settings they started with
+    let mut thread_configs = config.threads.clone();
+    if realtime {
+        for name in ["usb-reader", "usb-writer"] {
+            thread_configs.entry(name.to_string()).or_default().realtime = true;
+        }
+    }
+    thread::scope(|s| {
+        spawn_named(s, "usb-writer", &thread_configs, || {
+            run_writer(device, ctrl_rx).unwrap_or_else(|e| fail(e));
+        });
+
+        spawn_named(s, "ticker", &thread_configs, || {
+            run_ticker(&interpreter, ticker_out_tx, state_tx).unwrap_or_else(|e| fail(e));
+        });
+
+        spawn_named(s, "state", &thread_configs, || run_state_saver(state_rx));
+
+        spawn_named(s, "usb-reader", &thread_configs, || {
+            run_reader(&interpreter, device, reader_out_tx).unwrap_or_else(|e| fail(e));
+        });
+
+        if let Some(listener) = control {
+            spawn_named(s, "control", &thread_configs, move || {
+                control::serve(listener, &control_allow, load_tx, check_config).unwrap_or_else(|e| fail(e));
+            });
+        } else {
+            drop(load_tx);
+        }
+
+        // everything which depends on the interfaces is started over when a new config is loaded,
+        // while the device stays open
+        loop {
+            let stop = AtomicBool::new(false);
+            let new_config = thread::scope(|s| {
+                let (obs_tx, obs_rx) = mpsc::channel();
+                let (plugin_tx, plugin_rx) = mpsc::channel();
+                let (osc_out, osc_in) = bind_osc(&config).unwrap_or_else(|e| fail(e)).unzip();
+                let (interface_osc_in, bridge_osc_in) = match config.interface {
+                    Interface::Osc(_) => (osc_in, None),
+                    _ => (None, osc_in)
+                };
+                let (config, stop, out_rx, captures) = (&config, &stop, &mut out_rx, &mut captures);
+                let (receiver_out_tx, bridge_out_tx, plugin_out_tx, ctrl_tx) = (out_tx.clone(), out_tx.clone(), out_tx.clone(), ctrl_tx.clone());
+                let interpreter = &interpreter;
+
+                spawn_named(s, "output", &config.threads, move || {
+                    run_output(config, osc_out, out_rx, ctrl_tx, obs_tx, plugin_tx, captures, stop).unwrap_or_else(|e| fail(e));
                 });
 
-                let receiver_thread = s.spawn(|| {
-                    match config.interface {
-                        Interface::Midi(_) =>
-                            run_midi_receiver(&config, &interpreter, receiver_ctrl_tx).unwrap(),
-                        Interface::Osc(_) =>
-                            run_osc_receiver(&config, &interpreter, receiver_ctrl_tx).unwrap(),
-                    }
+                spawn_named(s, receiver_name(&config.interface), &config.threads, move || {
+                    run_receiver(config, &config.interface, interface_osc_in, interpreter, receiver_out_tx, obs_rx, stop).unwrap_or_else(|e| fail(e));
                 });
 
-                run_reader(&config, &interpreter, &handle, &ctrl_in_endpoint, reader_ctrl_tx).unwrap();
+                if let Some(ref bridge) = config.bridge {
+                    spawn_named(s, receiver_name(bridge), &config.threads, move || {
+                        run_receiver(config, bridge, bridge_osc_in, interpreter, bridge_out_tx, mpsc::channel().1, stop).unwrap_or_else(|e| fail(e));
+                    });
+                }
 
-                receiver_thread.join().unwrap();
-                writer_thread.join().unwrap();
+                spawn_named(s, "plugin", &config.threads, move || {
+                    run_plugin(config, interpreter, plugin_out_tx, plugin_rx, stop).unwrap_or_else(|e| fail(e));
+                });
 
-                // handle.write_interrupt(ctrl_out_endpoint.address, &[0x00, 0x00], DEFAULT_TIMEOUT)?;
+                // without a control socket, this returns right away and the threads run until autocrap stops
+                let new_config = load_rx.recv().ok();
+                if new_config.is_some() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                new_config
             });
+            let Some(new_config) = new_config else {
+                break;
+            };
+
+            if (profile::ids(&new_config).ok(), &new_config.remote) != (profile::ids(&config).ok(), &config.remote) {
+                warn!("the device can't be changed without restarting, keeping the current one");
+            }
+            diagnostics::set_config(&new_config);
+            let mut interpreter = interpreter.write().unwrap();
+            *interpreter = new_interpreter(&new_config);
+            device.reset().unwrap_or_else(|e| warn!("could not reset device: {}", e));
+            for response in interpreter.init() {
+                out_tx.send(response).unwrap();
+            }
+            config = new_config;
+            info!("loaded new config");
         }
-        None => error!("could not find device {:04x}:{:04x}", config.vendor_id, config.product_id),
-    }
+    });
 
     Ok(())
 }
 
-fn write_init<T: UsbContext>(handle: &mut DeviceHandle<T>, address: u8) -> Result<()> {
-    let write = |bytes| handle.write_interrupt(address, bytes, DEFAULT_TIMEOUT);
+#[cfg(feature = "midi")]
+fn open_midi_device(config: &Config) -> Result<Option<Box<dyn DeviceBackend>>> {
+    Ok(MidiDevice::open(config)?.map(|device| Box::new(device) as Box<dyn DeviceBackend>))
+}
 
-    // b0 looks to be a "start" byte, 00 00 is reset (all leds off)
-    write(&[0xb0, 0x00, 0x00])?;
+#[cfg(not(feature = "midi"))]
+fn open_midi_device(_config: &Config) -> Result<Option<Box<dyn DeviceBackend>>> {
+    Err(without_feature("midi_device", "midi").into())
+}
 
-    Ok(())
+/// starts a thread with a name, for debuggers and profilers, and with the settings the config has for it.
+fn spawn_named<'scope>(
+    s: &'scope thread::Scope<'scope, '_>,
+    name: &'static str,
+    thread_configs: &BTreeMap<String, ThreadConfig>,
+    f: impl FnOnce() + Send + 'scope
+) {
+    let thread_config = thread_configs.get(name).cloned();
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn_scoped(s, move || {
+            if let Some(thread_config) = thread_config {
+                threads::configure(name, &thread_config);
+            }
+            f()
+        })
+        .unwrap_or_else(|e| fail(e.into()));
 }
 
-fn open_device<T: UsbContext>(
-    context: &mut T,
-    vid: u16,
-    pid: u16,
-) -> Option<(Device<T>, DeviceDescriptor, DeviceHandle<T>)> {
-    let devices = match context.devices() {
-        Ok(d) => d,
-        Err(_) => return None,
-    };
+fn receiver_name(interface: &Interface) -> &'static str {
+    match interface {
+        Interface::Midi(_) => "midi-rx",
+        Interface::Osc(_) => "osc-rx",
+        Interface::Obs(_) => "obs-rx",
+    }
+}
 
-    for device in devices.iter() {
-        let device_desc = match device.device_descriptor() {
-            Ok(d) => d,
-            Err(_) => continue,
-        };
+/// something the config uses which this build was made without, and the cargo feature it needs.
+fn missing_feature(config: &Config) -> Option<(&'static str, &'static str)> {
+    let interfaces = std::iter::once(&config.interface).chain(config.bridge.as_ref());
+    let needed = interfaces
+        .map(|interface| match interface {
+            Interface::Midi(_) => ("the midi interface", "midi"),
+            Interface::Osc(_) => ("the osc interface", "osc"),
+            Interface::Obs(_) => ("the obs interface", "web"),
+        })
+        .chain(config.midi_device.as_ref().map(|_| ("midi_device", "midi")));
+    let mut missing = needed.filter(|&(_, feature)| match feature {
+        "midi" => !cfg!(feature = "midi"),
+        "osc" => !cfg!(feature = "osc"),
+        _ => !cfg!(feature = "web"),
+    });
+    missing.next()
+}
 
-        if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
-            match device.open() {
-                Ok(handle) => return Some((device, device_desc, handle)),
-                Err(e) => panic!("Device found but failed to open: {}", e),
-            }
+fn without_feature(what: &str, feature: &str) -> String {
+    format!("the config uses {}, but this autocrap was built without the {} feature", what, feature)
+}
+
+/// checks for problems which would keep a config from working. `value` is the JSON it was parsed from.
+fn check_config(config: &Config, value: &serde_json::Value) -> Result<()> {
+    if let Some((what, feature)) = missing_feature(config) {
+        return Err(without_feature(what, feature).into());
+    }
+    if config.midi_device.is_none() && config.remote.is_none() {
+        profile::ids(config)?;
+    }
+    for mapping in &config.mappings {
+        mapping.expand()?;
+    }
+    for mapping in config.mappings.iter().flat_map(|m| m.expand_iter()) {
+        let too_high = mapping.midi_table.as_ref().and_then(|table| table.0.iter().find(|(_, &out)| out > 0x7f));
+        if let Some((val, out)) = too_high {
+            return Err(format!("{}: midi_table sends {} for {}, but MIDI values only go up to 127", mapping.name, out, val).into());
+        }
+    }
+    if let Some(addr) = config.control_addr {
+        if !addr.ip().is_loopback() && config.control_allow.is_empty() {
+            return Err(format!("control_addr {} is beyond localhost, so it needs control_allow with the addresses to let in", addr).into());
+        }
+    }
+    for lint in profile::lint(config) {
+        warn!("{}", lint);
+    }
+    for name in config.threads.keys().filter(|name| !THREAD_NAMES.contains(&name.as_str())) {
+        warn!("threads: there is no {} thread, known threads are: {}", name, THREAD_NAMES.join(", "));
+    }
+
+    if let Some(osc) = osc_interface(config) {
+        if osc.out_addr == osc.in_addr {
+            return Err("out_addr and in_addr are the same, so autocrap would hear itself".into());
+        }
+        if osc.host_addr == Some(osc.out_addr) {
+            return Err("host_addr can't be out_addr, which is where the OSC program listens".into());
+        }
+        if !osc.prefix.is_empty() && (!osc.prefix.starts_with('/') || osc.prefix.ends_with('/')) {
+            return Err(format!("prefix {} should start with / and not end with one, like /nocturn1", osc.prefix).into());
+        }
+    }
+
+    let unknown = fields::unknown(value, config);
+    for field in &unknown {
+        if config.strict {
+            error!("{}", field);
+        } else {
+            warn!("{}", field);
+        }
+    }
+
+    if config.strict {
+        if !unknown.is_empty() {
+            return Err("unknown fields in the config, and strict is on".into());
+        }
+        let problems = config.mapping_problems();
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        if !problems.is_empty() {
+            return Err("mappings which would not work, and strict is on".into());
+        }
+    }
+
+    if config.on_conflict == ConflictPolicy::Reject {
+        let conflicts = config.find_conflicts();
+        for conflict in &conflicts {
+            error!("{}", conflict);
+        }
+        if !conflicts.is_empty() {
+            return Err("conflicting mappings".into());
         }
     }
 
-    None
+    match (&config.interface, &config.bridge) {
+        (_, None) | (Interface::Midi(_), Some(Interface::Osc(_))) | (Interface::Osc(_), Some(Interface::Midi(_))) => Ok(()),
+        _ => Err("bridge must be an OSC interface when the interface is MIDI, or vice versa".into())
+    }
 }
 
-fn find_endpoint<T: UsbContext>(
-    device: &mut Device<T>,
-    device_desc: &DeviceDescriptor,
-    predicate: impl Fn(Endpoint) -> bool
-) -> Option<Endpoint> {
-    for n in 0..device_desc.num_configurations() {
-        let config_desc = match device.config_descriptor(n) {
-            Ok(c) => c,
-            Err(_) => continue,
+fn run_reader(
+    interpreter: &Arc<RwLock<Interpreter>>,
+    device: &dyn DeviceBackend,
+    out_tx: mpsc::Sender<Response>
+) -> Result<()> {
+    loop {
+        let events = match device.read_events(DEFAULT_TIMEOUT) {
+            Ok(events) => events,
+            Err(e) if e.is::<GaveUp>() => return Err(e),
+            Err(_) => continue
         };
+        let received = Instant::now();
 
-        for interface in config_desc.interfaces() {
-            for interface_desc in interface.descriptors() {
-                for endpoint_desc in interface_desc.endpoint_descriptors() {
-                    let endpoint = Endpoint {
-                        config: config_desc.number(),
-                        iface: interface_desc.interface_number(),
-                        setting: interface_desc.setting_number(),
-                        address: endpoint_desc.address(),
-                        transfer_type: endpoint_desc.transfer_type(),
-                        direction: endpoint_desc.direction()
-                    };
-
-                    if predicate(endpoint) {
-                        return Some(endpoint);
-                    }
+        if device.reconnected() {
+            for response in interpreter.write().unwrap().redraw() {
+                out_tx.send(response)?;
+            }
+        }
+
+        for [num, val] in events {
+            trace!("bytes: {:02x?}", [num, val]);
+
+            if !handle(interpreter, &out_tx, received, |i| i.handle_ctrl(num, val))? {
+                unhandled::warn(format!("data {:02x}", num), format_args!("data: {:02x?}", [num, val]));
+            }
+        }
+    }
+}
+
+/// steps through every mapping, blinking its LED until the control is actuated or `timeout` passes,
+/// then reports which controls never responded.
+fn run_selftest(
+    config: &Config,
+    device: &dyn DeviceBackend,
+    timeout: Duration
+) -> Result<()> {
+    let mappings: Vec<_> = config.mappings.iter()
+        .flat_map(|m| m.expand_iter())
+        .filter(|m| !m.ctrl_in_nums().is_empty())
+        .collect();
+
+    println!("self-test: actuate each control when asked. its LED, if any, should be blinking.");
+    let mut dead = vec![];
+    let mut dead_leds = BTreeSet::new();
+    for (i, mapping) in mappings.iter().enumerate() {
+        let nums = mapping.ctrl_in_nums();
+        println!("[{}/{}] {} (control {:?})", i + 1, mappings.len(), mapping.name, nums);
+
+        let start = Instant::now();
+        let mut led_on = false;
+        let mut responded = false;
+        while start.elapsed() < timeout && !responded {
+            let blink_on = (start.elapsed().as_millis() / SELFTEST_BLINK_INTERVAL.as_millis()).is_multiple_of(2);
+            if let (Some(num), true) = (mapping.ctrl_out_num, blink_on != led_on) {
+                led_on = blink_on;
+                if device.write_feedback(&[num, if led_on { 0x7f } else { 0x00 }]).is_err() {
+                    dead_leds.insert(mapping.name.as_str());
                 }
             }
+
+            let Ok(events) = device.read_events(SELFTEST_BLINK_INTERVAL) else {
+                continue;
+            };
+            responded = events.iter().any(|[num, _]| nums.contains(num));
+        }
+
+        if let Some(num) = mapping.ctrl_out_num {
+            let _ = device.write_feedback(&[num, 0x00]);
+        }
+
+        if responded {
+            println!("  ok");
+        } else {
+            println!("  no response!");
+            dead.push(mapping.name.clone());
         }
     }
 
-    None
+    println!();
+    println!("{} of {} controls responded.", mappings.len() - dead.len(), mappings.len());
+    if !dead.is_empty() {
+        println!("no response from: {}", dead.join(", "));
+    }
+    if !dead_leds.is_empty() {
+        println!("could not write LEDs of: {}", dead_leds.into_iter().collect::<Vec<_>>().join(", "));
+    }
+    Ok(())
 }
 
-fn configure_endpoint<T: UsbContext>(
-    handle: &mut DeviceHandle<T>,
-    endpoint: &Endpoint,
+/// an interpreter for `config`, on the page it was on when autocrap last ran.
+fn new_interpreter(config: &Config) -> Interpreter {
+    let mut interpreter = Interpreter::new(config);
+    if let Some(ref path) = config.state_file {
+        match state::load(Path::new(path)) {
+            Ok(State { page: Some(page) }) => {
+                info!("restoring page {}", page);
+                interpreter.restore_page(page);
+            },
+            Ok(_) => {},
+            Err(e) => warn!("could not read state from {}: {}", path, e)
+        }
+    }
+    interpreter
+}
+
+fn run_ticker(
+    interpreter: &Arc<RwLock<Interpreter>>,
+    out_tx: mpsc::Sender<Response>,
+    state_tx: mpsc::Sender<(PathBuf, State)>
 ) -> Result<()> {
-    // handle.set_active_configuration(endpoint.config)?;
-    info!("configure_endpoint {:?}", endpoint);
-    handle.claim_interface(endpoint.iface)?;
-    // handle.set_alternate_setting(endpoint.iface, endpoint.setting)?;
-    Ok(())
+    let mut saved_page = interpreter.read().unwrap().latched_page();
+    loop {
+        let (ticks, slow) = {
+            let interpreter = interpreter.read().unwrap();
+            (interpreter.ticks(), interpreter.slow_tick())
+        };
+        thread::sleep(if slow { IDLE_TICK_INTERVAL } else { TICK_INTERVAL });
+
+        let now = Instant::now();
+        unhandled::summarize(now);
+        if ticks {
+            let mut interpreter = interpreter.write().unwrap();
+            for mut response in interpreter.tick(now) {
+                response.received = Some(now);
+                out_tx.send(response)?;
+            }
+        }
+
+        let interpreter = interpreter.read().unwrap();
+        let page = interpreter.latched_page();
+        if page == saved_page {
+            continue;
+        }
+        saved_page = page;
+        if let Some(path) = interpreter.state_file() {
+            state_tx.send((PathBuf::from(path), State { page }))?;
+        }
+    }
 }
 
-fn run_reader<T: UsbContext>(
+/// writes the state the ticker hands over, so that waiting for the disk doesn't hold up the ticks.
+fn run_state_saver(state_rx: mpsc::Receiver<(PathBuf, State)>) {
+    while let Ok(next) = state_rx.recv() {
+        // only the newest state matters
+        let (path, state) = state_rx.try_iter().last().unwrap_or(next);
+        if let Err(e) = state::save(&path, &state) {
+            warn!("could not save state to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_output(
     config: &Config,
-    interpreter: &Arc<RwLock<Interpreter>>,
-    handle: &DeviceHandle<T>,
-    endpoint: &Endpoint,
-    ctrl_tx: mpsc::Sender<Vec<u8>>
+    osc_sock: Option<UdpSocket>,
+    out_rx: &mut mpsc::Receiver<Response>,
+    ctrl_tx: mpsc::Sender<DeviceWrite>,
+    obs_tx: mpsc::Sender<OscResponse>,
+    plugin_tx: mpsc::Sender<OscResponse>,
+    captures: &mut [Capture],
+    stop: &AtomicBool
 ) -> Result<()> {
-    let osc = if let Interface::Osc(OscInterface { host_addr, out_addr, .. }) = config.interface {
-        let sock = UdpSocket::bind(host_addr)?;
-        Some((sock, out_addr))
-    } else {
-        None
-    };
+    let interfaces: Vec<&Interface> = std::iter::once(&config.interface).chain(config.bridge.as_ref()).collect();
+    let obs = interfaces.iter().any(|i| matches!(i, Interface::Obs(_)));
+
+    let osc = osc_interface(config).zip(osc_sock).map(|(osc, sock)| (sock, osc));
+    let mut osc_reconnect = Reconnect::new("osc destination", &config.reconnect);
+
+    let mut midi = None;
+    let mut page_midi = vec![];
+    let mut out_filters: &[_] = &[];
+    if let Some(interface) = interfaces.iter().find_map(|i| if let Interface::Midi(midi) = i { Some(midi) } else { None }) {
+        midi = Some(MidiOut::open(config, &interface.client_name, &interface.out_port)?);
+        out_filters = &interface.out_filters;
+        for port in &interface.page_out_ports {
+            page_midi.push(MidiOut::open(config, &interface.client_name, port)?);
+        }
 
-    let mut midi = if let Interface::Midi(ref interface) = config.interface {
-        let client_name = &interface.client_name;
-        let midi_out = MidiOutput::new(client_name)?;
-        match interface.out_port {
-            MidiPort::Index(index) =>
-                Some(midi_out.ports().remove(index))
-                .map(|p| (midi_out.port_name(&p).unwrap(), midi_out.connect(&p, client_name).unwrap())),
-            MidiPort::Name(ref name) =>
-                midi_out.ports().into_iter().find(|p| &midi_out.port_name(&p).unwrap() == name)
-                .map(|p| (midi_out.port_name(&p).unwrap(), midi_out.connect(&p, client_name).unwrap())),
-            #[cfg(unix)]
-            MidiPort::Virtual(ref name) =>
-                Some((client_name.to_string(), midi_out.create_virtual(client_name).unwrap())),
-            #[cfg(not(unix))]
-            MidiPort::Virtual(ref name) => {
-                unimplemented!("virtual midi ports are currently unsupported on non-unix systems")
+        if let Some(out) = midi.as_mut().filter(|_| !interface.on_activate.is_empty()) {
+            if out.connected()? {
+                info!("sending {} activation messages", interface.on_activate.len());
+                for data in &interface.on_activate {
+                    for capture in captures.iter_mut() {
+                        capture.midi(data, Instant::now())?;
+                    }
+                    out.send(data)?;
+                }
+            } else {
+                warn!("midi out port is not there, activation messages not sent");
             }
         }
-    } else {
-        None
-    };
-
-    let mut all_bytes = [0u8; 8];
+    }
 
-    loop {
-        let Ok(num_bytes) =
-            handle.read_interrupt(endpoint.address, &mut all_bytes, DEFAULT_TIMEOUT)
-        else {
-            continue;
+    // output may never pause, e.g. with the clock running, so this can't wait for a timeout to notice
+    while !stop.load(Ordering::Relaxed) {
+        let response = match out_rx.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(response) => response,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // nothing is being sent, a good time to write out what the captures buffered
+                for capture in captures.iter_mut() {
+                    capture.flush()?;
+                }
+                continue;
+            },
+            Err(e) => return Err(e.into())
         };
+        let at = response.received.unwrap_or_else(Instant::now);
+        if let Some(received) = response.received {
+            trace!("response {:?} after its event", received.elapsed());
+        }
 
-        trace!("read({:?}): {:02x?}", num_bytes, &all_bytes[..num_bytes]);
-        let mut i = 0;
-        while i+1 < num_bytes {
-            if all_bytes[i] == 0xb0 {
-                i += 1;
-                continue
+        if obs {
+            for osc_response in response.osc {
+                // on reload, the obs thread may have stopped already
+                if let Err(e) = obs_tx.send(osc_response) {
+                    if !stop.load(Ordering::Relaxed) {
+                        return Err(e.into());
+                    }
+                }
+            }
+        } else if let Some((sock, interface)) = osc.as_ref() {
+            let mut packets = vec![];
+            for OscResponse { addr, args } in response.osc {
+                let msg = OscMessage {
+                    addr: interface.prefixed(&addr),
+                    args,
+                };
+                for capture in captures.iter_mut() {
+                    capture.osc(&msg, at)?;
+                }
+                packets.push(OscPacket::Message(msg));
+            }
+            // everything an event caused goes together, timed as the event
+            if let (true, Some(received), false) = (interface.timetag, response.received, packets.is_empty()) {
+                packets = vec![OscPacket::Bundle(OscBundle {
+                    timetag: OscTime::try_from(capture::system_time(received))?,
+                    content: packets
+                })];
             }
 
-            let bytes = &all_bytes[i..i+2];
-            i += 2;
-
-            trace!("bytes: {:02x?}", bytes);
+            for msg in packets {
+                debug!("send osc: {:?}", msg);
+                let msg_buf = encoder::encode(&msg)?;
+                diagnostics::record_osc(Direction::Out, &msg_buf);
 
-            let num = bytes[0];
-            let val = bytes[1];
+                // while the destination is unreachable, messages are dropped until the next attempt is due
+                if !osc_reconnect.ready(Instant::now()) {
+                    continue;
+                }
+                match sock.send_to(&msg_buf, interface.out_addr) {
+                    Ok(_) => osc_reconnect.succeeded(),
+                    Err(e) => {
+                        warn!("could not send osc to {}: {}", interface.out_addr, e);
+                        osc_reconnect.failed(Instant::now())?;
+                    }
+                }
+            }
+        }
 
-            let Some(response) = interpreter.write().unwrap().handle_ctrl(num, val) else {
-                warn!("unhandled data: {:02x?}", bytes);
-                continue;
+        for MidiResponse { data, page } in response.midi {
+            // messages for a page without its own port, or whose port is gone, go to the main port
+            let page_index = page.and_then(|p| (p as usize).checked_sub(1)).filter(|&i| i < page_midi.len());
+            let out = match page_index {
+                Some(i) if page_midi[i].connected()? => &mut page_midi[i],
+                _ => match midi.as_mut() {
+                    Some(out) => out,
+                    None => continue
+                }
             };
+            if !out.connected()? {
+                continue;
+            }
 
-            if let Some((sock, out_addr)) = osc.as_ref() {
-                if let Some(OscResponse { addr, args }) = response.osc {
-                    let msg = OscPacket::Message(OscMessage {
-                        addr: addr,
-                        args: args,
-                    });
-                    debug!("send osc: {:?}", msg);
-                    let msg_buf = encoder::encode(&msg)?;
+            let data = filter::apply_all(out_filters, data);
+            for capture in captures.iter_mut() {
+                capture.midi(&data, at)?;
+            }
+            out.send(&data)?;
+        }
 
-                    sock.send_to(&msg_buf, out_addr)?;
+        for plugin_response in response.plugin {
+            // likewise the plugin thread
+            if let Err(e) = plugin_tx.send(plugin_response) {
+                if !stop.load(Ordering::Relaxed) {
+                    return Err(e.into());
                 }
             }
+        }
 
-            if let Some((_, out_conn)) = midi.as_mut() {
-                if let Some(MidiResponse { data }) = response.midi {
-                    debug!("send midi: {:02x?}", data);
-                    out_conn.send(&data)?;
+        for ctrl in response.ctrl {
+            ctrl_tx.send(DeviceWrite::Led(ctrl))?;
+        }
+        for motor in response.motor {
+            ctrl_tx.send(DeviceWrite::Motor(motor))?;
+        }
+    }
+    Ok(())
+}
+
+/// a MIDI output port, opened again following the reconnect policy if it goes away.
+#[cfg(feature = "midi")]
+struct MidiOut {
+    client_name: String,
+    port: MidiPort,
+    conn: Option<(String, MidiOutputConnection)>,
+    reconnect: Reconnect,
+}
+
+#[cfg(feature = "midi")]
+impl MidiOut {
+    fn open(config: &Config, client_name: &str, port: &MidiPort) -> Result<MidiOut> {
+        Ok(MidiOut {
+            client_name: client_name.to_string(),
+            port: port.clone(),
+            conn: open_midi_out(client_name, port)?,
+            reconnect: Reconnect::new(&format!("midi out port {:?}", port), &config.reconnect),
+        })
+    }
+
+    /// whether the port is there, trying to open it again if an attempt is due.
+    fn connected(&mut self) -> Result<bool> {
+        if self.conn.is_none() && self.reconnect.ready(Instant::now()) {
+            match open_midi_out(&self.client_name, &self.port) {
+                Ok(Some(conn)) => {
+                    self.conn = Some(conn);
+                    self.reconnect.succeeded();
+                },
+                Ok(None) => self.reconnect.failed(Instant::now())?,
+                Err(e) => {
+                    debug!("could not open midi out port {:?}: {}", self.port, e);
+                    self.reconnect.failed(Instant::now())?;
                 }
             }
+        }
+        Ok(self.conn.is_some())
+    }
 
-            if let Some(CtrlResponse { data }) = response.ctrl {
-                ctrl_tx.send(data)?;
-            }
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        let Some((ref name, ref mut conn)) = self.conn else {
+            return Ok(());
+        };
+
+        debug!("send midi to {}: {:02x?}", name, data);
+        diagnostics::record_midi(Direction::Out, data);
+        if let Err(e) = conn.send(data) {
+            warn!("lost midi out port {}: {}", name, e);
+            self.conn = None;
+            self.reconnect.failed(Instant::now())?;
+            return Ok(());
         }
+        midiloop::sent(data);
+        Ok(())
+    }
+}
+
+/// without the midi feature there are no ports to send to. `check_config` turns away configs which would
+/// need them.
+#[cfg(not(feature = "midi"))]
+struct MidiOut;
+
+#[cfg(not(feature = "midi"))]
+impl MidiOut {
+    fn open(_config: &Config, _client_name: &str, _port: &MidiPort) -> Result<MidiOut> {
+        Err(without_feature("the midi interface", "midi").into())
+    }
+
+    fn connected(&mut self) -> Result<bool> {
+        Ok(false)
     }
+
+    fn send(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// connects to an output port, or creates a virtual one. each call creates a new client,
+/// so several virtual ports can be published under the same client name.
+#[cfg(feature = "midi")]
+fn open_midi_out(client_name: &str, port: &MidiPort) -> Result<Option<(String, MidiOutputConnection)>> {
+    let midi_out = MidiOutput::new(client_name)?;
+    let found = match port {
+        MidiPort::Index(index) => midi_out.ports().get(*index).cloned(),
+        MidiPort::Name(ref name) => midi_out.ports().into_iter().find(|p| midi_out.port_name(p).is_ok_and(|n| &n == name)),
+        #[cfg(unix)]
+        MidiPort::Virtual(ref name) => {
+            let conn = midi_out.create_virtual(name).map_err(|e| e.to_string())?;
+            info!("midi out port: {}", name);
+            return Ok(Some((name.to_string(), conn)));
+        },
+        #[cfg(not(unix))]
+        MidiPort::Virtual(_) => return Err("virtual midi ports are currently unsupported on non-unix systems".into())
+    };
+    let Some(found) = found else {
+        return Ok(None);
+    };
+
+    // the port may have gone away since it was listed
+    let name = midi_out.port_name(&found)?;
+    let conn = midi_out.connect(&found, client_name).map_err(|e| e.to_string())?;
+    info!("midi out port: {}", name);
+    Ok(Some((name, conn)))
 }
 
-fn run_writer<T: UsbContext>(
-    handle: &DeviceHandle<T>,
-    endpoint: &Endpoint,
-    ctrl_rx: mpsc::Receiver<Vec<u8>>
+/// something to send to the device.
+enum DeviceWrite {
+    Led(CtrlResponse),
+    Motor(MotorResponse),
+}
+
+fn run_writer(
+    device: &dyn DeviceBackend,
+    ctrl_rx: mpsc::Receiver<DeviceWrite>
 ) -> Result<()> {
     loop {
-        let data = ctrl_rx.recv()?;
-        debug!("send ctrl: {:02x?}", data);
-        handle.write_interrupt(endpoint.address, &data, DEFAULT_TIMEOUT)?;
+        // send everything that piled up since the last write together
+        let mut leds = vec![];
+        let mut colors = vec![];
+        let mut raws = vec![];
+        let mut motors = vec![];
+        for write in std::iter::once(ctrl_rx.recv()?).chain(ctrl_rx.try_iter()) {
+            match write {
+                DeviceWrite::Led(CtrlResponse { data, color: None, raw: false }) => leds.push(data),
+                DeviceWrite::Led(CtrlResponse { data, raw: true, .. }) => raws.push(data),
+                DeviceWrite::Led(CtrlResponse { data, color: Some(color), .. }) => colors.push((data, color)),
+                DeviceWrite::Motor(motor) => motors.push(motor),
+            }
+        }
+        if !leds.is_empty() {
+            device.write_feedback_batch(&leds)?;
+        }
+        for (data, color) in colors {
+            device.write_color(&data, color)?;
+        }
+        for data in raws {
+            device.write_raw(&data)?;
+        }
+        for MotorResponse { num, position } in motors {
+            device.write_motor(num, position)?;
+        }
     }
 }
 
-fn run_osc_receiver(
+// which of the arguments are used depends on the interfaces in the build
+#[cfg_attr(not(all(feature = "midi", feature = "osc", feature = "web")), allow(unused_variables))]
+fn run_receiver(
     config: &Config,
+    interface: &Interface,
+    osc_sock: Option<UdpSocket>,
     interpreter: &Arc<RwLock<Interpreter>>,
-    ctrl_tx: mpsc::Sender<Vec<u8>>
+    out_tx: mpsc::Sender<Response>,
+    obs_rx: mpsc::Receiver<OscResponse>,
+    stop: &AtomicBool
 ) -> Result<()> {
-    let Interface::Osc(OscInterface { in_addr, .. }) = config.interface else {
-        return Ok(())
+    match interface {
+        #[cfg(feature = "midi")]
+        Interface::Midi(interface) => run_midi_receiver(config, interface, interpreter, out_tx, stop),
+        #[cfg(feature = "osc")]
+        Interface::Osc(interface) => run_osc_receiver(interface, osc_sock.ok_or("the osc socket is not open")?, interpreter, out_tx, stop),
+        #[cfg(feature = "web")]
+        Interface::Obs(interface) => run_obs_receiver(config, interface, interpreter, out_tx, obs_rx, stop),
+        // turned away by check_config
+        #[allow(unreachable_patterns)]
+        _ => Err(format!("{} is not in this build", receiver_name(interface)).into())
+    }
+}
+
+/// the OSC interface or bridge, if there is one.
+fn osc_interface(config: &Config) -> Option<&OscInterface> {
+    std::iter::once(&config.interface).chain(config.bridge.as_ref())
+        .find_map(|i| if let Interface::Osc(osc) = i { Some(osc) } else { None })
+}
+
+/// binds the sockets for sending and receiving OSC. they are one and the same when `host_addr` is left out
+/// or is `in_addr`, since binding the same address twice would fail.
+fn bind_osc(config: &Config) -> Result<Option<(UdpSocket, UdpSocket)>> {
+    let Some(interface) = osc_interface(config) else {
+        return Ok(None);
     };
 
-    let sock = UdpSocket::bind(in_addr)?;
-    info!("listening to {}", in_addr);
+    let recv_sock = UdpSocket::bind(interface.in_addr)?;
+    sockopt::apply(&recv_sock, &interface.socket)?;
+    info!("listening to {}", interface.in_addr);
+
+    let send_sock = match interface.host_addr {
+        Some(host_addr) if !interface.shares_socket() => {
+            let sock = UdpSocket::bind(host_addr)?;
+            sockopt::apply(&sock, &interface.socket)?;
+            sock
+        },
+        _ => recv_sock.try_clone()?
+    };
+    info!("sending from {}", send_sock.local_addr()?);
+    Ok(Some((send_sock, recv_sock)))
+}
+
+#[cfg(feature = "osc")]
+fn run_osc_receiver(
+    interface: &OscInterface,
+    sock: UdpSocket,
+    interpreter: &Arc<RwLock<Interpreter>>,
+    out_tx: mpsc::Sender<Response>,
+    stop: &AtomicBool
+) -> Result<()> {
 
+    let mut rejected = HashSet::new();
+    let mut unauthorized = HashSet::new();
+    let mut schedule = Schedule::new(Duration::from_millis(interface.bundle_tolerance_ms));
+    let mut recv_errors = RecvErrors::default();
     let mut buf = [0u8; rosc::decoder::MTU];
-    loop {
+    while !stop.load(Ordering::Relaxed) {
+        let now = SystemTime::now();
+        for msg in schedule.due(now) {
+            handle_bundled_osc(interface, interpreter, &out_tx, &msg)?;
+        }
+        // wake up in time for the next scheduled message
+        let wait = schedule.next(now).map_or(STOP_POLL_INTERVAL, |wait| wait.clamp(Duration::from_millis(1), STOP_POLL_INTERVAL));
+        sock.set_read_timeout(Some(wait))?;
+
         match sock.recv_from(&mut buf) {
             Ok((size, addr)) => {
-                let (_, packet) = rosc::decoder::decode_udp(&buf[..size])?;
-                match packet {
-                    OscPacket::Message(msg) => {
-                        debug!("recv osc: {} {:?}", msg.addr, msg.args);
-                        let Some(response) = interpreter.write().unwrap().handle_osc(&msg) else {
-                            warn!("unhandled osc message: with size {} from {}: {} {:?}", size, addr, msg.addr, msg.args);
-                            continue;
-                        };
+                let received = Instant::now();
+                if !interface.allows(addr) {
+                    if rejected.insert(addr.ip()) {
+                        warn!("ignoring osc from {}, which is not in allow", addr.ip());
+                    }
+                    continue;
+                }
 
-                        trace!("osc in response: {:?}", response);
+                let packet = match rosc::decoder::decode_udp(&buf[..size]) {
+                    Ok((_, packet)) => packet,
+                    Err(e) => {
+                        recv_errors.note("osc", format_args!("could not decode packet from {}: {}", addr, e));
+                        continue;
+                    }
+                };
+                match packet {
+                    OscPacket::Message(mut msg) => {
+                        let mut packet_buf = &buf[..size];
+                        let stripped;
+                        if let (Some(password), false) = (&interface.password, interface.is_forward(addr)) {
+                            if !matches!(msg.args.first(), Some(OscType::String(s)) if s == password) {
+                                if unauthorized.insert(addr.ip()) {
+                                    warn!("ignoring osc from {} without the password, starting with {}", addr.ip(), msg.addr);
+                                }
+                                continue;
+                            }
+                            msg.args.remove(0);
+                            stripped = encoder::encode(&OscPacket::Message(msg.clone()))?;
+                            packet_buf = &stripped;
+                        }
 
-                        let Some(CtrlResponse { data }) = response.ctrl else {
+                        debug!("recv osc: {} {:?}", msg.addr, msg.args);
+                        diagnostics::record_osc(Direction::In, packet_buf);
+                        let local = interface.unprefixed(&msg.addr)
+                            .map(|local_addr| OscMessage { addr: local_addr.to_string(), args: msg.args.clone() });
+                        let replies = local.as_ref().and_then(|local| interpreter.read().unwrap().query(local));
+                        if let Some(replies) = replies {
+                            for reply in replies {
+                                match interface.reply_to {
+                                    ReplyTo::OutAddr => out_tx.send(reply.into())?,
+                                    ReplyTo::Sender => {
+                                        let reply = OscPacket::Message(OscMessage { addr: interface.prefixed(&reply.addr), args: reply.args });
+                                        debug!("reply osc to {}: {:?}", addr, reply);
+                                        if let Err(e) = sock.send_to(&encoder::encode(&reply)?, addr) {
+                                            recv_errors.note("osc", format_args!("could not reply to {}: {}", addr, e));
+                                        }
+                                    }
+                                }
+                            }
                             continue;
+                        }
+                        let handled = match local {
+                            Some(local) => handle(interpreter, &out_tx, received, |i| i.handle_osc(&local))?,
+                            None => false
                         };
-
-                        ctrl_tx.send(data)?
+                        if !handled {
+                            if let Some(to) = interface.forward_to(addr, std::iter::once(msg.addr.as_str())) {
+                                debug!("forward osc to {}: {} {:?}", to, msg.addr, msg.args);
+                                if let Err(e) = sock.send_to(packet_buf, to) {
+                                    recv_errors.note("osc", format_args!("could not forward to {}: {}", to, e));
+                                }
+                                continue;
+                            }
+
+                            unhandled::warn(
+                                format!("osc {}", msg.addr),
+                                format_args!("osc message: with size {} from {}: {} {:?}", size, addr, msg.addr, msg.args)
+                            );
+                        }
                     }
                     OscPacket::Bundle(bundle) => {
+                        // the messages in a bundle can't be checked for the password one by one
+                        if interface.password.is_some() && !interface.is_forward(addr) {
+                            if unauthorized.insert(addr.ip()) {
+                                warn!("ignoring osc bundles from {}, bundles are not accepted with a password", addr.ip());
+                            }
+                            continue;
+                        }
+
                         debug!("recv osc bundle: {:?}", bundle);
-                        warn!("unhandled osc bundle: {:?}", bundle);
+                        diagnostics::record_osc(Direction::In, &buf[..size]);
+                        let addrs = bundle_addrs(&bundle.content);
+                        if let Some(to) = interface.forward_to(addr, addrs.iter().map(String::as_str)) {
+                            debug!("forward osc bundle to {}", to);
+                            if let Err(e) = sock.send_to(&buf[..size], to) {
+                                recv_errors.note("osc", format_args!("could not forward bundle to {}: {}", to, e));
+                            }
+                            continue;
+                        }
+
+                        for msg in schedule.bundle(SystemTime::now(), bundle) {
+                            handle_bundled_osc(interface, interpreter, &out_tx, &msg)?;
+                        }
                     }
                 }
             }
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {},
+            Err(e) if is_transient(&e) => recv_errors.note("osc", e),
             Err(e) => {
                 error!("error receiving from socket: {}", e);
                 break;
@@ -403,70 +1290,504 @@ fn run_osc_receiver(
     Ok(())
 }
 
-fn run_midi_receiver(
+/// applies a message from a bundle, once its time has come.
+#[cfg(feature = "osc")]
+fn handle_bundled_osc(
+    interface: &OscInterface,
+    interpreter: &Arc<RwLock<Interpreter>>,
+    out_tx: &mpsc::Sender<Response>,
+    msg: &OscMessage
+) -> Result<()> {
+    debug!("recv osc from bundle: {} {:?}", msg.addr, msg.args);
+    let handled = match interface.unprefixed(&msg.addr) {
+        // scheduled messages count as received when their time comes
+        Some(addr) => {
+            let local = OscMessage { addr: addr.to_string(), args: msg.args.clone() };
+            handle(interpreter, out_tx, Instant::now(), |i| i.handle_osc(&local))?
+        },
+        None => false
+    };
+    if !handled {
+        unhandled::warn(format!("osc {}", msg.addr), format_args!("osc message in a bundle: {} {:?}", msg.addr, msg.args));
+    }
+    Ok(())
+}
+
+/// passes an event to the interpreter and queues the response, stamped with when the event was received.
+/// the response is queued before letting go of the interpreter, so that responses go out in the order
+/// their events were handled. returns whether the event was handled.
+fn handle(
+    interpreter: &Arc<RwLock<Interpreter>>,
+    out_tx: &mpsc::Sender<Response>,
+    received: Instant,
+    f: impl FnOnce(&mut Interpreter) -> Option<Response>
+) -> Result<bool> {
+    let mut interpreter = interpreter.write().unwrap();
+    let Some(mut response) = f(&mut interpreter) else {
+        return Ok(false);
+    };
+
+    trace!("response: {:?}", response);
+    response.received = Some(received);
+    out_tx.send(response)?;
+    Ok(true)
+}
+
+/// addresses of all messages in a bundle, including nested bundles.
+#[cfg(feature = "osc")]
+fn bundle_addrs(content: &[OscPacket]) -> Vec<String> {
+    content.iter()
+        .flat_map(|packet| match packet {
+            OscPacket::Message(msg) => vec![msg.addr.clone()],
+            OscPacket::Bundle(bundle) => bundle_addrs(&bundle.content)
+        })
+        .collect()
+}
+
+#[cfg(feature = "web")]
+fn run_obs_receiver(
     config: &Config,
+    interface: &ObsInterface,
     interpreter: &Arc<RwLock<Interpreter>>,
-    ctrl_tx: mpsc::Sender<Vec<u8>>
+    out_tx: mpsc::Sender<Response>,
+    obs_rx: mpsc::Receiver<OscResponse>,
+    stop: &AtomicBool
 ) -> Result<()> {
-    let Interface::Midi(MidiInterface { ref client_name, ref in_port, .. }) = config.interface else {
+    let ObsInterface { ref url, ref password } = *interface;
+
+    let (mut ws, _) = tungstenite::connect(url)?;
+    if let MaybeTlsStream::Plain(stream) = ws.get_mut() {
+        stream.set_read_timeout(Some(OBS_POLL_INTERVAL))?;
+    }
+    info!("connected to obs at {}", url);
+
+    let mut bridge = ObsBridge::new(config, password.clone());
+    while !stop.load(Ordering::Relaxed) {
+        while let Ok(osc_response) = obs_rx.try_recv() {
+            for request in bridge.requests_for(&osc_response) {
+                debug!("send obs: {}", request);
+                ws.send(Message::Text(request.to_string()))?;
+            }
+        }
+
+        let text = match ws.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.into())
+        };
+
+        trace!("recv obs: {}", text);
+        let (replies, feedback) = bridge.handle_message(&serde_json::from_str(&text)?);
+        for reply in replies {
+            debug!("send obs: {}", reply);
+            ws.send(Message::Text(reply.to_string()))?;
+        }
+
+        let received = Instant::now();
+        for msg in feedback {
+            debug!("obs feedback: {} {:?}", msg.addr, msg.args);
+            if !handle(interpreter, &out_tx, received, |i| i.handle_osc(&msg))? {
+                unhandled::warn(format!("obs {}", msg.addr), format_args!("obs feedback: {} {:?}", msg.addr, msg.args));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_plugin(
+    config: &Config,
+    interpreter: &Arc<RwLock<Interpreter>>,
+    out_tx: mpsc::Sender<Response>,
+    plugin_rx: mpsc::Receiver<OscResponse>,
+    stop: &AtomicBool
+) -> Result<()> {
+    let Some(ref plugin) = config.plugin else {
         return Ok(())
     };
+    let addr = plugin.addr;
 
-    let (tx, rx) = mpsc::channel();
-    let midi_in = MidiInput::new(client_name).unwrap();
-    let midi = match in_port {
-        MidiPort::Index(index) =>
-            Some(midi_in.ports().remove(*index))
-            .map(|p| (midi_in.port_name(&p).unwrap(), midi_in.connect(
-                &p,
-                client_name,
-                move |_time, msg, tx| {
-                    tx.send(msg.to_vec()).unwrap();
-                },
-                tx
-            ).unwrap())),
-        MidiPort::Name(ref name) =>
-            midi_in.ports().into_iter().find(|p| &midi_in.port_name(&p).unwrap() == name)
-            .map(|p| (midi_in.port_name(&p).unwrap(), midi_in.connect(
-                &p,
-                client_name,
-                move |_time, msg, tx| {
-                    tx.send(msg.to_vec()).unwrap();
-                },
-                tx
-            ).unwrap())),
+    let sock = UdpSocket::bind(addr)?;
+    sock.set_read_timeout(Some(PLUGIN_POLL_INTERVAL))?;
+    info!("listening for plugin on {}", addr);
+
+    // replies go to wherever the plugin last spoke from
+    let mut plugin_addr: Option<SocketAddr> = None;
+    let mut recv_errors = RecvErrors::default();
+    let mut rejected = HashSet::new();
+    let mut buf = [0u8; rosc::decoder::MTU];
+    while !stop.load(Ordering::Relaxed) {
+        while let Ok(OscResponse { addr, args }) = plugin_rx.try_recv() {
+            let Some(plugin_addr) = plugin_addr else {
+                continue;
+            };
+
+            let msg = OscPacket::Message(OscMessage { addr, args });
+            debug!("send plugin: {:?}", msg);
+            sock.send_to(&encoder::encode(&msg)?, plugin_addr)?;
+        }
+
+        let (size, addr) = match sock.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) if is_transient(&e) => {
+                recv_errors.note("plugin", e);
+                continue;
+            },
+            Err(e) => return Err(e.into())
+        };
+        if !plugin.allows(addr) {
+            if rejected.insert(addr.ip()) {
+                warn!("ignoring plugin message from {}, which is not in allow", addr.ip());
+            }
+            continue;
+        }
+        plugin_addr = Some(addr);
+
+        let msg = match rosc::decoder::decode_udp(&buf[..size]) {
+            Ok((_, OscPacket::Message(msg))) => msg,
+            Ok((_, OscPacket::Bundle(_))) => {
+                warn!("unhandled plugin bundle from {}", addr);
+                continue;
+            },
+            Err(e) => {
+                recv_errors.note("plugin", format_args!("could not decode packet from {}: {}", addr, e));
+                continue;
+            }
+        };
+
+        debug!("recv plugin: {} {:?}", msg.addr, msg.args);
+        if !handle(interpreter, &out_tx, Instant::now(), |i| i.handle_plugin(&msg))? {
+            unhandled::warn(format!("plugin {}", msg.addr), format_args!("plugin message: {} {:?}", msg.addr, msg.args));
+        }
+    }
+    Ok(())
+}
+
+/// errors receiving from a UDP socket which leave it usable. on Windows, an ICMP port unreachable caused by
+/// an earlier send shows up as a connection reset on the next receive.
+fn is_transient(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::Interrupted)
+}
+
+/// logs errors which a receiver carries on after, at most once every `RECV_ERROR_LOG_INTERVAL`.
+#[derive(Default)]
+struct RecvErrors {
+    last_logged: Option<Instant>,
+    suppressed: u32,
+}
+
+impl RecvErrors {
+    fn note(&mut self, what: &str, e: impl std::fmt::Display) {
+        let now = Instant::now();
+        if self.last_logged.is_some_and(|at| now.duration_since(at) < RECV_ERROR_LOG_INTERVAL) {
+            self.suppressed += 1;
+            return;
+        }
+        match self.suppressed {
+            0 => warn!("error receiving {}: {}", what, e),
+            n => warn!("error receiving {}: {} ({} more not shown)", what, e, n),
+        }
+        self.last_logged = Some(now);
+        self.suppressed = 0;
+    }
+}
+
+/// connects to an input port, or creates a virtual one.
+#[cfg(feature = "midi")]
+fn open_midi_in<T: Send + 'static>(
+    client_name: &str,
+    port: &MidiPort,
+    mut callback: impl FnMut(&[u8], &mut T) + Send + 'static,
+    data: T
+) -> Result<Option<(String, MidiInputConnection<T>)>> {
+    let midi_in = MidiInput::new(client_name)?;
+    // the callback runs on the MIDI driver's thread, which a panic must not unwind into
+    let callback = move |_time: u64, msg: &[u8], data: &mut T| {
+        if panic::catch_unwind(AssertUnwindSafe(|| callback(msg, data))).is_err() {
+            error!("midi input callback failed on {:02x?}", msg);
+        }
+    };
+    let found = match port {
+        MidiPort::Index(index) => midi_in.ports().get(*index).cloned(),
+        MidiPort::Name(ref name) => midi_in.ports().into_iter().find(|p| midi_in.port_name(p).is_ok_and(|n| &n == name)),
         #[cfg(unix)]
-        MidiPort::Virtual(ref name) =>
-            Some((client_name.to_string(), midi_in.create_virtual(
-                client_name,
-                move |_time, msg, tx| {
-                    tx.send(msg.to_vec()).unwrap();
-                },
-                tx
-            ).unwrap())),
-        #[cfg(not(unix))]
         MidiPort::Virtual(ref name) => {
-            unimplemented!("virtual midi ports are currently unsupported on non-unix systems")
-        }
+            let conn = midi_in.create_virtual(name, callback, data).map_err(|e| e.to_string())?;
+            info!("midi in port: {}", name);
+            return Ok(Some((name.to_string(), conn)));
+        },
+        #[cfg(not(unix))]
+        MidiPort::Virtual(_) => return Err("virtual midi ports are currently unsupported on non-unix systems".into())
+    };
+    let Some(found) = found else {
+        return Ok(None);
     };
 
-    if let None = midi {
+    let name = midi_in.port_name(&found)?;
+    let conn = midi_in.connect(&found, client_name, callback, data).map_err(|e| e.to_string())?;
+    info!("midi in port: {}", name);
+    Ok(Some((name, conn)))
+}
+
+/// the sending end of a MIDI input callback. the receiver goes away when its thread stops, e.g. for a new
+/// config or on shutdown, which only means that the messages are no longer wanted.
+#[cfg(feature = "midi")]
+struct CallbackSender<T> {
+    tx: mpsc::Sender<T>,
+    gone: bool,
+}
+
+#[cfg(feature = "midi")]
+impl<T> CallbackSender<T> {
+    fn new(tx: mpsc::Sender<T>) -> CallbackSender<T> {
+        CallbackSender { tx, gone: false }
+    }
+
+    fn send(&mut self, value: T) {
+        if !self.gone && self.tx.send(value).is_err() {
+            debug!("midi input receiver is gone, dropping messages from now on");
+            self.gone = true;
+        }
+    }
+}
+
+#[cfg(feature = "midi")]
+fn run_midi_receiver(
+    config: &Config,
+    interface: &MidiInterface,
+    interpreter: &Arc<RwLock<Interpreter>>,
+    out_tx: mpsc::Sender<Response>,
+    stop: &AtomicBool
+) -> Result<()> {
+    let MidiInterface { ref client_name, ref in_port, ref in_channels, ref input_channel_map, ref thru_port, ref loop_detection, .. } = *interface;
+    let mappings: Vec<Mapping> = config.mappings.iter().flat_map(|m| m.expand_iter()).collect();
+
+    let (tx, rx) = mpsc::channel();
+    let midi = open_midi_in(client_name, in_port, |msg, tx: &mut CallbackSender<(Instant, Vec<u8>)>| {
+        tx.send((Instant::now(), msg.to_vec()));
+    }, CallbackSender::new(tx))?;
+
+    if midi.is_none() {
         warn!("no midi in port???");
     }
 
-    loop {
-        let msg = rx.recv().unwrap();
-        let Some(response) = interpreter.write().unwrap().handle_midi(&msg) else {
-            warn!("unhandled midi message: {:02x?}", msg);
-            continue;
-        };
+    // the thru port bypasses the interpreter, going straight to the output and its filters
+    let _thru = match thru_port {
+        Some(port) => open_midi_in(client_name, port, |msg, out_tx: &mut CallbackSender<Response>| {
+            trace!("midi thru: {:02x?}", msg);
+            out_tx.send(MidiResponse { data: msg.to_vec(), page: None }.into());
+        }, CallbackSender::new(out_tx.clone()))?,
+        None => None
+    };
 
-        let Some(CtrlResponse { data }) = response.ctrl else {
-            continue;
+    while !stop.load(Ordering::Relaxed) {
+        let (received, mut msg) = match rx.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            // there is no in port, so nothing is coming
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                thread::sleep(STOP_POLL_INTERVAL);
+                continue;
+            }
         };
+        diagnostics::record_midi(Direction::In, &msg);
+        if let (Some(&status), Some(channels)) = (msg.first(), in_channels) {
+            if status < 0xf0 && !channels.contains(&(status & 0x0f)) {
+                trace!("ignoring midi message on channel {}: {:02x?}", status & 0x0f, msg);
+                continue;
+            }
+        }
+        if midiloop::looped(loop_detection, &msg) && loop_detection.drop {
+            trace!("ignoring looped midi message: {:02x?}", msg);
+            continue;
+        }
+        if let Some(status) = msg.first_mut().filter(|status| **status < 0xf0) {
+            if let Some(&channel) = input_channel_map.get(&(*status & 0x0f)) {
+                *status = (*status & 0xf0) | (channel & 0x0f);
+            }
+        }
 
-        ctrl_tx.send(data)?
+        if !handle(interpreter, &out_tx, received, |i| i.handle_midi(&msg))? {
+            // the value varies, so messages are told apart by status and number
+            unhandled::warn_hinted(unhandled::describe_midi(&msg), format_args!("midi message: {:02x?}", msg), || {
+                unhandled::nearest_midi(&mappings, &msg)
+            });
+        }
     }
-
     Ok(())
 }
+
+// the tests talk to autocrap over MIDI or OSC
+#[cfg(all(test, any(feature = "midi", feature = "osc")))]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::Mutex,
+        thread,
+        time::{Duration, Instant}
+    };
+    #[cfg(feature = "osc")]
+    use std::net::{SocketAddr, UdpSocket};
+
+    #[cfg(feature = "osc")]
+    use rosc::{decoder, encoder, OscMessage, OscPacket, OscType};
+    use serde_json::json;
+
+    use super::{run_device, Config, DeviceBackend, Result};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// a device which plays back control events pushed to it, and keeps everything written to it.
+    #[derive(Default)]
+    struct ScriptedDevice {
+        events: Mutex<VecDeque<[u8; 2]>>,
+        written: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl ScriptedDevice {
+        fn push(&self, num: u8, val: u8) {
+            self.events.lock().unwrap().push_back([num, val]);
+        }
+
+        /// waits for a write starting with `num`, returning it.
+        fn wait_for_write(&self, num: u8) -> Option<Vec<u8>> {
+            wait_for(|| self.written.lock().unwrap().iter().find(|w| w.first() == Some(&num)).cloned())
+        }
+    }
+
+    impl DeviceBackend for ScriptedDevice {
+        fn open(_config: &Config) -> Result<Option<ScriptedDevice>> {
+            Ok(Some(ScriptedDevice::default()))
+        }
+
+        fn read_events(&self, timeout: Duration) -> Result<Vec<[u8; 2]>> {
+            let events: Vec<[u8; 2]> = self.events.lock().unwrap().drain(..).collect();
+            if events.is_empty() {
+                thread::sleep(timeout);
+            }
+            Ok(events)
+        }
+
+        fn write_feedback(&self, data: &[u8]) -> Result<()> {
+            self.written.lock().unwrap().push(data.to_vec());
+            Ok(())
+        }
+
+        fn reset(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> Option<T> {
+        let start = Instant::now();
+        while start.elapsed() < TIMEOUT {
+            if let Some(found) = f() {
+                return Some(found);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        None
+    }
+
+    /// starts autocrap on a scripted device. its threads run until the tests are over.
+    fn start(config: serde_json::Value) -> &'static ScriptedDevice {
+        let config: Config = serde_json::from_value(config).unwrap();
+        let device: &'static ScriptedDevice = Box::leak(Box::default());
+        thread::spawn(move || {
+            if let Err(e) = run_device(config, device, vec![], false) {
+                panic!("autocrap stopped: {}", e);
+            }
+        });
+        device
+    }
+
+    #[cfg(feature = "osc")]
+    fn free_addr() -> SocketAddr {
+        UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    fn mappings() -> serde_json::Value {
+        json!([
+            {"Single": {"name": "knob", "ctrl_in_num": 64, "ctrl_out_num": 64, "ctrl_kind": {"Relative": {"mode": "Accumulate"}},
+                "midi": {"channel": 0, "kind": "Cc", "num": 64}}},
+            {"Single": {"name": "button", "ctrl_in_num": 112, "ctrl_out_num": 112, "ctrl_kind": {"OnOff": {"mode": "Toggle"}},
+                "midi": {"channel": 0, "kind": "Cc", "num": 112}}}
+        ])
+    }
+
+    #[cfg(feature = "osc")]
+    #[test]
+    fn osc_round_trip() {
+        let host = UdpSocket::bind("127.0.0.1:0").unwrap();
+        host.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let in_addr = free_addr();
+        let device = start(json!({
+            "vendor_id": 4661,
+            "product_id": 10,
+            "interface": {"Osc": {"out_addr": host.local_addr().unwrap(), "in_addr": in_addr}},
+            "mappings": mappings()
+        }));
+
+        // from the device, through the reader and the output thread, to the host
+        device.push(112, 0x7f);
+        let mut buf = [0; rosc::decoder::MTU];
+        let size = host.recv(&mut buf).unwrap();
+        let (_, packet) = decoder::decode_udp(&buf[..size]).unwrap();
+        let OscPacket::Message(msg) = packet else {
+            panic!("expected a message, got {:?}", packet);
+        };
+        assert_eq!(msg.addr, "/button");
+        assert_eq!(msg.args, vec![OscType::Float(1.0)]);
+        assert_eq!(device.wait_for_write(112), Some(vec![112, 0x7f]));
+
+        // from the host, through the receiver and the writer, to the device's LEDs. sent until the
+        // receiver is listening
+        let msg = encoder::encode(&OscPacket::Message(OscMessage { addr: "/knob".into(), args: vec![OscType::Float(1.0)] })).unwrap();
+        let written = wait_for(|| {
+            host.send_to(&msg, in_addr).unwrap();
+            device.wait_for_write(64)
+        });
+        assert!(written.is_some_and(|w| w[1] != 0x00), "knob LED was not lit");
+    }
+
+    #[cfg(feature = "midi")]
+    #[test]
+    fn midi_round_trip() {
+        use midir::{MidiInput, MidiOutput};
+        use std::sync::mpsc;
+
+        // MIDI loopback needs a MIDI system with virtual ports, which e.g. containers often don't have
+        let (Ok(midi_in), Ok(midi_out)) = (MidiInput::new("autocrap-test"), MidiOutput::new("autocrap-test")) else {
+            eprintln!("no MIDI system, skipping");
+            return;
+        };
+        let device = start(json!({
+            "vendor_id": 4661,
+            "product_id": 10,
+            "interface": {"Midi": {
+                "client_name": "autocrap-test-device",
+                "out_port": {"Virtual": "autocrap-test-out"},
+                "in_port": {"Virtual": "autocrap-test-in"}
+            }},
+            "mappings": mappings()
+        }));
+
+        let in_port = wait_for(|| midi_in.ports().into_iter().find(|p| midi_in.port_name(p).is_ok_and(|n| n.contains("autocrap-test-out"))))
+            .expect("autocrap's out port did not show up");
+        let (tx, rx) = mpsc::channel();
+        let _in_conn = midi_in.connect(&in_port, "autocrap-test", move |_, msg, _| { let _ = tx.send(msg.to_vec()); }, ())
+            .unwrap();
+
+        device.push(112, 0x7f);
+        assert_eq!(rx.recv_timeout(TIMEOUT), Ok(vec![0xb0, 112, 0x7f]));
+
+        let out_port = wait_for(|| midi_out.ports().into_iter().find(|p| midi_out.port_name(p).is_ok_and(|n| n.contains("autocrap-test-in"))))
+            .expect("autocrap's in port did not show up");
+        let mut out_conn = midi_out.connect(&out_port, "autocrap-test").unwrap();
+        out_conn.send(&[0xb0, 64, 0x7f]).unwrap();
+        assert!(device.wait_for_write(64).is_some_and(|w| w[1] != 0x00), "knob LED was not lit");
+    }
+}